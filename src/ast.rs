@@ -3,6 +3,7 @@ use std::fmt::Formatter;
 
 use crate::ast_builder::*;
 use crate::token::*;
+use crate::type_checking::Type;
 
 pub trait Range {
     // lineno, start , end
@@ -16,6 +17,12 @@ pub struct Stmt {
     pub range: (usize, usize),
 }
 
+impl Range for (usize, usize) {
+    fn range(&self) -> (usize, usize) {
+        *self
+    }
+}
+
 impl Range for Stmt {
     fn range(&self) -> (usize, usize) {
         self.range
@@ -37,6 +44,32 @@ impl Stmt {
             ranges: vec![],
         }
     }
+    pub fn ann_assignment() -> AnnAssign {
+        AnnAssign {
+            name: None,
+            ty: None,
+            binding: None,
+            ranges: vec![],
+        }
+    }
+
+    pub fn aug_assignment() -> AugAssign {
+        AugAssign {
+            name: None,
+            op: None,
+            binding: None,
+            ranges: vec![],
+        }
+    }
+
+    pub fn multi_assignment() -> MultiAssign {
+        MultiAssign {
+            names: vec![],
+            bindings: vec![],
+            ranges: vec![],
+        }
+    }
+
     pub fn if_stmt() -> IfStmt {
         IfStmt {
             condition: None,
@@ -45,6 +78,42 @@ impl Stmt {
             ranges: vec![],
         }
     }
+
+    pub fn for_stmt() -> ForStmt {
+        ForStmt {
+            var: None,
+            start: None,
+            stop: None,
+            step: None,
+            body: None,
+            ranges: vec![],
+        }
+    }
+
+    pub fn def() -> Def {
+        Def {
+            name: None,
+            params: vec![],
+            ret: None,
+            body: None,
+            ranges: vec![],
+        }
+    }
+
+    pub fn return_stmt() -> ReturnStmt {
+        ReturnStmt {
+            value: None,
+            ranges: vec![],
+        }
+    }
+
+    pub fn while_stmt() -> WhileStmt {
+        WhileStmt {
+            condition: None,
+            body: None,
+            ranges: vec![],
+        }
+    }
 }
 
 impl Display for Stmt {
@@ -56,6 +125,17 @@ impl Display for Stmt {
             StmtData::Assign { name, binding } => {
                 write!(f, "{} = {}", name.lexeme(), binding)
             }
+            StmtData::AnnAssign { name, ty, binding } => {
+                write!(f, "{}: {} = {}", name.lexeme(), ty, binding)
+            }
+            StmtData::AugAssign { name, op, binding } => {
+                write!(f, "{} {}= {}", name.lexeme(), op.lexeme(), binding)
+            }
+            StmtData::MultiAssign { names, bindings } => {
+                let names: Vec<&str> = names.iter().map(Token::lexeme).collect();
+                let bindings: Vec<String> = bindings.iter().map(ToString::to_string).collect();
+                write!(f, "{} = {}", names.join(", "), bindings.join(", "))
+            }
             StmtData::If {
                 condition,
                 then,
@@ -63,6 +143,40 @@ impl Display for Stmt {
             } => {
                 write!(f, "if ({}) {} else {}", condition, then, else_)
             }
+            StmtData::While { condition, body } => {
+                write!(f, "while ({}) {}", condition, body)
+            }
+            StmtData::For {
+                var,
+                start,
+                stop,
+                step,
+                body,
+            } => {
+                write!(f, "for {} in range({}, {}", var.lexeme(), start, stop)?;
+                if let Some(s) = step {
+                    write!(f, ", {}", s)?;
+                }
+                write!(f, ") {}", body)
+            }
+            StmtData::Def {
+                name,
+                params,
+                ret,
+                body,
+            } => {
+                let params: Vec<String> = params
+                    .iter()
+                    .map(|(p, ty)| format!("{}: {}", p.lexeme(), ty))
+                    .collect();
+                write!(f, "def {}({})", name.lexeme(), params.join(", "))?;
+                if let Some(ret) = ret {
+                    write!(f, " -> {}", ret)?;
+                }
+                write!(f, " {}", body)
+            }
+            StmtData::Return(Some(e)) => write!(f, "return {}", e),
+            StmtData::Return(None) => write!(f, "return"),
         }
     }
 }
@@ -75,6 +189,23 @@ pub enum StmtData {
         name: Token,
         binding: Expr,
     },
+    /// `x: T = e`, 声明变量的类型. 在Shrink中被改写为`Assign`.
+    AnnAssign {
+        name: Token,
+        ty: TypeExpr,
+        binding: Expr,
+    },
+    /// `x += e`, `op`是对应的二元运算符(`+`). 在Shrink中被改写为`x = x + e`.
+    AugAssign {
+        name: Token,
+        op: Token,
+        binding: Expr,
+    },
+    /// `a, b = e1, e2`, 先求出右边所有的值再依次赋值. 在Shrink中被改写为多个`Assign`.
+    MultiAssign {
+        names: Vec<Token>,
+        bindings: Vec<Expr>,
+    },
     // If {
     // 	condition: Expr,
     // 	then: Vec<Stmt>,
@@ -85,6 +216,87 @@ pub enum StmtData {
         then: Expr,
         else_: Expr,
     },
+    /// `body`是一个块表达式, 它的值被丢弃.
+    While {
+        condition: Expr,
+        body: Expr,
+    },
+    /// `for var in range(start, stop, step)`, 省略`step`时为1.
+    /// 在Shrink中被改写为`while`循环.
+    For {
+        var: Token,
+        start: Expr,
+        stop: Expr,
+        step: Option<Expr>,
+        body: Expr,
+    },
+    /// 顶层的函数定义. 参数和返回值的类型标注由类型检查解析;
+    /// 省略返回值类型时函数不返回值.
+    Def {
+        name: Token,
+        params: Vec<(Token, TypeExpr)>,
+        ret: Option<TypeExpr>,
+        body: Expr,
+    },
+    Return(Option<Expr>),
+}
+
+/// 类型标注.
+#[derive(Debug, Clone)]
+pub enum TypeExpr {
+    /// `int`, `bool`
+    Name(Token),
+    /// `tuple[int, bool]`
+    Generic {
+        name: Token,
+        args: Vec<TypeExpr>,
+        range: (usize, usize),
+    },
+    /// `Callable[[int, bool], int]`
+    Callable {
+        params: Vec<TypeExpr>,
+        ret: Box<TypeExpr>,
+        range: (usize, usize),
+    },
+    /// 由编译器生成的函数的类型, 在类型检查之后已经确定.
+    Checked(Type),
+}
+
+impl Display for TypeExpr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeExpr::Name(t) => write!(f, "{}", t.lexeme()),
+            TypeExpr::Generic { name, args, .. } => {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                write!(f, "{}[{}]", name.lexeme(), args.join(", "))
+            }
+            TypeExpr::Callable { params, ret, .. } => {
+                let params: Vec<String> = params.iter().map(ToString::to_string).collect();
+                write!(f, "Callable[[{}], {}]", params.join(", "), ret)
+            }
+            TypeExpr::Checked(ty) => write!(f, "{}", ty),
+        }
+    }
+}
+
+impl TypeExpr {
+    /// 类型检查之后所有的标注都已被改写为`Checked`.
+    pub fn checked(&self) -> &Type {
+        match self {
+            TypeExpr::Checked(ty) => ty,
+            _ => unreachable!("Type annotations are resolved by the type checker."),
+        }
+    }
+}
+
+impl Range for TypeExpr {
+    fn range(&self) -> (usize, usize) {
+        match self {
+            TypeExpr::Name(t) => t.range(),
+            TypeExpr::Generic { range, .. } | TypeExpr::Callable { range, .. } => *range,
+            TypeExpr::Checked(_) => (0, 0),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -100,6 +312,7 @@ impl Display for Expr {
             ExprData::Name(n) => write!(f, "{}", n.lexeme()),
             ExprData::Int(i) => write!(f, "{}", i),
             ExprData::Float(n) => write!(f, "{}", n),
+            ExprData::Str(s) => write!(f, "{}", quote(s)),
             ExprData::Call { name, args } => {
                 write!(f, "{}(", name)?;
                 if let Some(a) = args.first() {
@@ -113,7 +326,7 @@ impl Display for Expr {
             ExprData::Prim { op, operands } if operands.len() == 2 => {
                 write!(f, "{} {} {}", operands[0], op.lexeme(), operands[1])
             }
-            ExprData::Prim { op, operands } if operands.len() == 1 => {
+            ExprData::Prim { op, operands } => {
                 write!(f, "{} {}", op.lexeme(), operands[0])
             }
             ExprData::Bool(b) => write!(f, "{}", b),
@@ -123,7 +336,7 @@ impl Display for Expr {
                 else_,
             } => write!(f, "{} if {} else {}", then, condition, else_),
             ExprData::Block { body, result } => {
-                writeln!(f, "{{");
+                writeln!(f, "{{")?;
                 for s in body {
                     writeln!(f, "{}", s)?;
                 }
@@ -132,7 +345,49 @@ impl Display for Expr {
                 }
                 write!(f, "}}")
             }
-            _ => unimplemented!(),
+            ExprData::Lambda { params, body, .. } => {
+                let params: Vec<&str> = params.iter().map(Token::lexeme).collect();
+                write!(f, "lambda {}: {}", params.join(", "), body)
+            }
+            ExprData::Tuple { elems } if elems.len() == 1 => write!(f, "({},)", elems[0]),
+            ExprData::Tuple { elems } => {
+                let elems: Vec<String> = elems.iter().map(ToString::to_string).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            ExprData::Subscript { value, index } => write!(f, "{}[{}]", value, index),
+            ExprData::List { elems, repeat, .. } => {
+                let elems: Vec<String> = elems.iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", elems.join(", "))?;
+                match repeat {
+                    Some(n) => write!(f, " * {}", n),
+                    None => Ok(()),
+                }
+            }
+            ExprData::ListRef { list, index } => write!(f, "{}[{}]", list, index),
+            ExprData::ListSet { list, index, value } => {
+                write!(f, "{}[{}] = {}", list, index, value)
+            }
+            ExprData::Closure { func, captures } => {
+                write!(f, "%closure({}", func.lexeme())?;
+                for c in captures {
+                    write!(f, ", {}", c)?;
+                }
+                write!(f, ")")
+            }
+            ExprData::Capture { closure, index } => write!(f, "%capture({}, {})", closure, index),
+            ExprData::Apply { closure, args } => {
+                write!(f, "%apply({}", closure)?;
+                for a in args {
+                    write!(f, ", {}", a)?;
+                }
+                write!(f, ")")
+            }
+            ExprData::GlobalValue(name) => write!(f, "%global({})", name),
+            ExprData::Collect(bytes) => write!(f, "%collect({})", bytes),
+            ExprData::AllocateList { len, ty } => write!(f, "%allocate({}, {})", len, ty),
+            ExprData::ListInit { list, index, value } => {
+                write!(f, "%list_init({}, {}, {})", list, index, value)
+            }
         }
     }
 }
@@ -149,9 +404,20 @@ impl Expr {
     }
 
     pub fn is_atom(&self) -> bool {
+        matches!(
+            &self.data,
+            ExprData::Int(_) | ExprData::Float(_) | ExprData::Name(_) | ExprData::Bool(_)
+        )
+    }
+
+    /// 整数字面量及其相反数的值.
+    pub fn const_int(&self) -> Option<i64> {
         match &self.data {
-            ExprData::Int(_) | ExprData::Float(_) | ExprData::Name(_) | ExprData::Bool(_) => true,
-            _ => false,
+            ExprData::Int(i) => Some(*i),
+            ExprData::Prim { op, operands } if op.kind() == Kind::Minus && operands.len() == 1 => {
+                operands[0].const_int().map(i64::wrapping_neg)
+            }
+            _ => None,
         }
     }
 
@@ -179,6 +445,13 @@ impl Expr {
                     range,
                 }
             }
+            Kind::Str => {
+                let s = unescape(t.lexeme()).unwrap();
+                Expr {
+                    data: ExprData::Str(s),
+                    range,
+                }
+            }
             Kind::Name => Expr {
                 data: ExprData::Name(t),
                 range,
@@ -221,6 +494,45 @@ impl Expr {
         }
     }
 
+    pub fn lambda() -> Lambda {
+        Lambda {
+            params: vec![],
+            body: None,
+            ranges: vec![],
+        }
+    }
+
+    pub fn tuple() -> Tuple {
+        Tuple {
+            elems: vec![],
+            ranges: vec![],
+        }
+    }
+
+    pub fn subscript() -> Subscript {
+        Subscript {
+            value: None,
+            index: None,
+            ranges: vec![],
+        }
+    }
+
+    pub fn list() -> List {
+        List {
+            elems: vec![],
+            ranges: vec![],
+        }
+    }
+
+    pub fn list_set() -> ListSet {
+        ListSet {
+            list: None,
+            index: None,
+            value: None,
+            ranges: vec![],
+        }
+    }
+
     pub fn condition() -> Condition {
         Condition {
             condition: None,
@@ -236,6 +548,8 @@ pub enum ExprData {
     Int(i64),
     Bool(bool),
     Float(f64),
+    /// 字符串字面量, 存放在只读数据段中, 所以不是原子.
+    Str(String),
     Name(Token),
     /// binary & unary
     Prim {
@@ -255,4 +569,71 @@ pub enum ExprData {
         body: Vec<Stmt>,
         result: Option<Box<Expr>>,
     },
+    /// `lambda x, y: body`. 参数的类型由类型检查根据上下文确定, 之后`ty`是整个函数的类型,
+    /// `captures`是用到的外层变量及其类型.
+    Lambda {
+        params: Vec<Token>,
+        body: Box<Expr>,
+        ty: Option<Type>,
+        captures: Vec<(String, Type)>,
+    },
+    /// `(a, b)`, 在堆上分配. 至少有一个元素.
+    Tuple {
+        elems: Vec<Expr>,
+    },
+    /// `t[i]`, 下标是整数字面量.
+    Subscript {
+        value: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `[a, b]`, 元素的类型相同. `[a, b] * n`由类型检查改写为带`repeat`的列表, 即重复`n`次.
+    /// 类型检查之后`ty`是元素的类型.
+    List {
+        elems: Vec<Expr>,
+        repeat: Option<Box<Expr>>,
+        ty: Option<Type>,
+    },
+    /// 列表的元素`a[i]`, 由类型检查从`Subscript`改写得到. 运行时检查下标是否越界.
+    ListRef {
+        list: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `a[i] = v`, 只作为语句出现. 运行时检查下标是否越界.
+    ListSet {
+        list: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    /// 以下由闭包转换生成.
+    /// 在堆上分配的闭包: 顶层函数`func`的地址和捕获的值.
+    Closure {
+        func: Token,
+        captures: Vec<Expr>,
+    },
+    /// 闭包捕获的第`index`个值.
+    Capture {
+        closure: Box<Expr>,
+        index: usize,
+    },
+    /// 调用闭包: 闭包本身作为第一个参数传给它的函数.
+    Apply {
+        closure: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    /// 以下由ExposeAllocation生成.
+    /// 运行时(runtime.c)中全局变量的值, 如`free_ptr`.
+    GlobalValue(String),
+    /// 调用运行时的`collect`, 保证之后至少有这么多字节的空闲空间.
+    Collect(Box<Expr>),
+    /// 分配长度为`len`的列表, `ty`是列表的类型. 元素未初始化, 随后由`ListInit`写入.
+    AllocateList {
+        len: Box<Expr>,
+        ty: Type,
+    },
+    /// 初始化刚分配的列表的元素, 不检查下标.
+    ListInit {
+        list: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
 }
@@ -1,6 +1,6 @@
 use crate::{
-    ast::{Expr, ExprData, Range, Stmt, StmtData},
-    token::Token,
+    ast::{Expr, ExprData, Range, Stmt, StmtData, TypeExpr},
+    token::{Kind, Token},
 };
 
 pub struct BinaryExpr {
@@ -143,12 +143,8 @@ impl FunctionCall {
         self.args.push(arg);
         self
     }
-    pub fn args(mut self, args: Vec<Expr>) -> Self {
-        args.iter().for_each(|a| {
-            self.ranges.push(a.range());
-        });
-        self.args.extend(args.into_iter());
-        self
+    pub fn args(self, args: Vec<Expr>) -> Self {
+        args.into_iter().fold(self, Self::arg)
     }
 
     pub fn build(mut self) -> Expr {
@@ -166,6 +162,194 @@ impl FunctionCall {
     }
 }
 
+pub struct Lambda {
+    pub params: Vec<Token>,
+    pub body: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl Lambda {
+    /// `lambda`关键字本身.
+    pub fn keyword(mut self, t: Token) -> Self {
+        self.ranges.push(t.range());
+        self
+    }
+
+    pub fn param(mut self, p: Token) -> Self {
+        self.ranges.push(p.range());
+        self.params.push(p);
+        self
+    }
+
+    pub fn body(mut self, b: Expr) -> Self {
+        self.ranges.push(b.range());
+        self.body = Some(b);
+        self
+    }
+
+    pub fn build(mut self) -> Expr {
+        let data = ExprData::Lambda {
+            params: self.params,
+            body: Box::new(self.body.take().expect("`body` is not initialized.")),
+            ty: None,
+            captures: vec![],
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Expr::new(data, range)
+    }
+}
+
+pub struct Tuple {
+    pub elems: Vec<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl Tuple {
+    /// 括号本身.
+    pub fn paren(mut self, t: Token) -> Self {
+        self.ranges.push(t.range());
+        self
+    }
+
+    pub fn elem(mut self, e: Expr) -> Self {
+        self.ranges.push(e.range());
+        self.elems.push(e);
+        self
+    }
+
+    pub fn build(mut self) -> Expr {
+        let data = ExprData::Tuple { elems: self.elems };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Expr::new(data, range)
+    }
+}
+
+pub struct Subscript {
+    pub value: Option<Expr>,
+    pub index: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl Subscript {
+    pub fn value(mut self, v: Expr) -> Self {
+        self.ranges.push(v.range());
+        self.value = Some(v);
+        self
+    }
+
+    pub fn index(mut self, i: Expr) -> Self {
+        self.ranges.push(i.range());
+        self.index = Some(i);
+        self
+    }
+
+    /// 右方括号.
+    pub fn bracket(mut self, t: Token) -> Self {
+        self.ranges.push(t.range());
+        self
+    }
+
+    pub fn build(mut self) -> Expr {
+        let data = ExprData::Subscript {
+            value: Box::new(self.value.take().expect("`value` is not initialized.")),
+            index: Box::new(self.index.take().expect("`index` is not initialized.")),
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Expr::new(data, range)
+    }
+}
+
+pub struct List {
+    pub elems: Vec<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl List {
+    /// 方括号本身.
+    pub fn bracket(mut self, t: Token) -> Self {
+        self.ranges.push(t.range());
+        self
+    }
+
+    pub fn elem(mut self, e: Expr) -> Self {
+        self.ranges.push(e.range());
+        self.elems.push(e);
+        self
+    }
+
+    pub fn build(mut self) -> Expr {
+        let data = ExprData::List {
+            elems: self.elems,
+            repeat: None,
+            ty: None,
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Expr::new(data, range)
+    }
+}
+
+pub struct ListSet {
+    pub list: Option<Expr>,
+    pub index: Option<Expr>,
+    pub value: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl ListSet {
+    pub fn list(mut self, l: Expr) -> Self {
+        self.ranges.push(l.range());
+        self.list = Some(l);
+        self
+    }
+
+    pub fn index(mut self, i: Expr) -> Self {
+        self.ranges.push(i.range());
+        self.index = Some(i);
+        self
+    }
+
+    pub fn value(mut self, v: Expr) -> Self {
+        self.ranges.push(v.range());
+        self.value = Some(v);
+        self
+    }
+
+    pub fn build(mut self) -> Expr {
+        let data = ExprData::ListSet {
+            list: Box::new(self.list.take().expect("`list` is not initialized.")),
+            index: Box::new(self.index.take().expect("`index` is not initialized.")),
+            value: Box::new(self.value.take().expect("`value` is not initialized.")),
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Expr::new(data, range)
+    }
+}
+
 pub struct Assign {
     pub name: Option<Token>,
     pub binding: Option<Expr>,
@@ -200,6 +384,135 @@ impl Assign {
     }
 }
 
+pub struct AnnAssign {
+    pub name: Option<Token>,
+    pub ty: Option<TypeExpr>,
+    pub binding: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl AnnAssign {
+    pub fn name(mut self, v: Token) -> Self {
+        self.ranges.push(v.range());
+        self.name = Some(v);
+        self
+    }
+
+    pub fn ty(mut self, ty: TypeExpr) -> Self {
+        self.ranges.push(ty.range());
+        self.ty = Some(ty);
+        self
+    }
+
+    pub fn binding(mut self, b: Expr) -> Self {
+        self.ranges.push(b.range());
+        self.binding = Some(b);
+        self
+    }
+
+    pub fn build(mut self) -> Stmt {
+        let stmt = StmtData::AnnAssign {
+            name: self.name.take().expect("`var` is not initialized."),
+            ty: self.ty.take().expect("`ty` is not initialized."),
+            binding: self.binding.take().expect("`binding` is not initialized."),
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Stmt { stmt, range }
+    }
+}
+
+pub struct AugAssign {
+    pub name: Option<Token>,
+    pub op: Option<Token>,
+    pub binding: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl AugAssign {
+    pub fn name(mut self, v: Token) -> Self {
+        self.ranges.push(v.range());
+        self.name = Some(v);
+        self
+    }
+
+    /// `op`是`+=`这样的复合赋值符号, 保存的是去掉`=`之后的运算符.
+    pub fn op(mut self, op: Token) -> Self {
+        self.ranges.push(op.range());
+        let kind = match op.kind() {
+            Kind::PlusEqual => Kind::Plus,
+            Kind::MinusEqual => Kind::Minus,
+            Kind::StarEqual => Kind::Star,
+            Kind::SlashEqual => Kind::Slash,
+            Kind::PercentEqual => Kind::Percent,
+            k => panic!("`{:?}` is not an augmented assignment.", k),
+        };
+        let lexeme = op.lexeme().trim_end_matches('=').to_string();
+        self.op = Some(Token::new(kind, lexeme, op.line(), op.pos()));
+        self
+    }
+
+    pub fn binding(mut self, b: Expr) -> Self {
+        self.ranges.push(b.range());
+        self.binding = Some(b);
+        self
+    }
+
+    pub fn build(mut self) -> Stmt {
+        let stmt = StmtData::AugAssign {
+            name: self.name.take().expect("`var` is not initialized."),
+            op: self.op.take().expect("`op` is not initialized."),
+            binding: self.binding.take().expect("`binding` is not initialized."),
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Stmt { stmt, range }
+    }
+}
+
+pub struct MultiAssign {
+    pub names: Vec<Token>,
+    pub bindings: Vec<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl MultiAssign {
+    pub fn name(mut self, v: Token) -> Self {
+        self.ranges.push(v.range());
+        self.names.push(v);
+        self
+    }
+
+    pub fn binding(mut self, b: Expr) -> Self {
+        self.ranges.push(b.range());
+        self.bindings.push(b);
+        self
+    }
+
+    pub fn build(mut self) -> Stmt {
+        assert!(!self.names.is_empty(), "`names` is not initialized.");
+        let stmt = StmtData::MultiAssign {
+            names: self.names,
+            bindings: self.bindings,
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Stmt { stmt, range }
+    }
+}
+
 pub struct ExprStmt {
     pub expr: Option<Expr>,
     pub ranges: Vec<(usize, usize)>,
@@ -285,3 +598,183 @@ impl IfStmt {
         Stmt { stmt, range }
     }
 }
+
+pub struct WhileStmt {
+    pub condition: Option<Expr>,
+    pub body: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl WhileStmt {
+    pub fn condition(mut self, e: Expr) -> Self {
+        self.ranges.push(e.range());
+        self.condition = Some(e);
+        self
+    }
+
+    pub fn body(mut self, b: Expr) -> Self {
+        self.ranges.push(b.range());
+        self.body = Some(b);
+        self
+    }
+
+    pub fn build(mut self) -> Stmt {
+        let stmt = StmtData::While {
+            condition: self
+                .condition
+                .take()
+                .expect("`condition` is not initialized."),
+            body: self.body.take().expect("`body` is not initialized."),
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Stmt { stmt, range }
+    }
+}
+
+pub struct ForStmt {
+    pub var: Option<Token>,
+    pub start: Option<Expr>,
+    pub stop: Option<Expr>,
+    pub step: Option<Expr>,
+    pub body: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl ForStmt {
+    pub fn var(mut self, v: Token) -> Self {
+        self.ranges.push(v.range());
+        self.var = Some(v);
+        self
+    }
+
+    pub fn start(mut self, e: Expr) -> Self {
+        self.ranges.push(e.range());
+        self.start = Some(e);
+        self
+    }
+
+    pub fn stop(mut self, e: Expr) -> Self {
+        self.ranges.push(e.range());
+        self.stop = Some(e);
+        self
+    }
+
+    pub fn step(mut self, e: Expr) -> Self {
+        self.ranges.push(e.range());
+        self.step = Some(e);
+        self
+    }
+
+    pub fn body(mut self, b: Expr) -> Self {
+        self.ranges.push(b.range());
+        self.body = Some(b);
+        self
+    }
+
+    pub fn build(mut self) -> Stmt {
+        let stmt = StmtData::For {
+            var: self.var.take().expect("`var` is not initialized."),
+            start: self.start.take().expect("`start` is not initialized."),
+            stop: self.stop.take().expect("`stop` is not initialized."),
+            step: self.step.take(),
+            body: self.body.take().expect("`body` is not initialized."),
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Stmt { stmt, range }
+    }
+}
+
+pub struct Def {
+    pub name: Option<Token>,
+    pub params: Vec<(Token, TypeExpr)>,
+    pub ret: Option<TypeExpr>,
+    pub body: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl Def {
+    pub fn name(mut self, v: Token) -> Self {
+        self.ranges.push(v.range());
+        self.name = Some(v);
+        self
+    }
+
+    pub fn param(mut self, name: Token, ty: TypeExpr) -> Self {
+        self.ranges.push(name.range());
+        self.ranges.push(ty.range());
+        self.params.push((name, ty));
+        self
+    }
+
+    pub fn ret(mut self, ty: TypeExpr) -> Self {
+        self.ranges.push(ty.range());
+        self.ret = Some(ty);
+        self
+    }
+
+    pub fn body(mut self, b: Expr) -> Self {
+        self.ranges.push(b.range());
+        self.body = Some(b);
+        self
+    }
+
+    pub fn build(mut self) -> Stmt {
+        let stmt = StmtData::Def {
+            name: self.name.take().expect("`name` is not initialized."),
+            params: self.params,
+            ret: self.ret.take(),
+            body: self.body.take().expect("`body` is not initialized."),
+        };
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self.ranges.first().unwrap().0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Stmt { stmt, range }
+    }
+}
+
+pub struct ReturnStmt {
+    pub value: Option<Expr>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl ReturnStmt {
+    /// `return`关键字本身.
+    pub fn keyword(mut self, t: Token) -> Self {
+        self.ranges.push(t.range());
+        self
+    }
+
+    pub fn value(mut self, e: Expr) -> Self {
+        self.ranges.push(e.range());
+        self.value = Some(e);
+        self
+    }
+
+    pub fn build(mut self) -> Stmt {
+        let stmt = StmtData::Return(self.value.take());
+
+        self.ranges.sort_by_key(|r| r.0);
+        let start = self
+            .ranges
+            .first()
+            .expect("`keyword` is not initialized.")
+            .0;
+        self.ranges.sort_by_key(|r| r.1);
+        let end = self.ranges.last().unwrap().1;
+        let range = (start, end);
+        Stmt { stmt, range }
+    }
+}
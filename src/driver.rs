@@ -0,0 +1,676 @@
+use std::{
+    error::Error,
+    fmt::{Display, Write as _},
+    fs::{create_dir_all, read_to_string, remove_dir_all, write, File},
+    io::{BufRead, BufWriter, Read, Write},
+    os::unix::process::ExitStatusExt,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use petgraph::dot::{Config, Dot};
+
+use crate::{
+    interp::{AstInterpreter, ClikeInterpreter, X86Emulator},
+    parser::Parser,
+    pass::{
+        allocate::Allocation,
+        assign_homes::AssignHomes,
+        build_interference::BuildInterference,
+        clike::CProgrom,
+        clike_parser::ClikeParser,
+        closure_conversion::ClosureConversion,
+        explicate_control::ExplicateControl,
+        expose_allocation::ExposeAllocation,
+        frame::Frame,
+        gen::CodeGen,
+        limit_functions::LimitFunctions,
+        liveness::{LiveBlocks, UncoverLive},
+        patch::PatchInstructions,
+        rco::RemoveComplexOperands,
+        select_instructions::SelectInstructions,
+        shrink::Shrink,
+        x86::Program,
+    },
+    reporter::ErrorReporter,
+    scanner::Scanner,
+    type_checking::TypeChecker,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+pub const USAGE: &str = "\
+Usage: eoc-rs [options] <input>
+
+An <input> ending in `.clike` is read as the C-like IR (see `--emit=clike`).
+
+Options:
+  -o <file>         Write the output to <file> (`-` for stdout).
+  --emit=<stage>    Stop after <stage> and dump its result. One of:
+                    tokens, ast, shrink, closure, limit, expose, rco, clike,
+                    select, live, interference, alloc, homes, patch,
+                    asm (default).
+  --link            Assemble and link with the runtime into an executable.
+  --run             Build into a temporary directory and run the program,
+                    exiting with its exit code.
+  --interp          Run the program with the reference interpreter instead
+                    of compiling it.
+  --check-passes    Interpret the program and the output of every pass on
+                    the same stdin, and report the first pass that behaves
+                    differently.
+  --checked-arith   Exit with a runtime error on integer overflow and
+                    division by zero instead of wrapping around.
+  --layout          Delimit blocks by `:` and indentation, as in Python,
+                    instead of `{` and `}`.
+  --runtime <file>  The C runtime to link against (default: $EOC_RUNTIME,
+                    or tests/runtime.c of this crate).
+  -h, --help        Print this message.";
+
+/// 编译流程中可以导出结果的阶段, 按执行顺序排列.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Shrink,
+    Closure,
+    Limit,
+    Expose,
+    Rco,
+    Clike,
+    Select,
+    Live,
+    Interference,
+    Alloc,
+    Homes,
+    Patch,
+    Asm,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        use Emit::*;
+        match s {
+            "tokens" => Ok(Tokens),
+            "ast" => Ok(Ast),
+            "shrink" => Ok(Shrink),
+            "closure" => Ok(Closure),
+            "limit" => Ok(Limit),
+            "expose" => Ok(Expose),
+            "rco" => Ok(Rco),
+            "clike" => Ok(Clike),
+            "select" => Ok(Select),
+            "live" => Ok(Live),
+            "interference" => Ok(Interference),
+            "alloc" => Ok(Alloc),
+            "homes" => Ok(Homes),
+            "patch" => Ok(Patch),
+            "asm" => Ok(Asm),
+            o => Err(format!("Unknown stage `{}`", o)),
+        }
+    }
+}
+
+/// 得到汇编之后要做的事情.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// 只输出汇编.
+    Assemble,
+    /// 与运行时链接, 生成可执行文件.
+    Link,
+    /// 生成可执行文件并立即运行.
+    Run,
+    /// 不编译, 直接用解释器运行源程序.
+    Interp,
+    /// 解释执行每个pass的输出并与源程序比较.
+    CheckPasses,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub input: PathBuf,
+    /// `None` 表示输出到stdout.
+    pub output: Option<PathBuf>,
+    pub emit: Emit,
+    pub mode: Mode,
+    pub runtime: PathBuf,
+    /// 为算术运算生成溢出和除零检查.
+    pub checked_arith: bool,
+    /// 用缩进而不是花括号划分块.
+    pub layout: bool,
+}
+
+impl Options {
+    /// 解析命令行参数(不包括程序名). 返回`Ok(None)`表示只需打印帮助信息.
+    pub fn parse<I: Iterator<Item = String>>(
+        mut args: I,
+    ) -> std::result::Result<Option<Self>, String> {
+        let mut input = None;
+        let mut output = None;
+        let mut emit = None;
+        let mut mode = Mode::Assemble;
+        let mut runtime = None;
+        let mut checked_arith = false;
+        let mut layout = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--link" => mode = Mode::Link,
+                "--run" => mode = Mode::Run,
+                "--interp" => mode = Mode::Interp,
+                "--check-passes" => mode = Mode::CheckPasses,
+                "--checked-arith" => checked_arith = true,
+                "--layout" => layout = true,
+                "--runtime" => {
+                    let file = args
+                        .next()
+                        .ok_or("Expected a file name after `--runtime`")?;
+                    runtime = Some(PathBuf::from(file));
+                }
+                "-o" => {
+                    let file = args.next().ok_or("Expected a file name after `-o`")?;
+                    output = Some(file);
+                }
+                "--emit" => {
+                    let stage = args.next().ok_or("Expected a stage after `--emit`")?;
+                    emit = Some(stage.parse()?);
+                }
+                a if a.starts_with("--emit=") => {
+                    emit = Some(a["--emit=".len()..].parse()?);
+                }
+                a if a.starts_with('-') && a != "-" => {
+                    return Err(format!("Unknown option `{}`", a));
+                }
+                _ => {
+                    if input.replace(PathBuf::from(arg)).is_some() {
+                        return Err("Expected exactly one input file".into());
+                    }
+                }
+            }
+        }
+
+        let input = input.ok_or("No input file")?;
+        let emit = emit.unwrap_or(Emit::Asm);
+        if emit != Emit::Asm && mode != Mode::Assemble {
+            return Err("`--emit` can only be used when compiling to assembly".into());
+        }
+        // 默认只有汇编和可执行文件写入文件(与`cc`一样放在源文件旁边), 其余阶段打印到stdout.
+        let output = match (output.as_deref(), mode) {
+            (Some("-"), Mode::Link) => return Err("Cannot write an executable to stdout".into()),
+            (Some("-"), _) => None,
+            (Some(f), _) => Some(PathBuf::from(f)),
+            (None, Mode::Assemble) if emit == Emit::Asm => Some(input.with_extension("s")),
+            (None, Mode::Link) => Some(input.with_extension("")),
+            (None, _) => None,
+        };
+        let runtime = runtime
+            .or_else(|| std::env::var_os("EOC_RUNTIME").map(PathBuf::from))
+            .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/runtime.c"));
+
+        Ok(Some(Self {
+            input,
+            output,
+            emit,
+            mode,
+            runtime,
+            checked_arith,
+            layout,
+        }))
+    }
+
+    fn scanner<'r>(&self, source: &str, reporter: &'r ErrorReporter) -> Scanner<'r> {
+        let scanner = Scanner::new(source, reporter);
+        if self.layout {
+            scanner.layout()
+        } else {
+            scanner
+        }
+    }
+
+    fn select_instructions<'r>(&self, reporter: &'r ErrorReporter) -> SelectInstructions<'r> {
+        let select = SelectInstructions::new(reporter);
+        if self.checked_arith {
+            select.checked_arith()
+        } else {
+            select
+        }
+    }
+
+    /// 解释器按同样的规则检查算术运算.
+    fn ast_interpreter<'a, R: BufRead, W: Write>(
+        &self,
+        reporter: &'a ErrorReporter,
+        input: R,
+        output: W,
+    ) -> AstInterpreter<'a, R, W> {
+        let interp = AstInterpreter::new(input, output).reporter(reporter);
+        if self.checked_arith {
+            interp.checked_arith(reporter)
+        } else {
+            interp
+        }
+    }
+
+    fn clike_interpreter<'a, R: BufRead, W: Write>(
+        &self,
+        reporter: &'a ErrorReporter,
+        input: R,
+        output: W,
+    ) -> ClikeInterpreter<'a, R, W> {
+        let interp = ClikeInterpreter::new(input, output);
+        if self.checked_arith {
+            interp.checked_arith(reporter)
+        } else {
+            interp
+        }
+    }
+
+    fn write_output(&self, content: &str) -> Result<()> {
+        match &self.output {
+            Some(f) => write(f, content)?,
+            None => print!("{}", content),
+        }
+        Ok(())
+    }
+
+    /// 输出中间阶段的结果, 编译到此为止.
+    fn dump<T>(&self, content: &str) -> Result<Option<T>> {
+        self.write_output(content)?;
+        Ok(None)
+    }
+}
+
+fn dump_lines<T: Display>(items: impl IntoIterator<Item = T>) -> String {
+    let mut out = String::new();
+    for i in items {
+        let _ = writeln!(out, "{}", i);
+    }
+    out
+}
+
+/// 按`opts.mode`执行编译, 返回进程的退出码.
+pub fn run(opts: &Options) -> Result<i32> {
+    match opts.mode {
+        Mode::Interp => return interpret(opts),
+        Mode::CheckPasses => return check_passes(opts),
+        _ => (),
+    }
+    let asm = match compile(opts)? {
+        Some(asm) => asm,
+        None => return Ok(0),
+    };
+    match opts.mode {
+        Mode::Assemble => {
+            opts.write_output(&asm)?;
+            Ok(0)
+        }
+        Mode::Link => {
+            let exe = opts.output.as_ref().expect("Expected an executable path.");
+            let dir = build_dir()?;
+            let res = link(&asm, &opts.runtime, &dir, exe);
+            let _ = remove_dir_all(&dir);
+            res.map(|_| 0)
+        }
+        Mode::Run => {
+            let dir = build_dir()?;
+            let exe = dir.join("a.out");
+            let res = link(&asm, &opts.runtime, &dir, &exe).and_then(|_| {
+                // 子进程继承stdin/stdout/stderr.
+                let status = Command::new(&exe).status()?;
+                Ok(status
+                    .code()
+                    .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)))
+            });
+            let _ = remove_dir_all(&dir);
+            res
+        }
+        Mode::Interp | Mode::CheckPasses => unreachable!(),
+    }
+}
+
+/// 类型检查之后用AST解释器运行程序, 读写进程的stdin/stdout(或`-o`指定的文件).
+fn interpret(opts: &Options) -> Result<i32> {
+    let source = read_to_string(&opts.input)?;
+    let reporter = ErrorReporter::new(Some(opts.input.clone()), source.clone());
+    let tokens = opts.scanner(&source, &reporter).scan_tokens()?;
+    let mut sts = Parser::new(tokens, &reporter).program()?;
+    TypeChecker::new(&reporter).check(&mut sts)?;
+
+    let stdin = std::io::stdin().lock();
+    match &opts.output {
+        Some(f) => {
+            let out = BufWriter::new(File::create(f)?);
+            opts.ast_interpreter(&reporter, stdin, out)
+                .interp_program(&sts)?
+        }
+        None => opts
+            .ast_interpreter(&reporter, stdin, std::io::stdout().lock())
+            .interp_program(&sts)?,
+    }
+    Ok(0)
+}
+
+fn build_dir() -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("eoc-rs-{}", std::process::id()));
+    create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// 程序的标准输出, 以及退出码或解释器报告的错误(如运行时错误).
+type Outcome = (String, std::result::Result<u8, String>);
+
+fn observe<F>(input: &str, f: F) -> Outcome
+where
+    F: FnOnce(&[u8], &mut Vec<u8>) -> Result<i64>,
+{
+    let mut out = vec![];
+    // 与进程的退出码一样只保留最低字节.
+    let res = f(input.as_bytes(), &mut out)
+        .map(|code| code as u8)
+        .map_err(|e| e.to_string());
+    (String::from_utf8_lossy(&out).into_owned(), res)
+}
+
+fn describe(res: &std::result::Result<u8, String>) -> String {
+    match res {
+        Ok(code) => format!("exit code {}", code),
+        Err(e) => format!("error `{}`", e),
+    }
+}
+
+/// 比较`pass`的输出与源程序的行为, 输出比较结果, 一致时返回`true`.
+/// 在同样的输出之后报告同样的错误也算一致.
+fn check_pass(pass: &str, expected: &Outcome, actual: Outcome) -> bool {
+    if actual == *expected {
+        println!("{}: ok", pass);
+        return true;
+    }
+    println!("{}: diverges", pass);
+    println!(
+        "expected {}, output:\n{}",
+        describe(&expected.1),
+        expected.0
+    );
+    println!("actual {}, output:\n{}", describe(&actual.1), actual.0);
+    false
+}
+
+/// 在同一输入上解释执行源程序和每个pass的输出, 在第一个行为不一致的pass处停止.
+fn check_passes(opts: &Options) -> Result<i32> {
+    let source = read_to_string(&opts.input)?;
+    let reporter = ErrorReporter::new(Some(opts.input.clone()), source.clone());
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+
+    let tokens = opts.scanner(&source, &reporter).scan_tokens()?;
+    let mut sts = Parser::new(tokens, &reporter).program()?;
+    TypeChecker::new(&reporter).check(&mut sts)?;
+    let expected = observe(&input, |i, o| {
+        opts.ast_interpreter(&reporter, i, o).interp_program(&sts)?;
+        Ok(0)
+    });
+
+    macro_rules! check {
+        ($pass:expr, $run:expr) => {
+            if !check_pass($pass, &expected, observe(&input, $run)) {
+                return Ok(1);
+            }
+        };
+    }
+
+    let sts = Shrink::new(&reporter).shrink_stmts(sts);
+    check!("shrink", |i, o| {
+        opts.ast_interpreter(&reporter, i, o).interp_program(&sts)?;
+        Ok(0)
+    });
+
+    let sts = ClosureConversion::new().convert_stmts(sts);
+    check!("closure", |i, o| {
+        opts.ast_interpreter(&reporter, i, o).interp_program(&sts)?;
+        Ok(0)
+    });
+
+    let sts = LimitFunctions::new().limit_stmts(sts);
+    check!("limit", |i, o| {
+        opts.ast_interpreter(&reporter, i, o).interp_program(&sts)?;
+        Ok(0)
+    });
+
+    let sts = ExposeAllocation::new(&reporter).expose_stmts(sts);
+    check!("expose", |i, o| {
+        opts.ast_interpreter(&reporter, i, o).interp_program(&sts)?;
+        Ok(0)
+    });
+
+    let stmts = RemoveComplexOperands::new().rco_stmts(sts);
+    check!("rco", |i, o| {
+        opts.ast_interpreter(&reporter, i, o)
+            .interp_program(&stmts)?;
+        Ok(0)
+    });
+
+    let cprog = ExplicateControl::new().explicate_control(stmts)?;
+    check!("clike", |i, o| {
+        opts.clike_interpreter(&reporter, i, o)
+            .interp_program(&cprog)?
+            .int()
+    });
+
+    // 后端分别处理每个函数, 合并之后再执行.
+    let defs = opts.select_instructions(&reporter).select_program(cprog);
+    let prog = Program::merge(defs.iter().map(|(_, p)| p.clone()));
+    check!("select", |i, o| X86Emulator::new(i, o).run(&prog));
+
+    let defs: Vec<_> = defs
+        .into_iter()
+        .map(|(name, prog)| {
+            let (prog, frame) = allocate_registers(prog);
+            (name, prog, frame)
+        })
+        .collect();
+    let prog = Program::merge(defs.iter().map(|(_, p, _)| p.clone()));
+    check!("homes", |i, o| X86Emulator::new(i, o).run(&prog));
+
+    let defs: Vec<_> = defs
+        .into_iter()
+        .map(|(name, prog, frame)| {
+            (
+                name,
+                PatchInstructions::new(prog).patch_instructions(),
+                frame,
+            )
+        })
+        .collect();
+    let prog = Program::merge(defs.iter().map(|(_, p, _)| p.clone()));
+    check!("patch", |i, o| X86Emulator::new(i, o).run(&prog));
+
+    let prog = Program::merge(
+        defs.into_iter()
+            .map(|(name, prog, frame)| CodeGen::new(&name, prog, frame).code_gen()),
+    );
+    check!("asm", |i, o| X86Emulator::new(i, o).run(&prog));
+
+    Ok(0)
+}
+
+/// 为一个函数分配寄存器和栈位置.
+fn allocate_registers(prog: Program) -> (Program, Frame) {
+    let live_blocks = UncoverLive::uncover_live(&prog);
+    let (graph, move_graph) = BuildInterference::new(&prog.pointers).build_graph(&live_blocks);
+    let (mapping, frame) = Allocation::new(graph, move_graph, &prog.pointers).color_graph();
+    (AssignHomes::new(prog, mapping).assign_homes(), frame)
+}
+
+/// 把汇编写到`dir`中, 再调用系统的`cc`与运行时一起编译链接为`exe`.
+fn link(asm: &str, runtime: &Path, dir: &Path, exe: &Path) -> Result<()> {
+    let asm_file = dir.join("program.s");
+    write(&asm_file, asm)?;
+    let output = Command::new("cc")
+        .arg("-w")
+        .arg("-o")
+        .arg(exe)
+        .arg(&asm_file)
+        .arg(runtime)
+        .output()
+        .map_err(|e| format!("failed to invoke `cc`: {}", e))?;
+    if !output.status.success() {
+        let msg = format!(
+            "linking failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return Err(msg.into());
+    }
+    Ok(())
+}
+
+/// 按块的输出顺序打印每条指令及其live-after集合.
+fn dump_liveness(prog: &Program, blocks: &LiveBlocks) -> String {
+    let mut out = String::new();
+    for label in prog.block_order() {
+        let _ = writeln!(out, "{}:", label);
+        for (inst, live) in &blocks[&label] {
+            let _ = writeln!(out, "\t{}\n\t    {}", inst, live);
+        }
+    }
+    out
+}
+
+/// 运行编译流程. 若`opts.emit`是汇编之前的阶段, 就在该阶段停止并输出其结果, 返回`None`;
+/// 否则返回完整的汇编文件.
+/// 扩展名为`.clike`的输入是C-like IR的文本格式, 直接从指令选择开始编译.
+pub fn compile(opts: &Options) -> Result<Option<String>> {
+    let source = read_to_string(&opts.input)?;
+    let reporter = ErrorReporter::new(Some(opts.input.clone()), source.clone());
+
+    let cprog = if opts.input.extension().is_some_and(|e| e == "clike") {
+        if opts.emit < Emit::Clike {
+            return Err(format!("cannot emit {:?} for C-like input", opts.emit).into());
+        }
+        ClikeParser::new(&source, &reporter).program()?
+    } else {
+        match front_end(opts, &source, &reporter)? {
+            Some(cprog) => cprog,
+            None => return Ok(None),
+        }
+    };
+    if opts.emit == Emit::Clike {
+        return opts.dump(&cprog.to_string());
+    }
+
+    // 后端分别处理每个函数, 最后把所有函数合并为一个汇编文件.
+    let mut progs = vec![];
+    let mut text = String::new();
+    for (name, prog) in opts.select_instructions(&reporter).select_program(cprog) {
+        match back_end(opts, &name, prog) {
+            BackEnd::Prog(prog) => progs.push(prog),
+            BackEnd::Text(t) => {
+                if name != CProgrom::MAIN {
+                    let _ = writeln!(text, "# {}", name);
+                }
+                text.push_str(&t);
+            }
+        }
+    }
+    let prog = Program::merge(progs);
+    match opts.emit {
+        Emit::Asm => Ok(Some(prog.to_string())),
+        Emit::Live | Emit::Interference | Emit::Alloc => opts.dump(&text),
+        _ => opts.dump(&prog.to_string()),
+    }
+}
+
+/// 一个函数在后端某个阶段的结果.
+enum BackEnd {
+    Prog(Program),
+    /// 不是x86程序的中间结果.
+    Text(String),
+}
+
+/// 从指令选择之后到生成完整的函数. 在`opts.emit`指定的阶段停止.
+fn back_end(opts: &Options, name: &str, prog: Program) -> BackEnd {
+    if opts.emit == Emit::Select {
+        return BackEnd::Prog(prog);
+    }
+
+    let live_blocks = UncoverLive::uncover_live(&prog);
+    if opts.emit == Emit::Live {
+        return BackEnd::Text(dump_liveness(&prog, &live_blocks));
+    }
+
+    let (graph, move_graph) = BuildInterference::new(&prog.pointers).build_graph(&live_blocks);
+    if opts.emit == Emit::Interference {
+        let dot = Dot::with_config(&graph, &[Config::EdgeNoLabel]);
+        return BackEnd::Text(format!("{:?}", dot));
+    }
+
+    let (mapping, frame) = Allocation::new(graph, move_graph, &prog.pointers).color_graph();
+    if opts.emit == Emit::Alloc {
+        let mut lines: Vec<_> = mapping
+            .iter()
+            .map(|(v, loc)| format!("{} -> {}", v, loc))
+            .collect();
+        lines.sort();
+        return BackEnd::Text(dump_lines(lines));
+    }
+
+    let prog = AssignHomes::new(prog, mapping).assign_homes();
+    if opts.emit == Emit::Homes {
+        return BackEnd::Prog(prog);
+    }
+
+    let prog = PatchInstructions::new(prog).patch_instructions();
+    if opts.emit == Emit::Patch {
+        return BackEnd::Prog(prog);
+    }
+
+    BackEnd::Prog(CodeGen::new(name, prog, frame).code_gen())
+}
+
+/// 从源程序到C-like IR. 在`opts.emit`指定的阶段停止时返回`None`.
+fn front_end(opts: &Options, source: &str, reporter: &ErrorReporter) -> Result<Option<CProgrom>> {
+    let tokens = opts.scanner(source, reporter).scan_tokens()?;
+    if opts.emit == Emit::Tokens {
+        let out = dump_lines(
+            tokens
+                .iter()
+                .map(|tk| format!("{:?} {:?}", tk.kind(), tk.lexeme())),
+        );
+        return opts.dump(&out);
+    }
+
+    let mut sts = Parser::new(tokens, reporter).program()?;
+    TypeChecker::new(reporter).check(&mut sts)?;
+    if opts.emit == Emit::Ast {
+        return opts.dump(&dump_lines(&sts));
+    }
+
+    let sts = Shrink::new(reporter).shrink_stmts(sts);
+    if opts.emit == Emit::Shrink {
+        return opts.dump(&dump_lines(&sts));
+    }
+
+    let sts = ClosureConversion::new().convert_stmts(sts);
+    if opts.emit == Emit::Closure {
+        return opts.dump(&dump_lines(&sts));
+    }
+
+    let sts = LimitFunctions::new().limit_stmts(sts);
+    if opts.emit == Emit::Limit {
+        return opts.dump(&dump_lines(&sts));
+    }
+
+    let sts = ExposeAllocation::new(reporter).expose_stmts(sts);
+    if opts.emit == Emit::Expose {
+        return opts.dump(&dump_lines(&sts));
+    }
+
+    let stmts = RemoveComplexOperands::new().rco_stmts(sts);
+    if opts.emit == Emit::Rco {
+        return opts.dump(&dump_lines(&stmts));
+    }
+
+    let cprog = ExplicateControl::new().explicate_control(stmts)?;
+    Ok(Some(cprog))
+}
@@ -41,10 +41,12 @@ impl<T> Env<T> {
         self.current_level += 1;
     }
 
+    #[allow(dead_code)]
     pub fn contains(&mut self, sym: &str) -> bool {
         self.map.contains_key(sym)
     }
 
+    #[allow(dead_code)]
     pub fn update(&mut self, symbol: String, data: T) {
         let pos = self.map.get(&symbol).cloned().unwrap();
         self.stack[pos].data = data;
@@ -106,6 +108,7 @@ impl<T> Env<T> {
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -0,0 +1,535 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use super::{
+    allocate_list, arith_error, div_floor, global_value, list_ref, list_set, subscript, Closure,
+    Io, Result, Value,
+};
+use crate::{
+    ast::{Expr, ExprData, Stmt, StmtData},
+    pass::{
+        closure_conversion::free_vars,
+        expose_allocation::MAX_LIST_LEN,
+        select_instructions::{ERROR_DIVIDE_BY_ZERO, ERROR_OVERFLOW, ERROR_ZERO_STEP},
+    },
+    reporter::ErrorReporter,
+    token::{Kind, Token},
+    type_checking::BUILTINS,
+};
+
+/// 非尾调用的嵌套层数上限, 超过时报告错误而不是耗尽宿主的栈.
+const MAX_DEPTH: usize = 20_000;
+
+enum Callee {
+    Func(String),
+    Closure(Rc<Closure>),
+}
+
+/// 直接解释执行AST. Shrink和RCO的输出仍是AST, 也可以用它来执行.
+pub struct AstInterpreter<'a, R, W> {
+    io: Io<R, W>,
+    /// 与编译后的程序一样, 块中赋值的变量在块外仍然可见. 每次函数调用都有自己的变量.
+    vars: HashMap<String, Value>,
+    /// 顶层函数的参数和函数体.
+    functions: HashMap<String, (Vec<&'a str>, &'a Expr)>,
+    /// 执行过的lambda的参数, 函数体和自由变量, 以`lambda@位置`为名.
+    lambdas: HashMap<String, (Vec<&'a str>, &'a Expr, Vec<String>)>,
+    /// 执行`return`之后为`Some`, 直到回到调用者.
+    ret: Option<Value>,
+    /// 尾部位置的调用, 由`call`执行.
+    tail: Option<(Callee, Vec<Value>)>,
+    /// 尚未返回的调用个数.
+    depth: usize,
+    /// 检查算术运算时用于换算出错的位置, 见`eval_prim`.
+    checked: Option<&'a ErrorReporter>,
+    /// 用于换算其他运行时错误的位置.
+    reporter: Option<&'a ErrorReporter>,
+}
+
+impl<'a, R: BufRead, W: Write> AstInterpreter<'a, R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            io: Io::new(input, output),
+            vars: HashMap::new(),
+            functions: HashMap::new(),
+            lambdas: HashMap::new(),
+            ret: None,
+            tail: None,
+            depth: 0,
+            checked: None,
+            reporter: None,
+        }
+    }
+
+    pub fn reporter(mut self, reporter: &'a ErrorReporter) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    /// 与`--checked-arith`编译的程序一样, 溢出和除零时报告运行时错误.
+    pub fn checked_arith(mut self, reporter: &'a ErrorReporter) -> Self {
+        self.checked = Some(reporter);
+        self
+    }
+
+    /// 执行整个程序. 函数可以在定义之前调用, 所以先收集所有的函数定义.
+    pub fn interp_program(&mut self, stmts: &'a [Stmt]) -> Result<()> {
+        for s in stmts {
+            if let StmtData::Def {
+                name, params, body, ..
+            } = &s.stmt
+            {
+                let params = params.iter().map(|(p, _)| p.lexeme()).collect();
+                self.functions.insert(name.lexeme().into(), (params, body));
+            }
+        }
+        self.interp_stmts(stmts)
+    }
+
+    fn interp_stmts(&mut self, stmts: &'a [Stmt]) -> Result<()> {
+        for s in stmts {
+            self.interp_stmt(s)?;
+            if self.ret.is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 尾调用不增长宿主的栈: 函数体在尾部位置的调用记在`self.tail`中, 返回到这里后再执行.
+    fn call(&mut self, mut callee: Callee, mut args: Vec<Value>) -> Result<Value> {
+        if self.depth == MAX_DEPTH {
+            return Err("maximum recursion depth exceeded".into());
+        }
+        self.depth += 1;
+        let caller = std::mem::take(&mut self.vars);
+        let res = loop {
+            let res = match callee {
+                Callee::Func(f) => self.call_func(&f, args),
+                Callee::Closure(c) => self.apply(&c, args),
+            };
+            match (res, self.tail.take()) {
+                (Ok(_), Some((c, a))) => {
+                    self.ret = None;
+                    (callee, args) = (c, a);
+                }
+                (res, _) => break res,
+            }
+        };
+        self.vars = caller;
+        self.depth -= 1;
+        res
+    }
+
+    fn call_func(&mut self, func: &str, args: Vec<Value>) -> Result<Value> {
+        let Some((params, body)) = self.functions.get(func).cloned() else {
+            return self.io.call_builtin(func, &args);
+        };
+        self.vars = params.into_iter().map(String::from).zip(args).collect();
+        self.interp_exp(body)?;
+        Ok(self.ret.take().unwrap_or(Value::Unit))
+    }
+
+    /// lambda的捕获值绑定到其自由变量上. 其他闭包中是顶层函数.
+    fn apply(&mut self, closure: &Closure, args: Vec<Value>) -> Result<Value> {
+        let Some((params, body, free)) = self.lambdas.get(&closure.func).cloned() else {
+            return self.call_func(&closure.func, args);
+        };
+        let captures = free.into_iter().zip(closure.captures.iter().cloned());
+        let vars = params.into_iter().map(String::from).zip(args);
+        self.vars = captures.chain(vars).collect();
+        Ok(self.interp_tail(body)?.unwrap_or(Value::Unit))
+    }
+
+    /// 尾部位置的调用只计算被调用者和参数, 返回`None`.
+    fn interp_tail(&mut self, e: &'a Expr) -> Result<Option<Value>> {
+        match &e.data {
+            ExprData::Condition {
+                condition,
+                then,
+                else_,
+            } => {
+                if self.interp_exp(condition)?.bool()? {
+                    self.interp_tail(then)
+                } else {
+                    self.interp_tail(else_)
+                }
+            }
+            ExprData::Block {
+                body,
+                result: Some(r),
+            } => {
+                self.interp_stmts(body)?;
+                if self.ret.is_some() {
+                    return Ok(Some(Value::Unit));
+                }
+                self.interp_tail(r)
+            }
+            ExprData::Call { .. } | ExprData::Apply { .. } => {
+                self.tail = Some(self.callee(e)?);
+                Ok(None)
+            }
+            _ => self.interp_exp(e).map(Some),
+        }
+    }
+
+    fn callee(&mut self, e: &'a Expr) -> Result<(Callee, Vec<Value>)> {
+        let mut values = vec![];
+        let callee = match &e.data {
+            ExprData::Call { name, .. } => {
+                match name.get_ident().filter(|f| !self.vars.contains_key(*f)) {
+                    Some(f) => Callee::Func(f.into()),
+                    None => match self.interp_exp(name)? {
+                        Value::Closure(c) => Callee::Closure(c),
+                        v => return Err(format!("Expected a function, found {}", v).into()),
+                    },
+                }
+            }
+            ExprData::Apply { closure, .. } => {
+                let c = self.interp_exp(closure)?;
+                let Value::Closure(clos) = &c else {
+                    return Err(format!("Expected a closure, found {}", c).into());
+                };
+                let func = clos.func.clone();
+                values.push(c);
+                Callee::Func(func)
+            }
+            _ => unreachable!(),
+        };
+        let (ExprData::Call { args, .. } | ExprData::Apply { args, .. }) = &e.data else {
+            unreachable!()
+        };
+        for a in args {
+            values.push(self.interp_exp(a)?);
+        }
+        Ok((callee, values))
+    }
+
+    fn lambda(&mut self, e: &'a Expr, params: &'a [Token], body: &'a Expr) -> Result<Value> {
+        let func = format!("lambda@{}", e.range.0);
+        if !self.lambdas.contains_key(&func) {
+            let functions = &self.functions;
+            let is_global = |n: &str| functions.contains_key(n) || BUILTINS.contains(&n);
+            let free = free_vars(params, body, &is_global);
+            let params = params.iter().map(|p| p.lexeme()).collect();
+            self.lambdas.insert(func.clone(), (params, body, free));
+        }
+        let mut captures = vec![];
+        for v in &self.lambdas[&func].2 {
+            captures.push(self.var(v)?);
+        }
+        Ok(Value::Closure(Rc::new(Closure { func, captures })))
+    }
+
+    fn var(&self, name: &str) -> Result<Value> {
+        self.vars
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Undefined variable `{}`", name).into())
+    }
+
+    fn interp_stmt(&mut self, s: &'a Stmt) -> Result<()> {
+        match &s.stmt {
+            StmtData::Expr(e) => {
+                self.interp_exp(e)?;
+            }
+            StmtData::Assign { name, binding } | StmtData::AnnAssign { name, binding, .. } => {
+                let v = self.interp_exp(binding)?;
+                self.vars.insert(name.lexeme().into(), v);
+            }
+            StmtData::AugAssign { name, op, binding } => {
+                let v = self.interp_exp(binding)?;
+                let old = self.var(name.lexeme())?;
+                let range = Some((name.pos(), s.range.1));
+                let v = eval_prim(op.kind(), &[old, v], self.checked, range)?;
+                self.vars.insert(name.lexeme().into(), v);
+            }
+            StmtData::MultiAssign { names, bindings } => {
+                let mut values = vec![];
+                for b in bindings {
+                    values.push(self.interp_exp(b)?);
+                }
+                // `a, b = t`
+                if let ([Value::Tuple(elems)], true) = (values.as_slice(), names.len() > 1) {
+                    values = elems.to_vec();
+                }
+                for (name, v) in names.iter().zip(values) {
+                    self.vars.insert(name.lexeme().into(), v);
+                }
+            }
+            StmtData::If {
+                condition,
+                then,
+                else_,
+            } => {
+                if self.interp_exp(condition)?.bool()? {
+                    self.interp_exp(then)?;
+                } else {
+                    self.interp_exp(else_)?;
+                }
+            }
+            StmtData::Def { .. } => (),
+            StmtData::Return(value) => {
+                let v = match value {
+                    Some(e) => self.interp_tail(e)?.unwrap_or(Value::Unit),
+                    None => Value::Unit,
+                };
+                self.ret = Some(v);
+            }
+            StmtData::While { condition, body } => {
+                while self.ret.is_none() && self.interp_exp(condition)?.bool()? {
+                    self.interp_exp(body)?;
+                }
+            }
+            // 与Shrink的改写一致: 循环体对循环变量的赋值不影响迭代, 步长不为正时递减.
+            StmtData::For {
+                var,
+                start,
+                stop,
+                step,
+                body,
+            } => {
+                let mut i = self.interp_exp(start)?.int()?;
+                let stop = self.interp_exp(stop)?.int()?;
+                let step = match step {
+                    Some(s) => match self.interp_exp(s)?.int()? {
+                        0 => {
+                            let (line, col) =
+                                self.reporter.map_or((0, 0), |r| r.line_col(s.range.0));
+                            return Err(arith_error(ERROR_ZERO_STEP, line as i64, col as i64));
+                        }
+                        s => s,
+                    },
+                    None => 1,
+                };
+                while self.ret.is_none() && if step > 0 { i < stop } else { i > stop } {
+                    self.vars.insert(var.lexeme().into(), Value::Int(i));
+                    i = i.wrapping_add(step);
+                    self.interp_exp(body)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn interp_exp(&mut self, e: &'a Expr) -> Result<Value> {
+        match &e.data {
+            ExprData::Int(i) => Ok(Value::Int(*i)),
+            ExprData::Bool(b) => Ok(Value::Bool(*b)),
+            ExprData::Float(_) => Err("Floats are not supported".into()),
+            ExprData::Str(s) => Ok(Value::Str(s.as_str().into())),
+            // 变量不会与顶层函数同名.
+            ExprData::Name(n) if self.functions.contains_key(n.lexeme()) => {
+                Ok(Value::Closure(Rc::new(Closure {
+                    func: n.lexeme().into(),
+                    captures: vec![],
+                })))
+            }
+            ExprData::Name(n) => self.var(n.lexeme()),
+            ExprData::Prim { op, operands } => match op.kind() {
+                // 短路求值
+                Kind::And => {
+                    if self.interp_exp(&operands[0])?.bool()? {
+                        self.interp_exp(&operands[1])
+                    } else {
+                        Ok(Value::Bool(false))
+                    }
+                }
+                Kind::Or => {
+                    if self.interp_exp(&operands[0])?.bool()? {
+                        Ok(Value::Bool(true))
+                    } else {
+                        self.interp_exp(&operands[1])
+                    }
+                }
+                k => {
+                    let mut args = vec![];
+                    for a in operands {
+                        args.push(self.interp_exp(a)?);
+                    }
+                    eval_prim(k, &args, self.checked, Some(e.range))
+                }
+            },
+            ExprData::Call { .. } | ExprData::Apply { .. } => {
+                let (callee, args) = self.callee(e)?;
+                self.call(callee, args)
+            }
+            ExprData::Condition {
+                condition,
+                then,
+                else_,
+            } => {
+                if self.interp_exp(condition)?.bool()? {
+                    self.interp_exp(then)
+                } else {
+                    self.interp_exp(else_)
+                }
+            }
+            ExprData::Block { body, result } => {
+                self.interp_stmts(body)?;
+                if self.ret.is_some() {
+                    return Ok(Value::Unit);
+                }
+                match result {
+                    Some(r) => self.interp_exp(r),
+                    None => Ok(Value::Unit),
+                }
+            }
+            ExprData::Lambda { params, body, .. } => self.lambda(e, params, body),
+            ExprData::Closure { func, captures } => {
+                let mut values = vec![];
+                for c in captures {
+                    values.push(self.interp_exp(c)?);
+                }
+                Ok(Value::Closure(Rc::new(Closure {
+                    func: func.lexeme().into(),
+                    captures: values,
+                })))
+            }
+            ExprData::Capture { closure, index } => match self.interp_exp(closure)? {
+                Value::Closure(c) => Ok(c.captures[*index].clone()),
+                v => Err(format!("Expected a closure, found {}", v).into()),
+            },
+            ExprData::Tuple { elems } => {
+                let mut values = vec![];
+                for e in elems {
+                    values.push(self.interp_exp(e)?);
+                }
+                Ok(Value::Tuple(Rc::new(values)))
+            }
+            ExprData::Subscript { value, index } => {
+                let v = self.interp_exp(value)?;
+                subscript(&v, self.interp_exp(index)?.int()?)
+            }
+            ExprData::List { elems, repeat, .. } => {
+                let mut values = vec![];
+                for e in elems {
+                    values.push(self.interp_exp(e)?);
+                }
+                if let Some(n) = repeat {
+                    let n = self.interp_exp(n)?.int()?;
+                    // 与编译得到的代码一样, 在分配之前检查长度.
+                    let len = (values.len() as i64).saturating_mul(n.max(0));
+                    if len > MAX_LIST_LEN {
+                        let (line, col) = self.reporter.map_or((0, 0), |r| r.line_col(e.range.0));
+                        return Err(arith_error(ERROR_OVERFLOW, line as i64, col as i64));
+                    }
+                    let mut list = vec![];
+                    list.try_reserve_exact(len as usize)
+                        .map_err(|_| format!("failed to allocate a list of length {}", len))?;
+                    list.extend(values.iter().cycle().take(len as usize).cloned());
+                    values = list;
+                }
+                Ok(Value::List(Rc::new(RefCell::new(values))))
+            }
+            ExprData::ListRef { list, index } => {
+                let list = self.interp_exp(list)?;
+                list_ref(&list, self.interp_exp(index)?.int()?)
+            }
+            ExprData::ListSet { list, index, value }
+            | ExprData::ListInit { list, index, value } => {
+                let list = self.interp_exp(list)?;
+                let index = self.interp_exp(index)?.int()?;
+                list_set(&list, index, self.interp_exp(value)?)
+            }
+            ExprData::AllocateList { len, .. } => Ok(allocate_list(self.interp_exp(len)?.int()?)),
+            ExprData::GlobalValue(name) => global_value(name),
+            ExprData::Collect(_) => Ok(Value::Unit),
+        }
+    }
+}
+
+/// 基本运算. 整数运算与x86一样按64位补码回绕.
+pub fn interp_prim(op: Kind, args: &[Value]) -> Result<Value> {
+    use Value::*;
+    let v = match (op, args) {
+        (Kind::Minus, [a]) => Int(a.int()?.wrapping_neg()),
+        (Kind::Bang, [a]) => Bool(!a.bool()?),
+        (Kind::Plus, [a, b]) => Int(a.int()?.wrapping_add(b.int()?)),
+        (Kind::Minus, [a, b]) => Int(a.int()?.wrapping_sub(b.int()?)),
+        (Kind::Star, [a, b]) => Int(a.int()?.wrapping_mul(b.int()?)),
+        // `MIN / -1`与除零一样使idivq出错.
+        (Kind::Slash | Kind::Percent, [a, b]) => {
+            let (a, b) = (a.int()?, b.int()?);
+            if b == 0 {
+                return Err("division by zero".into());
+            }
+            let (q, r) = div_floor(a, b).ok_or("division overflow")?;
+            Int(if op == Kind::Slash { q } else { r })
+        }
+        (Kind::EqualEqual, [a, b]) => Bool(a == b),
+        (Kind::BangEqual, [a, b]) => Bool(a != b),
+        (Kind::Less, [a, b]) => Bool(a.int()? < b.int()?),
+        (Kind::LessEqual, [a, b]) => Bool(a.int()? <= b.int()?),
+        (Kind::Greater, [a, b]) => Bool(a.int()? > b.int()?),
+        (Kind::GreaterEqual, [a, b]) => Bool(a.int()? >= b.int()?),
+        (k, _) => return Err(format!("Unsupported operator {:?}", k).into()),
+    };
+    Ok(v)
+}
+
+/// `checked`不为`None`时检查溢出和除零, 与运行时的`arith_error`报告相同的错误,
+/// 出错的位置`range`由`checked`换算为行列号.
+pub fn eval_prim(
+    op: Kind,
+    args: &[Value],
+    checked: Option<&ErrorReporter>,
+    range: Option<(usize, usize)>,
+) -> Result<Value> {
+    let Some(reporter) = checked else {
+        return interp_prim(op, args);
+    };
+    let error = |kind| {
+        let (line, col) = range.map_or((0, 0), |r| reporter.line_col(r.0));
+        arith_error(kind, line as i64, col as i64)
+    };
+    let res = match (op, args) {
+        (Kind::Minus, [a]) => a.int()?.checked_neg(),
+        (Kind::Plus, [a, b]) => a.int()?.checked_add(b.int()?),
+        (Kind::Minus, [a, b]) => a.int()?.checked_sub(b.int()?),
+        (Kind::Star, [a, b]) => a.int()?.checked_mul(b.int()?),
+        (Kind::Slash | Kind::Percent, [a, b]) => {
+            let (a, b) = (a.int()?, b.int()?);
+            if b == 0 {
+                return Err(error(ERROR_DIVIDE_BY_ZERO));
+            }
+            div_floor(a, b).map(|(q, r)| if op == Kind::Slash { q } else { r })
+        }
+        _ => return interp_prim(op, args),
+    };
+    res.map(Value::Int).ok_or_else(|| error(ERROR_OVERFLOW))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, reporter::ErrorReporter, scanner::Scanner};
+
+    fn interp(source: &str, input: &str) -> String {
+        let reporter = ErrorReporter::new(None, source.to_string());
+        let tokens = Scanner::new(source, &reporter).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens, &reporter).program().unwrap();
+        let mut out = vec![];
+        AstInterpreter::new(input.as_bytes(), &mut out)
+            .interp_program(&stmts)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn condition_and_input() {
+        let source = "x = input_int()\ny = input_int()\nprint(x + y if x > 0 and y > 0 else 0)\n";
+        assert_eq!(interp(source, "40 2\n"), "42");
+        assert_eq!(interp(source, "-1\n2\n"), "0");
+    }
+
+    #[test]
+    fn block_assignments_are_visible_outside() {
+        let source = "x = 1\nif x == 1 { x = 2 } else { x = 3 }\nprint(x)\nprint(not (x < 2))\n";
+        assert_eq!(interp(source, ""), "2#t");
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use super::{
+    allocate_list, ast::eval_prim, global_value, list_ref, list_set, subscript, Closure, Io,
+    Result, Value,
+};
+use crate::{
+    pass::clike::{Atom, CProgrom, Expr, FunDef, Stmt},
+    reporter::ErrorReporter,
+};
+
+/// 执行`CProgrom`: 从`main`的入口块开始, 沿着`goto`/`if`在块之间跳转, 直到`return`.
+pub struct ClikeInterpreter<'a, R, W> {
+    io: Io<R, W>,
+    /// 每次函数调用都有自己的变量.
+    vars: HashMap<String, Value>,
+    functions: HashMap<&'a str, &'a FunDef>,
+    /// 检查算术运算时用于换算出错的位置, 见`eval_prim`.
+    checked: Option<&'a ErrorReporter>,
+}
+
+/// 函数执行结束的方式.
+enum Tail {
+    Return(Value),
+    Call(String, Vec<Value>),
+}
+
+impl<'a, R: BufRead, W: Write> ClikeInterpreter<'a, R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            io: Io::new(input, output),
+            vars: HashMap::new(),
+            functions: HashMap::new(),
+            checked: None,
+        }
+    }
+
+    /// 与`--checked-arith`编译的程序一样, 溢出和除零时报告运行时错误.
+    pub fn checked_arith(mut self, reporter: &'a ErrorReporter) -> Self {
+        self.checked = Some(reporter);
+        self
+    }
+
+    /// 返回`main`中`return`语句的值.
+    pub fn interp_program(&mut self, prog: &'a CProgrom) -> Result<Value> {
+        for def in &prog.defs {
+            self.functions.insert(&def.name, def);
+        }
+        if !self.functions.contains_key(CProgrom::MAIN) {
+            return Err("Undefined function `main`".into());
+        }
+        self.call(CProgrom::MAIN, vec![])
+    }
+
+    /// 尾调用不增加调用的深度: 直接换成被调用的函数继续执行.
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let Some(mut def) = self.functions.get(name).cloned() else {
+            return self.io.call_builtin(name, &args);
+        };
+        let caller = std::mem::take(&mut self.vars);
+        let mut args = args;
+        let res = loop {
+            self.vars = def
+                .params
+                .iter()
+                .map(|(p, _)| p.clone())
+                .zip(args)
+                .collect();
+            match self.interp_def(def) {
+                Ok(Tail::Return(v)) => break Ok(v),
+                Ok(Tail::Call(name, a)) => match self.functions.get(name.as_str()) {
+                    Some(d) => (def, args) = (d, a),
+                    None => break self.io.call_builtin(&name, &a),
+                },
+                Err(e) => break Err(e),
+            }
+        };
+        self.vars = caller;
+        res
+    }
+
+    fn interp_def(&mut self, def: &FunDef) -> Result<Tail> {
+        let mut label = def.entry();
+        loop {
+            let block = def
+                .blocks
+                .get(&label)
+                .ok_or_else(|| format!("Undefined label `{}`", label))?;
+            for s in &block.0 {
+                match s {
+                    Stmt::Exp(e) => {
+                        self.interp_exp(e)?;
+                    }
+                    Stmt::Assign { name, binding } => {
+                        let v = self.interp_exp(binding)?;
+                        self.vars.insert(name.to_string(), v);
+                    }
+                    Stmt::Return(e) => return Ok(Tail::Return(self.interp_exp(e)?)),
+                    Stmt::TailCall { name, args } => {
+                        let args = self.interp_atoms(args)?;
+                        return Ok(Tail::Call(name.to_string(), args));
+                    }
+                    Stmt::TailApply { closure, args } => {
+                        let c = self.interp_atom(closure)?;
+                        let Value::Closure(clos) = &c else {
+                            return Err(format!("Expected a closure, found {}", c).into());
+                        };
+                        let func = clos.func.clone();
+                        let mut values = vec![c];
+                        values.extend(self.interp_atoms(args)?);
+                        return Ok(Tail::Call(func, values));
+                    }
+                    Stmt::Goto(l) => {
+                        label = l.clone();
+                        break;
+                    }
+                    Stmt::If { cond, then, else_ } => {
+                        label = if self.interp_exp(cond)?.bool()? {
+                            then.clone()
+                        } else {
+                            else_.clone()
+                        };
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn interp_exp(&mut self, e: &Expr) -> Result<Value> {
+        match e {
+            Expr::Atom(a) => self.interp_atom(a),
+            Expr::Str(s) => Ok(Value::Str(s.as_str().into())),
+            Expr::Prim {
+                op,
+                operands,
+                range,
+            } => {
+                let mut args = vec![];
+                for a in operands {
+                    args.push(self.interp_atom(a)?);
+                }
+                eval_prim(*op, &args, self.checked, *range)
+            }
+            Expr::Call { name, args } => {
+                let args = self.interp_atoms(args)?;
+                self.call(&name.to_string(), args)
+            }
+            Expr::Closure { func, captures } => Ok(Value::Closure(Rc::new(Closure {
+                func: func.clone(),
+                captures: self.interp_atoms(captures)?,
+            }))),
+            Expr::Capture { closure, index } => match self.interp_atom(closure)? {
+                Value::Closure(c) => Ok(c.captures[*index].clone()),
+                v => Err(format!("Expected a closure, found {}", v).into()),
+            },
+            Expr::Apply { closure, args } => {
+                let c = self.interp_atom(closure)?;
+                let Value::Closure(clos) = &c else {
+                    return Err(format!("Expected a closure, found {}", c).into());
+                };
+                let func = clos.func.clone();
+                let mut values = vec![c];
+                values.extend(self.interp_atoms(args)?);
+                self.call(&func, values)
+            }
+            Expr::Tuple(elems) => Ok(Value::Tuple(Rc::new(self.interp_atoms(elems)?))),
+            Expr::Subscript { tuple, index } => subscript(&self.interp_atom(tuple)?, *index as i64),
+            Expr::GlobalValue(name) => global_value(name),
+            Expr::Collect(_) => Ok(Value::Unit),
+            Expr::Allocate { len, .. } => Ok(allocate_list(self.interp_atom(len)?.int()?)),
+            Expr::ListRef { list, index, .. } => {
+                list_ref(&self.interp_atom(list)?, self.interp_atom(index)?.int()?)
+            }
+            Expr::ListSet {
+                list, index, value, ..
+            }
+            | Expr::ListInit { list, index, value } => {
+                let index = self.interp_atom(index)?.int()?;
+                list_set(&self.interp_atom(list)?, index, self.interp_atom(value)?)
+            }
+        }
+    }
+
+    fn interp_atoms(&self, atoms: &[Atom]) -> Result<Vec<Value>> {
+        atoms.iter().map(|a| self.interp_atom(a)).collect()
+    }
+
+    fn interp_atom(&self, a: &Atom) -> Result<Value> {
+        match a {
+            Atom::Int(i) => Ok(Value::Int(*i)),
+            Atom::Bool(b) => Ok(Value::Bool(*b)),
+            Atom::Float(_) => Err("Floats are not supported".into()),
+            Atom::Name(n) => self
+                .vars
+                .get(n)
+                .cloned()
+                .ok_or_else(|| format!("Undefined variable `{}`", n).into()),
+        }
+    }
+}
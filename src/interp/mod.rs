@@ -0,0 +1,227 @@
+//! 各阶段中间表示的解释器, 用作差分测试的参照.
+
+pub mod ast;
+pub mod clike;
+pub mod x86;
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    error::Error,
+    fmt::Display,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use crate::pass::select_instructions::{ERROR_OVERFLOW, ERROR_ZERO_STEP};
+
+pub use self::{ast::AstInterpreter, clike::ClikeInterpreter, x86::X86Emulator};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Str(Rc<str>),
+    Unit,
+    Closure(Rc<Closure>),
+    Tuple(Rc<Vec<Value>>),
+    /// 列表可以修改, 所有引用看到的是同一个列表.
+    List(Rc<RefCell<Vec<Value>>>),
+}
+
+/// 函数值: 函数名和创建时捕获的值.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Closure {
+    pub func: String,
+    pub captures: Vec<Value>,
+}
+
+impl Value {
+    pub fn int(&self) -> Result<i64> {
+        match self {
+            Self::Int(i) => Ok(*i),
+            o => Err(format!("Expected an integer, found {}", o).into()),
+        }
+    }
+
+    pub fn bool(&self) -> Result<bool> {
+        match self {
+            Self::Bool(b) => Ok(*b),
+            o => Err(format!("Expected a boolean, found {}", o).into()),
+        }
+    }
+}
+
+/// 与运行时(runtime.c)中打印的格式相同.
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Bool(true) => write!(f, "#t"),
+            Self::Bool(false) => write!(f, "#f"),
+            Self::Str(s) => write!(f, "{}", s),
+            Self::Unit => write!(f, "()"),
+            Self::Closure(_) => write!(f, "#<function>"),
+            Self::Tuple(elems) if elems.len() == 1 => write!(f, "({},)", elems[0]),
+            Self::Tuple(elems) => {
+                let elems: Vec<String> = elems.iter().map(ToString::to_string).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Self::List(elems) => {
+                let elems: Vec<String> = elems.borrow().iter().map(ToString::to_string).collect();
+                write!(f, "[{}]", elems.join(", "))
+            }
+        }
+    }
+}
+
+/// 元组的第`index`个元素.
+fn subscript(value: &Value, index: i64) -> Result<Value> {
+    match value {
+        Value::Tuple(elems) => usize::try_from(index)
+            .ok()
+            .and_then(|i| elems.get(i))
+            .cloned()
+            .ok_or_else(|| format!("Index {} out of range for {}", index, value).into()),
+        v => Err(format!("Expected a tuple, found {}", v).into()),
+    }
+}
+
+/// 列表的下标越界时的错误, 与运行时的`index_error`相同.
+fn check_index(elems: &[Value], index: i64) -> Result<usize> {
+    match usize::try_from(index) {
+        Ok(i) if i < elems.len() => Ok(i),
+        _ => Err(format!(
+            "runtime error: index {} out of range for list of length {}",
+            index,
+            elems.len()
+        )
+        .into()),
+    }
+}
+
+/// 列表的第`index`个元素.
+fn list_ref(list: &Value, index: i64) -> Result<Value> {
+    match list {
+        Value::List(elems) => {
+            let elems = elems.borrow();
+            Ok(elems[check_index(&elems, index)?].clone())
+        }
+        v => Err(format!("Expected a list, found {}", v).into()),
+    }
+}
+
+/// 修改列表的第`index`个元素.
+fn list_set(list: &Value, index: i64, value: Value) -> Result<Value> {
+    match list {
+        Value::List(elems) => {
+            let mut elems = elems.borrow_mut();
+            let i = check_index(&elems, index)?;
+            elems[i] = value;
+            Ok(Value::Unit)
+        }
+        v => Err(format!("Expected a list, found {}", v).into()),
+    }
+}
+
+/// 长度为`len`的列表, 元素随后才初始化.
+fn allocate_list(len: i64) -> Value {
+    let elems = vec![Value::Unit; len.max(0) as usize];
+    Value::List(Rc::new(RefCell::new(elems)))
+}
+
+/// 与Python一样向下取整, 余数与除数同号. 除零和`MIN / -1`时返回`None`.
+fn div_floor(a: i64, b: i64) -> Option<(i64, i64)> {
+    let (q, r) = (a.checked_div(b)?, a.checked_rem(b)?);
+    if r != 0 && (r < 0) != (b < 0) {
+        Some((q - 1, r + b))
+    } else {
+        Some((q, r))
+    }
+}
+
+/// 与运行时的`arith_error`相同的错误.
+fn arith_error(kind: i64, line: i64, col: i64) -> Box<dyn Error> {
+    let msg = match kind {
+        ERROR_OVERFLOW => "integer overflow",
+        ERROR_ZERO_STEP => "`range` step must not be zero",
+        _ => "division by zero",
+    };
+    format!("runtime error: {} at {}:{}", msg, line, col).into()
+}
+
+/// 运行时中全局变量的值. 解释器中没有真正的堆, 分配总能成功, 不需要垃圾回收.
+fn global_value(name: &str) -> Result<Value> {
+    match name {
+        "free_ptr" | "fromspace_begin" => Ok(Value::Int(0)),
+        "fromspace_end" => Ok(Value::Int(i64::MAX)),
+        _ => Err(format!("Unknown global variable `{}`", name).into()),
+    }
+}
+
+/// 解释器的输入输出, 以及运行时提供的内建函数.
+pub struct Io<R, W> {
+    input: R,
+    output: W,
+    /// 已读入但还未被`input_int`取走的单词. 与`scanf`一样, 一行中可以有多个整数.
+    pending: VecDeque<String>,
+}
+
+impl<R: BufRead, W: Write> Io<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn read_int(&mut self) -> Result<i64> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err("input_int: unexpected end of input".into());
+            }
+            self.pending
+                .extend(line.split_whitespace().map(String::from));
+        }
+        let word = self.pending.pop_front().unwrap();
+        word.parse()
+            .map_err(|_| format!("input_int: `{}` is not an integer", word).into())
+    }
+
+    /// 调用内建函数. `print`在类型检查之后会被改写为`print_int`, `print_bool`或`print_str`.
+    pub fn call_builtin(&mut self, name: &str, args: &[Value]) -> Result<Value> {
+        match (name, args) {
+            ("input_int" | "read_int", []) => Ok(Value::Int(self.read_int()?)),
+            // 与运行时相同, 只有字符串之后换行.
+            ("print" | "print_int" | "print_bool", [v]) => {
+                write!(self.output, "{}", v)?;
+                Ok(Value::Unit)
+            }
+            ("print_str", [v]) => {
+                writeln!(self.output, "{}", v)?;
+                Ok(Value::Unit)
+            }
+            ("len", [Value::Tuple(elems)]) => Ok(Value::Int(elems.len() as i64)),
+            ("len", [Value::List(elems)]) => Ok(Value::Int(elems.borrow().len() as i64)),
+            // 检查算术运算时由编译器生成的调用.
+            ("arith_error", [kind, line, col]) => {
+                Err(arith_error(kind.int()?, line.int()?, col.int()?))
+            }
+            ("index_error", [index, len, line, col]) => Err(format!(
+                "runtime error: index {} out of range for list of length {} at {}:{}",
+                index, len, line, col
+            )
+            .into()),
+            _ => Err(format!(
+                "Unknown function `{}` with {} argument(s)",
+                name,
+                args.len()
+            )
+            .into()),
+        }
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+};
+
+use super::{Io, Result, Value};
+use crate::pass::x86::{Arg, ByteReg, CondCode, Instr, Label, Program, Reg};
+
+/// 模拟执行x86程序. 既可以执行指令选择之后仍含`Arg::Var`的程序,
+/// 也可以执行分配了寄存器和栈位置的程序, 以及带序言和结尾的完整程序.
+pub struct X86Emulator<R, W> {
+    io: Io<R, W>,
+    regs: HashMap<Reg, i64>,
+    vars: HashMap<String, i64>,
+    /// 按字节地址存放的8字节内存单元, 用于栈, 根栈和堆.
+    mem: HashMap<i64, i64>,
+    /// 运行时中的全局变量, 如`free_ptr`.
+    globals: HashMap<Label, i64>,
+    /// 最近一次`cmpq s, d`的操作数`(d, s)`, 条件码按`d - s`判断.
+    flags: (i64, i64),
+    /// 最近一次算术运算是否溢出.
+    overflow: bool,
+    /// 调用者的返回位置.
+    calls: Vec<CallFrame>,
+    /// 取过地址的函数, 地址由下标得到.
+    functions: Vec<Label>,
+    /// 只读数据段中每个字符串的地址.
+    rodata: HashMap<Label, i64>,
+    /// 每个地址处的字符串, 供`print_str`读取.
+    strings: HashMap<i64, String>,
+}
+
+/// 一次函数调用返回后继续执行的位置.
+struct CallFrame {
+    label: Label,
+    pc: usize,
+    func: Label,
+    vars: HashMap<String, i64>,
+    /// 还没有序言和结尾的函数由模拟器建立栈帧, 并保存callee-saved寄存器.
+    implicit: Option<Vec<(Reg, i64)>>,
+}
+
+/// 执行一条指令之后接下来要做的事.
+enum Step {
+    Next,
+    Jump(Label),
+    Call(Label, usize),
+    TailCall(Label, usize),
+    Return,
+}
+
+impl<R: BufRead, W: Write> X86Emulator<R, W> {
+    /// 栈底地址. 还没有序言的程序没有分配栈帧, 所以让%rsp离%rbp远一些.
+    const STACK_BASE: i64 = 0x7fff_0000;
+    const FRAME_SIZE: i64 = 4096;
+    /// 函数调用之后, 调用者保存的寄存器(%rax除外)都被置为这个值.
+    const CLOBBERED: i64 = 0x0bad_beef;
+    /// 压栈的返回地址. 真正的返回位置记录在`calls`中.
+    const RETURN_ADDRESS: i64 = 0xdead_c0de;
+    /// 第一个函数的地址.
+    const FUNCTION_BASE: i64 = 0x40_0000;
+    /// 只读数据段的起始地址. 字符串依次存放, 每个都以`\0`结尾.
+    const RODATA_BASE: i64 = 0x60_0000;
+    /// 堆的起始地址. 垃圾回收只是扩大堆, 不移动对象.
+    const HEAP_BASE: i64 = 0x1000_0000;
+    const HEAP_SIZE: i64 = 0x1_0000;
+    /// 根栈的起始地址.
+    const ROOTSTACK_BASE: i64 = 0x2000_0000;
+    /// 还没有序言的函数的根栈帧的大小.
+    const ROOT_FRAME_SIZE: i64 = 4096;
+
+    /// 还没有序言的程序不会调用`initialize`, 所以一开始就像已经初始化过一样.
+    pub fn new(input: R, output: W) -> Self {
+        let regs = HashMap::from([
+            (Reg::Rbp, Self::STACK_BASE),
+            (Reg::Rsp, Self::STACK_BASE - Self::FRAME_SIZE),
+            (Reg::R15, Self::ROOTSTACK_BASE + Self::ROOT_FRAME_SIZE),
+        ]);
+        let mut emulator = Self {
+            io: Io::new(input, output),
+            regs,
+            vars: HashMap::new(),
+            mem: HashMap::new(),
+            globals: HashMap::new(),
+            flags: (0, 0),
+            overflow: false,
+            calls: vec![],
+            functions: vec![],
+            rodata: HashMap::new(),
+            strings: HashMap::new(),
+        };
+        emulator.initialize(Self::HEAP_SIZE);
+        emulator
+    }
+
+    /// 模拟运行时的`initialize`.
+    fn initialize(&mut self, heap_size: i64) {
+        self.globals = HashMap::from([
+            ("free_ptr".into(), Self::HEAP_BASE),
+            ("fromspace_begin".into(), Self::HEAP_BASE),
+            ("fromspace_end".into(), Self::HEAP_BASE + heap_size),
+            ("rootstack_begin".into(), Self::ROOTSTACK_BASE),
+        ]);
+    }
+
+    fn global(&self, name: &str) -> Result<i64> {
+        self.globals
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("Undefined global variable `{}`", name).into())
+    }
+
+    /// 从`main`的序言(若存在)或入口块开始执行, 直到`main`返回或跳转到不存在的结尾块.
+    /// 返回%rax的值. `prog`中的函数都合并在一起.
+    pub fn run(&mut self, prog: &Program) -> Result<i64> {
+        self.load_strings(&prog.strings);
+        let mut func = Program::PRELUDE.to_string();
+        let mut label = if prog.blocks.contains_key(&func) {
+            func.clone()
+        } else {
+            Program::entry_of(&func)
+        };
+        let mut pc = 0;
+
+        loop {
+            let instrs = match prog.blocks.get(&label) {
+                Some(instrs) => instrs,
+                // 还没有结尾块时, 跳转到结尾就是返回.
+                None if label == Program::conclusion_of(&func) => match self.ret() {
+                    Some(frame) => {
+                        (label, pc, func) = frame;
+                        continue;
+                    }
+                    None => break,
+                },
+                None => return Err(format!("Undefined label `{}`", label).into()),
+            };
+            let Some(inst) = instrs.get(pc) else {
+                return Err(format!("Block `{}` does not end with a jump", label).into());
+            };
+            pc += 1;
+            match self.step(inst)? {
+                Step::Next => (),
+                Step::Jump(l) => {
+                    (label, pc) = (l, 0);
+                }
+                Step::Call(f, _) if prog.blocks.contains_key(&Program::entry_of(&f)) => {
+                    let implicit = !prog.blocks.contains_key(&f);
+                    self.enter(label, pc, func, implicit);
+                    label = if implicit {
+                        Program::entry_of(&f)
+                    } else {
+                        f.clone()
+                    };
+                    (pc, func) = (0, f);
+                }
+                Step::Call(f, arity) => self.call(&f, arity)?,
+                Step::TailCall(f, _) if prog.blocks.contains_key(&Program::entry_of(&f)) => {
+                    let implicit = !prog.blocks.contains_key(&f);
+                    self.tail_enter(implicit)?;
+                    label = if implicit {
+                        Program::entry_of(&f)
+                    } else {
+                        f.clone()
+                    };
+                    (pc, func) = (0, f);
+                }
+                // 内建函数返回到当前函数的调用者.
+                Step::TailCall(f, arity) => {
+                    self.call(&f, arity)?;
+                    match self.ret() {
+                        Some(frame) => (label, pc, func) = frame,
+                        None => break,
+                    }
+                }
+                Step::Return => match self.ret() {
+                    Some(frame) => (label, pc, func) = frame,
+                    None => break,
+                },
+            }
+        }
+        Ok(self.reg(Reg::Rax))
+    }
+
+    fn load_strings(&mut self, strings: &[(Label, String)]) {
+        let mut addr = Self::RODATA_BASE;
+        for (label, s) in strings {
+            self.rodata.insert(label.clone(), addr);
+            self.strings.insert(addr, s.clone());
+            addr += s.len() as i64 + 1;
+        }
+    }
+
+    fn string_at(&self, addr: i64) -> Result<Value> {
+        match self.strings.get(&addr) {
+            Some(s) => Ok(Value::Str(s.as_str().into())),
+            None => Err(format!("No string at address {:#x}", addr).into()),
+        }
+    }
+
+    /// 调用程序中的函数. 与`callq`一样压入返回地址, 必要时再模拟序言.
+    fn enter(&mut self, label: Label, pc: usize, func: Label, implicit: bool) {
+        self.push(Self::RETURN_ADDRESS);
+        let implicit = implicit.then(|| self.alloc_frame());
+        let vars = std::mem::take(&mut self.vars);
+        self.calls.push(CallFrame {
+            label,
+            pc,
+            func,
+            vars,
+            implicit,
+        });
+    }
+
+    /// 尾调用复用当前函数的返回位置. 有序言的函数在跳转之前已经释放了栈帧.
+    fn tail_enter(&mut self, implicit: bool) -> Result<()> {
+        let mut frame = self.calls.pop().ok_or("Tail call from `main`")?;
+        if let Some(saved) = frame.implicit.take() {
+            self.free_frame(saved);
+        }
+        frame.implicit = implicit.then(|| self.alloc_frame());
+        self.vars.clear();
+        self.calls.push(frame);
+        Ok(())
+    }
+
+    /// 模拟序言: 建立栈帧和根栈帧, 并保存callee-saved寄存器.
+    fn alloc_frame(&mut self) -> Vec<(Reg, i64)> {
+        self.push(self.reg(Reg::Rbp));
+        self.regs.insert(Reg::Rbp, self.reg(Reg::Rsp));
+        self.regs
+            .insert(Reg::Rsp, self.reg(Reg::Rsp) - Self::FRAME_SIZE);
+        let saved = Reg::callee_saved()
+            .into_iter()
+            .filter(|r| !matches!(r, Reg::Rsp | Reg::Rbp))
+            .map(|r| (r, self.reg(r)))
+            .collect();
+        self.regs
+            .insert(Reg::R15, self.reg(Reg::R15) + Self::ROOT_FRAME_SIZE);
+        saved
+    }
+
+    /// 模拟结尾中除`retq`之外的部分.
+    fn free_frame(&mut self, saved: Vec<(Reg, i64)>) {
+        self.regs.insert(Reg::Rsp, self.reg(Reg::Rbp));
+        let rbp = self.pop();
+        self.regs.insert(Reg::Rbp, rbp);
+        self.regs.extend(saved);
+    }
+
+    /// 从函数返回, 得到调用者继续执行的位置. `main`返回时得到`None`.
+    fn ret(&mut self) -> Option<(Label, usize, Label)> {
+        let frame = self.calls.pop()?;
+        if let Some(saved) = frame.implicit {
+            self.free_frame(saved);
+        }
+        self.pop();
+        self.vars = frame.vars;
+        let rax = self.reg(Reg::Rax);
+        for r in Reg::caller_saved() {
+            self.regs.insert(r, Self::CLOBBERED);
+        }
+        self.regs.insert(Reg::Rax, rax);
+        Some((frame.label, frame.pc, frame.func))
+    }
+
+    fn reg(&self, r: Reg) -> i64 {
+        self.regs.get(&r).copied().unwrap_or(0)
+    }
+
+    fn read(&self, a: &Arg) -> Result<i64> {
+        match a {
+            Arg::Imm(i) => Ok(*i),
+            Arg::Reg(r) => Ok(self.reg(*r)),
+            Arg::Deref(r, offset) => {
+                Ok(self.mem.get(&(self.reg(*r) + offset)).copied().unwrap_or(0))
+            }
+            Arg::Var(v) => self
+                .vars
+                .get(v)
+                .copied()
+                .ok_or_else(|| format!("Undefined variable `{}`", v).into()),
+            Arg::Global(name) => self.global(name),
+            Arg::FunRef(_) | Arg::StrRef(_) => Err(format!("Cannot read from {}", a).into()),
+        }
+    }
+
+    fn function_address(&mut self, func: &str) -> i64 {
+        let i = match self.functions.iter().position(|f| f == func) {
+            Some(i) => i,
+            None => {
+                self.functions.push(func.into());
+                self.functions.len() - 1
+            }
+        };
+        Self::FUNCTION_BASE + 16 * i as i64
+    }
+
+    fn function_at(&self, addr: i64) -> Result<Label> {
+        let i = (addr - Self::FUNCTION_BASE) / 16;
+        match self.functions.get(i as usize) {
+            Some(f) if addr >= Self::FUNCTION_BASE && addr % 16 == 0 => Ok(f.clone()),
+            _ => Err(format!("Jump to an invalid address {:#x}", addr).into()),
+        }
+    }
+
+    fn write(&mut self, a: &Arg, value: i64) -> Result<()> {
+        match a {
+            Arg::Imm(_) | Arg::FunRef(_) | Arg::StrRef(_) => {
+                return Err(format!("Cannot write to {}", a).into())
+            }
+            Arg::Reg(r) => {
+                self.regs.insert(*r, value);
+            }
+            Arg::Deref(r, offset) => {
+                self.mem.insert(self.reg(*r) + offset, value);
+            }
+            Arg::Var(v) => {
+                self.vars.insert(v.clone(), value);
+            }
+            Arg::Global(name) => {
+                self.global(name)?;
+                self.globals.insert(name.clone(), value);
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, value: i64) {
+        let rsp = self.reg(Reg::Rsp) - 8;
+        self.regs.insert(Reg::Rsp, rsp);
+        self.mem.insert(rsp, value);
+    }
+
+    fn pop(&mut self) -> i64 {
+        let rsp = self.reg(Reg::Rsp);
+        self.regs.insert(Reg::Rsp, rsp + 8);
+        self.mem.get(&rsp).copied().unwrap_or(0)
+    }
+
+    fn condition(&self, cc: CondCode) -> bool {
+        let (d, s) = self.flags;
+        match cc {
+            CondCode::O => self.overflow,
+            CondCode::E => d == s,
+            CondCode::Ne => d != s,
+            CondCode::L => d < s,
+            CondCode::Le => d <= s,
+            CondCode::G => d > s,
+            CondCode::Ge => d >= s,
+        }
+    }
+
+    fn step(&mut self, inst: &Instr) -> Result<Step> {
+        use Instr::*;
+        match inst {
+            Retq => return Ok(Step::Return),
+            Jump(l) => return Ok(Step::Jump(l.clone())),
+            JmpIf(cc, l) => {
+                if self.condition(*cc) {
+                    return Ok(Step::Jump(l.clone()));
+                }
+            }
+            Callq(func, arity) => return Ok(Step::Call(func.clone(), *arity)),
+            IndirectCallq(a, arity) => {
+                let func = self.function_at(self.read(a)?)?;
+                return Ok(Step::Call(func, *arity));
+            }
+            TailJmp(a, arity) => {
+                let func = self.function_at(self.read(a)?)?;
+                return Ok(Step::TailCall(func, *arity));
+            }
+            Leaq(s, d) => {
+                let addr = match s {
+                    Arg::FunRef(f) => self.function_address(f),
+                    Arg::StrRef(l) => *self
+                        .rodata
+                        .get(l)
+                        .ok_or_else(|| format!("Undefined label `{}`", l))?,
+                    Arg::Deref(r, offset) => self.reg(*r) + offset,
+                    _ => return Err(format!("Cannot take the address of {}", s).into()),
+                };
+                self.write(d, addr)?;
+            }
+            Pushq(a) => {
+                let v = self.read(a)?;
+                self.push(v);
+            }
+            Popq(a) => {
+                let v = self.pop();
+                self.write(a, v)?;
+            }
+            Negq(a) => {
+                let (v, o) = self.read(a)?.overflowing_neg();
+                self.overflow = o;
+                self.write(a, v)?;
+            }
+            Addq(s, d) => {
+                let (v, o) = self.read(d)?.overflowing_add(self.read(s)?);
+                self.overflow = o;
+                self.write(d, v)?;
+            }
+            Subq(s, d) => {
+                let (v, o) = self.read(d)?.overflowing_sub(self.read(s)?);
+                self.overflow = o;
+                self.write(d, v)?;
+            }
+            Imulq(s, d) => {
+                let (v, o) = self.read(d)?.overflowing_mul(self.read(s)?);
+                self.overflow = o;
+                self.write(d, v)?;
+            }
+            Idivq(a) => {
+                let divisor = self.read(a)?;
+                if divisor == 0 {
+                    return Err("division by zero".into());
+                }
+                // 只支持%rdx是%rax的符号扩展的情况, 即紧跟在cqto之后.
+                let dividend = self.reg(Reg::Rax);
+                if dividend == i64::MIN && divisor == -1 {
+                    return Err("division overflow".into());
+                }
+                self.regs.insert(Reg::Rax, dividend / divisor);
+                self.regs.insert(Reg::Rdx, dividend % divisor);
+            }
+            Cqto => {
+                let rdx = if self.reg(Reg::Rax) < 0 { -1 } else { 0 };
+                self.regs.insert(Reg::Rdx, rdx);
+            }
+            Xorq(s, d) => {
+                let v = self.read(d)? ^ self.read(s)?;
+                self.write(d, v)?;
+            }
+            Salq(s, d) => {
+                let v = self.read(d)?.wrapping_shl(self.read(s)? as u32);
+                self.write(d, v)?;
+            }
+            Sarq(s, d) => {
+                let v = self.read(d)?.wrapping_shr(self.read(s)? as u32);
+                self.write(d, v)?;
+            }
+            Cmpq(s, d) => {
+                self.flags = (self.read(d)?, self.read(s)?);
+                self.overflow = self.flags.0.overflowing_sub(self.flags.1).1;
+            }
+            Set(cc, ByteReg::Al) => {
+                let rax = self.reg(Reg::Rax) & !0xff | self.condition(*cc) as i64;
+                self.regs.insert(Reg::Rax, rax);
+            }
+            Movzbq(ByteReg::Al, d) => self.write(d, self.reg(Reg::Rax) & 0xff)?,
+            Movq(s, d) | Movabsq(s, d) => {
+                let v = self.read(s)?;
+                self.write(d, v)?;
+            }
+        }
+        Ok(Step::Next)
+    }
+
+    /// 调用运行时中的函数. 参数的个数都不超过6个, 只从寄存器中读取.
+    fn call(&mut self, func: &str, arity: usize) -> Result<()> {
+        let args: Vec<i64> = Reg::args_passing()
+            .into_iter()
+            .take(arity)
+            .map(|r| self.reg(r))
+            .collect();
+        let args: Vec<Value> = match func {
+            "print_bool" => args.into_iter().map(|a| Value::Bool(a != 0)).collect(),
+            "print_str" => args
+                .into_iter()
+                .map(|a| self.string_at(a))
+                .collect::<Result<_>>()?,
+            _ => args.into_iter().map(Value::Int).collect(),
+        };
+        let res = match func {
+            "initialize" => {
+                self.initialize(args[1].int()?);
+                Self::CLOBBERED
+            }
+            // 对象都不会被释放, 只需保证有足够的空闲空间.
+            "collect" => {
+                let end = self.global("free_ptr")? + args[1].int()? + Self::HEAP_SIZE;
+                self.globals.insert("fromspace_end".into(), end);
+                Self::CLOBBERED
+            }
+            _ => match self.io.call_builtin(func, &args)? {
+                Value::Int(i) => i,
+                Value::Bool(b) => b as i64,
+                Value::Unit => Self::CLOBBERED,
+                Value::Str(_) | Value::Closure(_) | Value::Tuple(_) | Value::List(_) => {
+                    unreachable!("Built-in functions only return integers and booleans.")
+                }
+            },
+        };
+
+        for r in Reg::caller_saved() {
+            self.regs.insert(r, Self::CLOBBERED);
+        }
+        self.regs.insert(Reg::Rax, res);
+        Ok(())
+    }
+}
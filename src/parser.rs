@@ -1,17 +1,28 @@
-use std::{error::Error, thread::current};
+use std::error::Error;
 
 use crate::{
-    ast::{Expr, Range, Stmt},
+    ast::{Expr, ExprData, Range, Stmt, TypeExpr},
     reporter::ErrorReporter,
     token::{self, Kind, Token},
 };
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
+/// 复合赋值符号.
+const AUG_ASSIGN: [Kind; 5] = [
+    Kind::PlusEqual,
+    Kind::MinusEqual,
+    Kind::StarEqual,
+    Kind::SlashEqual,
+    Kind::PercentEqual,
+];
+
 pub struct Parser<'r> {
     reporter: &'r ErrorReporter,
     tokens: Vec<Token>,
     current: usize,
+    /// 已经报告过的语法错误. 出错的语句被跳过, 继续解析之后的语句.
+    errors: Vec<Box<dyn Error>>,
 }
 
 /// public
@@ -21,38 +32,107 @@ impl<'r> Parser<'r> {
             reporter,
             tokens,
             current: 0,
+            errors: vec![],
         }
     }
 
-    pub fn stmts(&mut self) -> Result<Vec<Stmt>> {
+    /// 解析整个程序. 所有的语法错误都报告之后才返回.
+    pub fn program(mut self) -> Result<Vec<Stmt>> {
+        let mut stmts = vec![];
+        loop {
+            stmts.extend(self.stmts());
+            match self.peek() {
+                // 多余的`}`.
+                Some(tk) if tk.kind() != Kind::Eof => {
+                    let msg = format!("Unexpected `{}`", tk.lexeme());
+                    let e = self.reporter.error_token(&msg, &tk).unwrap_err();
+                    self.errors.push(e);
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        self.reporter.collect(self.errors)?;
+        Ok(stmts)
+    }
+
+    /// 语句序列, 直到块的结尾或文件结束. 出错时记录错误, 跳过这条语句.
+    fn stmts(&mut self) -> Vec<Stmt> {
         let mut stmts = vec![];
         loop {
             if self.match_any(vec![Kind::NewLine]) {
                 continue;
             }
-            if let Some(tk) = self.peek() {
-                match tk.kind() {
-                    Kind::Eof | Kind::RightBrace => {
-                        break;
-                    }
-                    _ => {
-                        let st = self.stmt()?;
-                        stmts.push(st);
+            match self.peek().map(|tk| tk.kind()) {
+                None | Some(Kind::Eof | Kind::RightBrace | Kind::Dedent) => break,
+                Some(_) => match self.stmt() {
+                    Ok(st) => stmts.push(st),
+                    Err(e) => {
+                        self.errors.push(e);
+                        self.synchronize();
                     }
+                },
+            }
+        }
+        stmts
+    }
+
+    /// 出错之后跳过当前语句余下的部分: 到行尾的`NewLine`(包括)或所在块结尾的`}`(不包括)为止.
+    /// 其中完整的块整个跳过.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(tk) = self.peek() {
+            match tk.kind() {
+                Kind::Eof => return,
+                Kind::NewLine if depth == 0 => {
+                    self.advance();
+                    return;
                 }
+                Kind::RightBrace | Kind::Dedent if depth == 0 => return,
+                Kind::LeftBrace | Kind::Indent => depth += 1,
+                Kind::RightBrace | Kind::Dedent => depth -= 1,
+                _ => (),
             }
+            self.advance();
         }
-        Ok(stmts)
     }
     fn stmt(&mut self) -> Result<Stmt> {
         if self.is_match_all(vec![Kind::Name, Kind::Equal]) {
             self.assignment()
+        } else if self.is_match_all(vec![Kind::Name, Kind::Colon]) {
+            self.ann_assignment()
+        } else if self.is_match_all(vec![Kind::Name, Kind::Comma]) {
+            self.multi_assignment()
+        } else if AUG_ASSIGN
+            .iter()
+            .any(|k| self.is_match_all(vec![Kind::Name, *k]))
+        {
+            self.aug_assignment()
         // } else if self.is_match_all(vec![Kind::Name , Kind::LeftParen])  {
         //     self.print_stmt()
         } else if self.match_any(vec![Kind::If]) {
             self.if_stmt()
+        } else if self.match_any(vec![Kind::While]) {
+            self.while_stmt()
+        } else if self.match_any(vec![Kind::For]) {
+            self.for_stmt()
+        } else if self.match_any(vec![Kind::Func]) {
+            self.def()
+        } else if self.match_any(vec![Kind::Return]) {
+            self.return_stmt()
         } else {
-            let e = self.exp()?;
+            let mut e = self.exp()?;
+            // a[i] = v
+            if self.is_match(Kind::Equal) {
+                if let ExprData::Subscript { value, index } = e.data {
+                    self.advance();
+                    e = Expr::list_set()
+                        .list(*value)
+                        .index(*index)
+                        .value(self.exp()?)
+                        .build();
+                }
+            }
             let st = Stmt::expr().expr(e).build();
             Ok(st)
         }
@@ -77,15 +157,32 @@ impl<'r> Parser<'r> {
     // 	Ok(ifstmt)
     // }
 
+    /// `elif`是`else`中只有一个`if`语句的简写. 缩进模式中可以省略`else`, 此时是空的块.
     fn if_stmt(&mut self) -> Result<Stmt> {
         let cond = self.exp()?;
-        self.expect(Kind::LeftBrace, "Expected `{`")?;
+        let layout = self.open_block()?.kind() == Kind::Colon;
         let then = self.block()?;
 
-        self.expect(Kind::Else, "Expected `else` branch")?;
-
-        self.expect(Kind::LeftBrace, "Expected `{`")?;
-        let els = self.block()?;
+        // 单行的块之后有换行.
+        if layout && self.is_match(Kind::NewLine) {
+            let next = self.tokens.get(self.current + 1).map(|tk| tk.kind());
+            if matches!(next, Some(Kind::Elif | Kind::Else)) {
+                self.advance();
+            }
+        }
+        let els = if self.match_any(vec![Kind::Elif]) {
+            let elif = self.if_stmt()?;
+            let range = elif.range;
+            let body = vec![elif];
+            Expr::new(ExprData::Block { body, result: None }, range)
+        } else if layout && !self.is_match(Kind::Else) {
+            let body = vec![];
+            Expr::new(ExprData::Block { body, result: None }, then.range)
+        } else {
+            self.expect(Kind::Else, "Expected `else` branch")?;
+            self.open_block()?;
+            self.block()?
+        };
 
         Ok(Stmt::if_stmt()
             .condition(cond)
@@ -94,6 +191,132 @@ impl<'r> Parser<'r> {
             .build())
     }
 
+    fn while_stmt(&mut self) -> Result<Stmt> {
+        let cond = self.exp()?;
+        self.open_block()?;
+        let body = self.block()?;
+        Ok(Stmt::while_stmt().condition(cond).body(body).build())
+    }
+
+    /// for i in range([start, ]stop[, step]) { ... }
+    fn for_stmt(&mut self) -> Result<Stmt> {
+        let var = self.expect(Kind::Name, "Expected a loop variable")?;
+        self.expect(Kind::In, "Expected `in`")?;
+        let range = self.expect(Kind::Name, "Expected `range`")?;
+        if range.lexeme() != "range" {
+            return Err(self
+                .reporter
+                .error_token("Expected `range`", &range)
+                .unwrap_err());
+        }
+        self.expect(Kind::LeftParen, "Expected `(`")?;
+        let mut args = self.arguments()?.into_iter();
+        self.expect(Kind::RightParen, "Expected `)`")?;
+
+        let mut builder = Stmt::for_stmt().var(var);
+        builder = match (args.next(), args.next(), args.next(), args.next()) {
+            (Some(stop), None, None, None) => {
+                let zero = Expr::new(crate::ast::ExprData::Int(0), range.range());
+                builder.start(zero).stop(stop)
+            }
+            (Some(start), Some(stop), None, None) => builder.start(start).stop(stop),
+            (Some(start), Some(stop), Some(step), None) => {
+                builder.start(start).stop(stop).step(step)
+            }
+            _ => {
+                return Err(self
+                    .reporter
+                    .error_token("Expected 1 to 3 arguments to `range`", &range)
+                    .unwrap_err())
+            }
+        };
+
+        self.open_block()?;
+        let body = self.block()?;
+        Ok(builder.body(body).build())
+    }
+
+    /// def f(x: int, y: bool) -> int { ... }
+    fn def(&mut self) -> Result<Stmt> {
+        let name = self.expect(Kind::Name, "Expected a function name")?;
+        let mut builder = Stmt::def().name(name);
+        self.expect(Kind::LeftParen, "Expected `(`")?;
+        if !self.is_match(Kind::RightParen) {
+            loop {
+                let param = self.expect(Kind::Name, "Expected a parameter name")?;
+                self.expect(Kind::Colon, "Expected `:`")?;
+                let ty = self.type_expr()?;
+                builder = builder.param(param, ty);
+                if !self.match_any(vec![Kind::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.expect(Kind::RightParen, "Expected `)`")?;
+        if self.match_any(vec![Kind::Arrow]) {
+            builder = builder.ret(self.type_expr()?);
+        }
+        self.open_block()?;
+        Ok(builder.body(self.block()?).build())
+    }
+
+    /// type = name | name "[" type {"," type} "]"
+    ///      | "Callable" "[" "[" [type {"," type}] "]" "," type "]"
+    fn type_expr(&mut self) -> Result<TypeExpr> {
+        let name = self.expect(Kind::Name, "Expected a type")?;
+        if name.lexeme() != "Callable" {
+            if !self.match_any(vec![Kind::LeftBracket]) {
+                return Ok(TypeExpr::Name(name));
+            }
+            let mut args = vec![];
+            loop {
+                args.push(self.type_expr()?);
+                if !self.match_any(vec![Kind::Comma]) {
+                    break;
+                }
+            }
+            let close = self.expect(Kind::RightBracket, "Expected `]`")?;
+            return Ok(TypeExpr::Generic {
+                range: (name.range().0, close.range().1),
+                name,
+                args,
+            });
+        }
+        self.expect(Kind::LeftBracket, "Expected `[`")?;
+        self.expect(Kind::LeftBracket, "Expected `[` before the parameter types")?;
+        let mut params = vec![];
+        if !self.is_match(Kind::RightBracket) {
+            loop {
+                params.push(self.type_expr()?);
+                if !self.match_any(vec![Kind::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.expect(Kind::RightBracket, "Expected `]`")?;
+        self.expect(Kind::Comma, "Expected `,`")?;
+        let ret = self.type_expr()?;
+        let close = self.expect(Kind::RightBracket, "Expected `]`")?;
+        Ok(TypeExpr::Callable {
+            range: (name.range().0, close.range().1),
+            params,
+            ret: ret.into(),
+        })
+    }
+
+    /// return [expression]
+    fn return_stmt(&mut self) -> Result<Stmt> {
+        let builder = Stmt::return_stmt().keyword(self.previous().unwrap());
+        if self.is_match(Kind::NewLine)
+            || self.is_match(Kind::RightBrace)
+            || self.is_match(Kind::Dedent)
+            || self.is_match(Kind::Eof)
+        {
+            return Ok(builder.build());
+        }
+        Ok(builder.value(self.exp()?).build())
+    }
+
     fn assignment(&mut self) -> Result<Stmt> {
         let name = self
             .match_all(vec![Kind::Name, Kind::Equal])
@@ -104,33 +327,135 @@ impl<'r> Parser<'r> {
         Ok(st)
     }
 
-    fn print_stmt(&mut self) -> Result<Stmt> {
-        todo!()
+    /// x: type = e
+    fn ann_assignment(&mut self) -> Result<Stmt> {
+        let name = self.advance().unwrap();
+        self.advance();
+        let ty = self.type_expr()?;
+        self.expect(Kind::Equal, "Expected `=`")?;
+        let binding = self.exp()?;
+        Ok(Stmt::ann_assignment()
+            .name(name)
+            .ty(ty)
+            .binding(binding)
+            .build())
+    }
+
+    /// a, b = e1, e2
+    fn multi_assignment(&mut self) -> Result<Stmt> {
+        let mut builder = Stmt::multi_assignment();
+        loop {
+            builder = builder.name(self.expect(Kind::Name, "Expected a name")?);
+            if !self.match_any(vec![Kind::Comma]) {
+                break;
+            }
+        }
+        self.expect(Kind::Equal, "Expected `=`")?;
+        loop {
+            builder = builder.binding(self.exp()?);
+            if !self.match_any(vec![Kind::Comma]) {
+                break;
+            }
+        }
+        Ok(builder.build())
+    }
+
+    /// x += e
+    fn aug_assignment(&mut self) -> Result<Stmt> {
+        let name = self.advance().unwrap();
+        let op = self.advance().unwrap();
+        let binding = self.exp()?;
+        Ok(Stmt::aug_assignment()
+            .name(name)
+            .op(op)
+            .binding(binding)
+            .build())
     }
 
     /// expression:
     pub fn exp(&mut self) -> Result<Expr> {
         if self.match_any(vec![Kind::LeftBrace]) {
             self.block()
+        } else if self.match_any(vec![Kind::Lambda]) {
+            self.lambda()
         } else {
             self.condition()
         }
     }
 
+    /// lambda = "lambda" [name {"," name}] ":" expression
+    fn lambda(&mut self) -> Result<Expr> {
+        let mut builder = Expr::lambda().keyword(self.previous().unwrap());
+        if !self.is_match(Kind::Colon) {
+            loop {
+                builder = builder.param(self.expect(Kind::Name, "Expected a parameter name")?);
+                if !self.match_any(vec![Kind::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.expect(Kind::Colon, "Expected `:`")?;
+        Ok(builder.body(self.exp()?).build())
+    }
+
+    /// 语句块的开头: `{`, 或者缩进模式中的`:`.
+    fn open_block(&mut self) -> Result<Token> {
+        if self.match_any(vec![Kind::Colon]) {
+            return Ok(self.previous().unwrap());
+        }
+        self.expect(Kind::LeftBrace, "Expected `{` or `:`")
+    }
+
+    /// `{`之后的语句直到`}`; 或者`:`之后换行, 缩进的语句直到缩进减少;
+    /// 或者`:`之后同一行中的一条简单语句.
     pub fn block(&mut self) -> Result<Expr> {
         let open = self.previous().unwrap();
-        while self.match_any(vec![Kind::NewLine]) {}
-        let mut sts = self.stmts()?;
-        while self.match_any(vec![Kind::NewLine]) {}
-        let close = self.expect(Kind::RightBrace, "Expected `}`")?;
-        let res = sts.pop();
+        let layout = open.kind() == Kind::Colon;
+        let (mut sts, range) = if layout && !self.is_match(Kind::NewLine) {
+            // 同一行中`:`之后的一条简单语句.
+            if let Some(tk) = self
+                .peek()
+                .filter(|tk| matches!(tk.kind(), Kind::If | Kind::While | Kind::For | Kind::Func))
+            {
+                let msg = "Expected a simple statement after `:`";
+                return Err(self.reporter.error_token(msg, &tk).unwrap_err());
+            }
+            let st = self.stmt()?;
+            let range = (open.range().0, st.range.1);
+            (vec![st], range)
+        } else {
+            if layout {
+                self.expect(Kind::NewLine, "Expected a newline after `:`")?;
+                while self.match_any(vec![Kind::NewLine]) {}
+                self.expect(Kind::Indent, "Expected an indented block")?;
+            }
+            while self.match_any(vec![Kind::NewLine]) {}
+            let sts = self.stmts();
+            while self.match_any(vec![Kind::NewLine]) {}
+            let end = self.previous().unwrap().range().1;
+            // 缩进的块在最后一条语句处结束, 而不是下一行的开头.
+            let range = if layout {
+                self.expect(Kind::Dedent, "Expected a dedent")?;
+                (open.range().0, end)
+            } else {
+                let close = self.expect(Kind::RightBrace, "Expected `}`")?;
+                (open.range().0, close.range().1)
+            };
+            (sts, range)
+        };
+        // 最后一个表达式语句作为块的值, 其他语句留在块中.
+        let result: Option<Box<Expr>> = match sts.pop() {
+            Some(Stmt {
+                stmt: crate::ast::StmtData::Expr(e),
+                ..
+            }) => Some(e.into()),
+            Some(s) => {
+                sts.push(s);
+                None
+            }
+            None => None,
+        };
         let body = sts;
-
-        let result: Option<Box<Expr>> = res.and_then(|s| match s.stmt {
-            crate::ast::StmtData::Expr(e) => Some(e.into()),
-            _ => None,
-        });
-        let range = (open.range().0, close.range().1);
         Ok(Expr {
             data: crate::ast::ExprData::Block { body, result },
             range,
@@ -195,7 +520,7 @@ impl<'r> Parser<'r> {
 
     fn factor(&mut self) -> Result<Expr> {
         let mut e1 = self.unary()?;
-        while self.match_any(vec![Kind::Star, Kind::Slash]) {
+        while self.match_any(vec![Kind::Star, Kind::Slash, Kind::Percent]) {
             let op = self.previous().unwrap();
             let e2 = self.unary()?;
             e1 = Expr::binary().left(e1).op(op).right(e2).build();
@@ -207,21 +532,45 @@ impl<'r> Parser<'r> {
     fn unary(&mut self) -> Result<Expr> {
         if self.match_any(vec![Kind::Minus, Kind::Bang]) {
             let op = self.previous().unwrap();
+            // `-9223372036854775808`: 字面量本身超出了`i64`的范围, 直接得到取负后的值.
+            if let Some(tk) = self.peek().filter(|tk| {
+                op.kind() == Kind::Minus
+                    && tk.kind() == Kind::Integer
+                    && i64::try_from(tk.clone()).is_err()
+            }) {
+                if let Some(i) = tk.negated_int() {
+                    self.advance();
+                    let range = (op.range().0, tk.range().1);
+                    return Ok(Expr::new(ExprData::Int(i), range));
+                }
+            }
             let expr = self.function_call()?;
             let unary = Expr::unary().op(op).operand(expr).build();
             return Ok(unary);
         }
-        Ok(self.function_call()?)
+        self.function_call()
     }
 
-    // call   → primary ( "(" arguments? ")" )* ;
+    // call   → primary ( "(" arguments? ")" | "[" expression "]" )* ;
     fn function_call(&mut self) -> Result<Expr> {
         let mut callee = self.primary()?;
 
-        while self.match_any(vec![Kind::LeftParen]) {
-            let args = self.arguments()?;
-            self.expect(Kind::RightParen, "Expected `)`")?;
-            callee = Expr::call().func(callee).args(args).build();
+        loop {
+            if self.match_any(vec![Kind::LeftParen]) {
+                let args = self.arguments()?;
+                self.expect(Kind::RightParen, "Expected `)`")?;
+                callee = Expr::call().func(callee).args(args).build();
+            } else if self.match_any(vec![Kind::LeftBracket]) {
+                let index = self.exp()?;
+                let close = self.expect(Kind::RightBracket, "Expected `]`")?;
+                callee = Expr::subscript()
+                    .value(callee)
+                    .index(index)
+                    .bracket(close)
+                    .build();
+            } else {
+                break;
+            }
         }
 
         Ok(callee)
@@ -253,25 +602,61 @@ impl<'r> Parser<'r> {
     fn primary(&mut self) -> Result<Expr> {
         if let Some(tk) = self.peek() {
             match tk.kind() {
-                Kind::Integer | Kind::Float | Kind::Name | Kind::True | Kind::False => {
+                // 二元运算符`-`之后的2^63.
+                Kind::Integer if i64::try_from(tk.clone()).is_err() => {
+                    let msg = "Integer literal is out of range for a 64-bit integer";
+                    Err(self.reporter.error_token(msg, &tk).unwrap_err())
+                }
+                Kind::Integer | Kind::Float | Kind::Str | Kind::Name | Kind::True | Kind::False => {
                     self.advance();
-                    return Ok(Expr::atom(tk));
+                    Ok(Expr::atom(tk))
                 }
+                // 有逗号时是元组: `(a,)`, `(a, b)`.
                 Kind::LeftParen => {
                     self.advance();
                     let r = self.exp()?;
-                    self.expect(Kind::RightParen, "Expected `)`.")?;
-                    return Ok(r);
+                    if !self.is_match(Kind::Comma) {
+                        self.expect(Kind::RightParen, "Expected `)`.")?;
+                        return Ok(r);
+                    }
+                    let mut builder = Expr::tuple().paren(tk).elem(r);
+                    while self.match_any(vec![Kind::Comma]) {
+                        if self.is_match(Kind::RightParen) {
+                            break;
+                        }
+                        builder = builder.elem(self.exp()?);
+                    }
+                    let close = self.expect(Kind::RightParen, "Expected `)`.")?;
+                    Ok(builder.paren(close).build())
+                }
+                // `[a, b]`, 可以有结尾的逗号.
+                Kind::LeftBracket => {
+                    self.advance();
+                    let mut builder = Expr::list().bracket(tk);
+                    while !self.is_match(Kind::RightBracket) {
+                        builder = builder.elem(self.exp()?);
+                        if !self.match_any(vec![Kind::Comma]) {
+                            break;
+                        }
+                    }
+                    let close = self.expect(Kind::RightBracket, "Expected `]`.")?;
+                    Ok(builder.bracket(close).build())
                 }
+                Kind::Eof => Err(self
+                    .reporter
+                    .error_token("Unexpected EOF.", &tk)
+                    .unwrap_err()),
                 _ => Err(self
                     .reporter
                     .error_token("Unexpected Token.", &tk)
                     .unwrap_err()),
             }
         } else {
-            self.reporter
-                .error_token("Unexpected EOF.", &self.previous().unwrap())?;
-            panic!("Unexpected EOF.");
+            let eof = self.tokens.last().expect("Tokens end with `Eof`.");
+            Err(self
+                .reporter
+                .error_token("Unexpected EOF.", eof)
+                .unwrap_err())
         }
     }
 }
@@ -344,6 +729,6 @@ impl<'r> Parser<'r> {
     }
 
     fn is_match(&self, kind: token::Kind) -> bool {
-        self.peek().map_or(false, |tk| tk.kind() == kind)
+        self.peek().is_some_and(|tk| tk.kind() == kind)
     }
 }
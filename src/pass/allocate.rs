@@ -9,10 +9,7 @@ use super::{
     x86::{Arg, Reg},
 };
 
-use petgraph::{
-    graph::NodeIndex,
-    visit::{IntoNeighbors, IntoNodeIdentifiers},
-};
+use petgraph::graph::NodeIndex;
 
 #[derive(Clone, Debug)]
 struct Saturation {
@@ -55,31 +52,43 @@ impl PartialOrd for Saturation {
     }
 }
 
-pub struct Allocation {
+pub struct Allocation<'p> {
     color2loc: HashMap<i32, Arg>,
+    /// 溢出的指针放在根栈中, 与其他溢出的变量分开着色.
+    root_color2loc: HashMap<i32, Arg>,
+    pointers: &'p HashSet<String>,
     graph: InterferenceGraph,
     move_rels: HashMap<NodeIndex, HashSet<NodeIndex>>,
     coloring: HashMap<NodeIndex, i32>,
     worklist: BinaryHeap<Saturation>,
 }
 
-impl Allocation {
+impl<'p> Allocation<'p> {
+    /// `pointers`是值为指向堆的指针的变量, 见`Program::pointers`.
     pub fn new(
         graph: InterferenceGraph,
         move_graph: HashMap<NodeIndex, HashSet<NodeIndex>>,
+        pointers: &'p HashSet<String>,
     ) -> Self {
         use Reg::*;
         // -1: rax, -2: rsp, -3: rbp, -4: r11, -5: r15
-        let not_used = vec![
+        // 暂不参与分配的寄存器也要预先着色, 否则(例如传参用的%rdi)会被当作变量分配到别处.
+        let not_used = [
             Arg::Reg(Rax),
             Arg::Reg(Rsp),
             Arg::Reg(Rbp),
             Arg::Reg(R11),
             Arg::Reg(R15),
+            Arg::Reg(Rdx),
+            Arg::Reg(Rsi),
+            Arg::Reg(Rdi),
+            Arg::Reg(R8),
+            Arg::Reg(R9),
+            Arg::Reg(R10),
         ];
-        // 0: rcx, 1: rdx, 2: rsi, 3: rdi, 4: r8, 5: r9,
-        // 6: r10, 7: rbx, 8: r12, 9: r13, 10: r14
-        let used = vec![
+        // 0: rcx, 1: rbx, 2: r12, 3: r13, 4: r14
+        // 函数调用前后都活跃的变量只能放在callee-saved寄存器中.
+        let used = [
             Arg::Reg(Rcx),
             // Arg::Reg(Rdx),
             // Arg::Reg(Rsi),
@@ -88,10 +97,10 @@ impl Allocation {
             // Arg::Reg(R9),
 
             // Arg::Reg(R10),
-            // Arg::Reg(Rbx),
-            // Arg::Reg(R12),
-            // Arg::Reg(R13),
-            // Arg::Reg(R14),
+            Arg::Reg(Rbx),
+            Arg::Reg(R12),
+            Arg::Reg(R13),
+            Arg::Reg(R14),
         ];
         let mut reg_color = HashMap::new();
         for (a, i) in not_used.iter().zip(1..) {
@@ -121,6 +130,8 @@ impl Allocation {
 
         let mut res = Self {
             color2loc: color2reg,
+            root_color2loc: HashMap::new(),
+            pointers,
             graph,
             move_rels: move_graph,
             coloring: coloring.clone(),
@@ -136,7 +147,7 @@ impl Allocation {
     fn update_saturation(&mut self, node: NodeIndex, color: i32) {
         let neighbors: HashSet<NodeIndex> = HashSet::from_iter(self.graph.neighbors(node));
 
-        let worklist = std::mem::replace(&mut self.worklist, BinaryHeap::new());
+        let worklist = std::mem::take(&mut self.worklist);
         let mut heap = BinaryHeap::new();
         for mut n in worklist.into_iter() {
             if neighbors.contains(&n.node) {
@@ -187,11 +198,11 @@ impl Allocation {
             if let Some(c) = self.coloring.get(&i) {
                 if let Some(Arg::Reg(r)) = self.color2loc.get(c) {
                     // 可分配到寄存器的变量
-                    if r.is_callee_saved() {
+                    if r.is_callee_saved() && v.get_var().is_some() {
                         // 是否为callee-saved寄存器
-                        used_callee.insert(r.clone());
+                        used_callee.insert(*r);
                     }
-                    mapping.insert(v.clone(), Arg::Reg(r.clone()));
+                    mapping.insert(v.clone(), Arg::Reg(*r));
                 } else {
                     // 溢出的变量
                     spilled.insert(v.clone(), *c);
@@ -203,11 +214,21 @@ impl Allocation {
         let mut frame = Frame::new(used_callee);
 
         for (t, c) in spilled {
-            let loc = if let Some(loc) = self.color2loc.get(&c) {
+            let is_pointer = t.get_var().is_some_and(|v| self.pointers.contains(v));
+            let color2loc = if is_pointer {
+                &mut self.root_color2loc
+            } else {
+                &mut self.color2loc
+            };
+            let loc = if let Some(loc) = color2loc.get(&c) {
                 loc.clone()
             } else {
-                let loc = frame.alloc_local(8);
-                self.color2loc.insert(c, loc.clone());
+                let loc = if is_pointer {
+                    frame.alloc_root()
+                } else {
+                    frame.alloc_local(8)
+                };
+                color2loc.insert(c, loc.clone());
                 loc
             };
             mapping.insert(t, loc);
@@ -1,34 +1,50 @@
 use std::{collections::HashMap, mem};
 
-use super::x86::{Arg, Instr};
+use super::x86::{Arg, Instr, Program};
 
 pub struct AssignHomes {
-    instrs: Vec<Instr>,
+    prog: Program,
     mapping: HashMap<Arg, Arg>,
 }
 
 impl AssignHomes {
-    pub fn new(instrs: Vec<Instr>, mapping: HashMap<Arg, Arg>) -> Self {
-        Self { instrs, mapping }
+    pub fn new(prog: Program, mapping: HashMap<Arg, Arg>) -> Self {
+        Self { prog, mapping }
     }
 
     fn replace_arg(&self, a: Arg) -> Arg {
         self.mapping.get(&a).map_or(a, |loc| loc.clone())
     }
-    pub fn assign_homes(mut self) -> Vec<Instr> {
+    pub fn assign_homes(mut self) -> Program {
+        let prog = mem::take(&mut self.prog);
+        prog.map_blocks(|instrs| {
+            instrs
+                .into_iter()
+                .map(|inst| self.assign_instr(inst))
+                .collect()
+        })
+    }
+
+    fn assign_instr(&self, inst: Instr) -> Instr {
         use Instr::*;
-        let instrs = mem::replace(&mut self.instrs, vec![]);
-        instrs
-            .into_iter()
-            .map(|inst| match inst {
-                Pushq(a) => Pushq(self.replace_arg(a)),
-                Popq(a) => Popq(self.replace_arg(a)),
-                Addq(s, d) => Addq(self.replace_arg(s), self.replace_arg(d)),
-                Subq(s, d) => Subq(self.replace_arg(s), self.replace_arg(d)),
-                Negq(a) => Negq(self.replace_arg(a)),
-                Movq(s, d) => Movq(self.replace_arg(s), self.replace_arg(d)),
-                o => o,
-            })
-            .collect()
+        match inst {
+            Pushq(a) => Pushq(self.replace_arg(a)),
+            Popq(a) => Popq(self.replace_arg(a)),
+            Addq(s, d) => Addq(self.replace_arg(s), self.replace_arg(d)),
+            Subq(s, d) => Subq(self.replace_arg(s), self.replace_arg(d)),
+            Negq(a) => Negq(self.replace_arg(a)),
+            Movq(s, d) => Movq(self.replace_arg(s), self.replace_arg(d)),
+            Imulq(s, d) => Imulq(self.replace_arg(s), self.replace_arg(d)),
+            Idivq(a) => Idivq(self.replace_arg(a)),
+            Xorq(s, d) => Xorq(self.replace_arg(s), self.replace_arg(d)),
+            Salq(s, d) => Salq(s, self.replace_arg(d)),
+            Sarq(s, d) => Sarq(s, self.replace_arg(d)),
+            Cmpq(s, d) => Cmpq(self.replace_arg(s), self.replace_arg(d)),
+            Movzbq(s, d) => Movzbq(s, self.replace_arg(d)),
+            Leaq(s, d) => Leaq(s, self.replace_arg(d)),
+            TailJmp(a, n) => TailJmp(self.replace_arg(a), n),
+            IndirectCallq(a, n) => IndirectCallq(self.replace_arg(a), n),
+            o => o,
+        }
     }
 }
@@ -1,32 +1,30 @@
 use std::collections::{HashMap, HashSet};
 
-use petgraph::{
-    dot::{Config, Dot},
-    Graph,
-};
-
 use crate::pass::x86::ReadWriteSet;
 
 use super::{
-    liveness::LiveAfter,
-    x86::{Arg, Instr},
+    liveness::LiveBlocks,
+    x86::{Arg, Instr, Reg},
 };
 use petgraph::graph::{EdgeIndex, NodeIndex};
 
 pub type InterferenceGraph = petgraph::graph::UnGraph<Arg, ()>;
 pub type MoveGraph = HashMap<NodeIndex, HashSet<NodeIndex>>;
-pub struct BuildInterference {
+pub struct BuildInterference<'p> {
     graph: InterferenceGraph,
     move_rels: MoveGraph,
     nodes: HashMap<Arg, petgraph::graph::NodeIndex>,
+    /// 值是指向堆的指针的变量, 见`Program::pointers`.
+    pointers: &'p HashSet<String>,
 }
 
-impl BuildInterference {
-    pub fn new() -> Self {
+impl<'p> BuildInterference<'p> {
+    pub fn new(pointers: &'p HashSet<String>) -> Self {
         Self {
             graph: petgraph::Graph::new_undirected(),
             move_rels: HashMap::new(),
             nodes: HashMap::new(),
+            pointers,
         }
     }
 
@@ -46,10 +44,10 @@ impl BuildInterference {
         let si = self.add_location(a);
         let di = self.add_location(b);
         if let Some(s) = self.move_rels.get_mut(&si) {
-            s.insert(di.clone());
+            s.insert(di);
         } else {
             let mut set = HashSet::new();
-            set.insert(di.clone());
+            set.insert(di);
             self.move_rels.insert(si, set);
         }
     }
@@ -57,7 +55,7 @@ impl BuildInterference {
     fn add_edge(&mut self, a: NodeIndex, b: NodeIndex) -> EdgeIndex {
         self.graph
             .find_edge(a, b)
-            .map_or_else(|| self.graph.add_edge(a, b, ()), |e| e)
+            .unwrap_or_else(|| self.graph.add_edge(a, b, ()))
     }
 
     fn interfere_with(&mut self, a: &Arg, b: &Arg) {
@@ -66,12 +64,17 @@ impl BuildInterference {
         self.add_edge(a, b);
     }
 
-    pub fn build_graph(
-        mut self,
-        instrs: Vec<(Instr, LiveAfter)>,
-    ) -> (InterferenceGraph, MoveGraph) {
-        for (inst, liveafter) in &instrs {
+    pub fn build_graph(mut self, blocks: &LiveBlocks) -> (InterferenceGraph, MoveGraph) {
+        for (inst, liveafter) in blocks.values().flatten() {
+            // 与其他位置都不冲突的变量也要分配位置.
+            for loc in inst.read_set().iter().chain(&inst.write_set()) {
+                if loc.get_var().is_some() {
+                    self.add_location(loc);
+                }
+            }
             match inst {
+                // 写入内存的指令不影响寄存器分配.
+                Instr::Movq(_, d) if d.get_location().is_none() => (),
                 Instr::Movq(s, d) => {
                     self.add_move_rel(s, d);
                     for loc in liveafter.iter().filter(|&l| l != d) {
@@ -86,14 +89,24 @@ impl BuildInterference {
                             self.interfere_with(w, loc);
                         }
                     }
+                    // 被调用的函数可能进行垃圾回收, 移动堆上的对象并更新根栈中的指针.
+                    // 所以调用前后都活跃的指针不能放在callee-saved寄存器中, 只能溢出到根栈.
+                    if let Instr::Callq(..) | Instr::IndirectCallq(..) = i {
+                        let pointers: Vec<Arg> = liveafter
+                            .iter()
+                            .filter(|l| l.get_var().is_some_and(|v| self.pointers.contains(v)))
+                            .cloned()
+                            .collect();
+                        for p in &pointers {
+                            for r in Reg::callee_saved() {
+                                self.interfere_with(p, &Arg::Reg(r));
+                            }
+                        }
+                    }
                 }
             }
         }
 
-        println!(
-            "{:?}",
-            Dot::with_config(&self.graph, &[Config::EdgeNoLabel])
-        );
         (self.graph, self.move_rels)
     }
 }
@@ -2,18 +2,139 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
 
-use crate::token::{Kind, Token};
-use crate::{ast, token};
+use crate::ast;
+use crate::token::{self, Kind};
+use crate::type_checking::Type;
 
 pub type Label = String;
 
+/// 由函数组成的程序, 第一个函数是由顶层语句组成的`main`.
 #[derive(Clone)]
 pub struct CProgrom {
-    blocks: HashMap<Label, BlockData>,
+    pub defs: Vec<FunDef>,
 }
 
+impl CProgrom {
+    /// 函数体的第一个块.
+    pub const ENTRY: &'static str = "start";
+    /// 顶层语句组成的函数.
+    pub const MAIN: &'static str = "main";
+
+    pub fn new(defs: Vec<FunDef>) -> Self {
+        Self { defs }
+    }
+
+    /// 每个函数的类型.
+    pub fn signatures(&self) -> HashMap<Label, Type> {
+        self.defs
+            .iter()
+            .map(|def| {
+                let ty = Type::Func {
+                    params: def.params.iter().map(|(_, ty)| ty.clone()).collect(),
+                    ret: def.ret.clone().into(),
+                };
+                (def.name.clone(), ty)
+            })
+            .collect()
+    }
+
+    /// 函数`func`中名为`block`的块的标签. 汇编中所有的标签都是全局的, 所以块名都以函数名和`.`为前缀,
+    /// 这样既不会与其他函数的块重名, 也不会与函数名重名. 只有`main`的入口块仍叫`start`.
+    pub fn label(func: &str, block: &str) -> Label {
+        if func == Self::MAIN && block == Self::ENTRY {
+            block.into()
+        } else {
+            format!("{}.{}", func, block)
+        }
+    }
+}
+
+/// 函数定义. 每个块都以跳转或返回结束.
 #[derive(Clone)]
-pub struct BlockData(Vec<Stmt>);
+pub struct FunDef {
+    pub name: Label,
+    pub params: Vec<(String, Type)>,
+    /// 没有返回值时是`Type::Unit`.
+    pub ret: Type,
+    pub blocks: HashMap<Label, BlockData>,
+}
+
+impl FunDef {
+    pub fn entry(&self) -> Label {
+        CProgrom::label(&self.name, CProgrom::ENTRY)
+    }
+
+    /// 由参数和函数的类型(`sigs`, 见`CProgrom::signatures`)推出每个变量的类型.
+    /// 同一个变量的类型不会在指针与非指针之间变化(见`TypeChecker::bind`),
+    /// 所以只需反复遍历所有赋值, 直到推不出新的变量.
+    pub fn var_types(&self, sigs: &HashMap<Label, Type>) -> HashMap<String, Type> {
+        let mut types: HashMap<String, Type> = self.params.iter().cloned().collect();
+        loop {
+            let mut changed = false;
+            for s in self.blocks.values().flat_map(|b| &b.0) {
+                if let Stmt::Assign {
+                    name: Atom::Name(n),
+                    binding,
+                } = s
+                {
+                    if types.contains_key(n) {
+                        continue;
+                    }
+                    if let Some(ty) = binding.type_of(&types, sigs) {
+                        types.insert(n.clone(), ty);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                return types;
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BlockData(pub Vec<Stmt>);
+
+/// `main`的块在最前且没有函数头, 其他函数以`def f(x: int, y: bool) -> int:`开头,
+/// 没有返回值时省略`-> T`.
+impl Display for CProgrom {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for def in &self.defs {
+            if def.name != Self::MAIN {
+                let params: Vec<String> = def
+                    .params
+                    .iter()
+                    .map(|(p, ty)| format!("{}: {}", p, ty))
+                    .collect();
+                write!(f, "def {}({})", def.name, params.join(", "))?;
+                if def.ret != Type::Unit {
+                    write!(f, " -> {}", def.ret)?;
+                }
+                writeln!(f, ":")?;
+            }
+            write!(f, "{}", def)?;
+        }
+        Ok(())
+    }
+}
+
+/// 输出时入口块在最前, 其余块按标签排序.
+impl Display for FunDef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let entry = self.entry();
+        let mut labels: Vec<&Label> = self.blocks.keys().filter(|l| **l != entry).collect();
+        labels.sort();
+        let entry = self.blocks.get_key_value(&entry).map(|(l, _)| l);
+        for label in entry.into_iter().chain(labels) {
+            writeln!(f, "{}:", label)?;
+            for s in &self.blocks[label].0 {
+                writeln!(f, "\t{}", s)?;
+            }
+        }
+        Ok(())
+    }
+}
 
 #[derive(Clone)]
 pub enum Stmt {
@@ -24,6 +145,16 @@ pub enum Stmt {
     },
     /// tail
     Return(Expr),
+    /// `return f(args)`. 不再回到当前函数, 所以可以先释放当前函数的栈帧.
+    TailCall {
+        name: Atom,
+        args: Vec<Atom>,
+    },
+    /// `return %apply(closure, args)`, 调用闭包的尾调用.
+    TailApply {
+        closure: Atom,
+        args: Vec<Atom>,
+    },
     Goto(Label),
     If {
         cond: Expr,
@@ -34,9 +165,41 @@ pub enum Stmt {
 
 impl Stmt {
     pub fn is_tail(&self) -> bool {
+        matches!(
+            self,
+            Self::Return(_)
+                | Self::TailCall { .. }
+                | Self::TailApply { .. }
+                | Self::Goto(_)
+                | Self::If { .. }
+        )
+    }
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Self::Return(_) | Self::Goto(_) | Self::If { .. } => true,
-            _ => false,
+            Self::Exp(e) => write!(f, "{}", e),
+            Self::Assign { name, binding } => write!(f, "{} = {}", name, binding),
+            Self::Return(e) => write!(f, "return {}", e),
+            Self::TailCall { name, args } => {
+                let call = Expr::Call {
+                    name: name.clone(),
+                    args: args.clone(),
+                };
+                write!(f, "tailcall {}", call)
+            }
+            Self::TailApply { closure, args } => {
+                let apply = Expr::Apply {
+                    closure: closure.clone(),
+                    args: args.clone(),
+                };
+                write!(f, "tailcall {}", apply)
+            }
+            Self::Goto(label) => write!(f, "goto {}", label),
+            Self::If { cond, then, else_ } => {
+                write!(f, "if {} goto {} else goto {}", cond, then, else_)
+            }
         }
     }
 }
@@ -53,14 +216,235 @@ impl Stmt {
 #[derive(Clone)]
 pub enum Expr {
     Atom(Atom),
-    Prim { op: Kind, operands: Vec<Atom> },
-    Call { name: Atom, args: Vec<Atom> },
+    /// 字符串字面量, 值是只读数据段中字符串的地址.
+    Str(String),
+    Prim {
+        op: Kind,
+        operands: Vec<Atom>,
+        /// 源程序中的位置, 用于运行时报错. 不是由源程序生成时为`None`.
+        range: Option<(usize, usize)>,
+    },
+    Call {
+        name: Atom,
+        args: Vec<Atom>,
+    },
+    /// 在堆上分配闭包: 函数`func`的地址和捕获的值.
+    Closure {
+        func: Label,
+        captures: Vec<Atom>,
+    },
+    /// 闭包捕获的第`index`个值.
+    Capture {
+        closure: Atom,
+        index: usize,
+    },
+    /// 调用闭包中的函数, 闭包本身是第一个参数.
+    Apply {
+        closure: Atom,
+        args: Vec<Atom>,
+    },
+    /// 在堆上分配元组. 之前已经确保有足够的空闲空间, 见`ExposeAllocation`.
+    Tuple(Vec<Atom>),
+    /// 元组的第`index`个元素.
+    Subscript {
+        tuple: Atom,
+        index: usize,
+    },
+    /// 运行时中全局变量的值.
+    GlobalValue(String),
+    /// 调用垃圾回收, 保证之后至少有这么多字节的空闲空间.
+    Collect(Atom),
+    /// 在堆上分配长度为`len`的列表, 元素未初始化. `ty`是列表的类型.
+    Allocate {
+        len: Atom,
+        ty: Type,
+    },
+    /// 列表的元素, 下标越界时报告运行时错误.
+    ListRef {
+        list: Atom,
+        index: Atom,
+        /// 源程序中的位置, 用于运行时报错.
+        range: Option<(usize, usize)>,
+    },
+    /// 写入列表的元素, 下标越界时报告运行时错误.
+    ListSet {
+        list: Atom,
+        index: Atom,
+        value: Atom,
+        range: Option<(usize, usize)>,
+    },
+    /// 初始化刚分配的列表的元素, 不检查下标.
+    ListInit {
+        list: Atom,
+        index: Atom,
+        value: Atom,
+    },
     // Condition {
     // 	condition: Box<Expr>,
     // 	then: Box<Expr>,
     // 	else_: Box<Expr>,
     // }
 }
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Atom(a) => write!(f, "{}", a),
+            Self::Str(s) => write!(f, "{}", token::quote(s)),
+            Self::Prim { op, operands, .. } if operands.len() == 2 => {
+                write!(f, "{} {} {}", operands[0], op_str(*op), operands[1])
+            }
+            Self::Prim { op, operands, .. } => write!(f, "{} {}", op_str(*op), operands[0]),
+            Self::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                for (i, a) in args.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", a)?;
+                }
+                write!(f, ")")
+            }
+            Self::Closure { func, captures } => {
+                write!(f, "{}({}", Self::CLOSURE, func)?;
+                for c in captures {
+                    write!(f, ", {}", c)?;
+                }
+                write!(f, ")")
+            }
+            Self::Capture { closure, index } => {
+                write!(f, "{}({}, {})", Self::CAPTURE, closure, index)
+            }
+            Self::Apply { closure, args } => {
+                write!(f, "{}({}", Self::APPLY, closure)?;
+                for a in args {
+                    write!(f, ", {}", a)?;
+                }
+                write!(f, ")")
+            }
+            Self::Tuple(elems) if elems.len() == 1 => write!(f, "({},)", elems[0]),
+            Self::Tuple(elems) => {
+                let elems: Vec<String> = elems.iter().map(ToString::to_string).collect();
+                write!(f, "({})", elems.join(", "))
+            }
+            Self::Subscript { tuple, index } => write!(f, "{}[{}]", tuple, index),
+            Self::GlobalValue(name) => write!(f, "{}({})", Self::GLOBAL, name),
+            Self::Collect(bytes) => write!(f, "{}({})", Self::COLLECT, bytes),
+            Self::Allocate { len, ty } => write!(f, "{}({}, {})", Self::ALLOCATE, len, ty),
+            Self::ListRef { list, index, .. } => {
+                write!(f, "{}({}, {})", Self::LIST_REF, list, index)
+            }
+            Self::ListSet {
+                list, index, value, ..
+            } => write!(f, "{}({}, {}, {})", Self::LIST_SET, list, index, value),
+            Self::ListInit { list, index, value } => {
+                write!(f, "{}({}, {}, {})", Self::LIST_INIT, list, index, value)
+            }
+        }
+    }
+}
+
+/// 闭包和内存管理操作的文本格式与函数调用相同, 以`%`开头的名字不会与源程序中的函数重名.
+impl Expr {
+    pub const CLOSURE: &'static str = "%closure";
+    pub const CAPTURE: &'static str = "%capture";
+    pub const APPLY: &'static str = "%apply";
+    pub const GLOBAL: &'static str = "%global";
+    pub const COLLECT: &'static str = "%collect";
+    pub const ALLOCATE: &'static str = "%allocate";
+    pub const LIST_REF: &'static str = "%list_ref";
+    pub const LIST_SET: &'static str = "%list_set";
+    pub const LIST_INIT: &'static str = "%list_init";
+
+    /// 表达式的值的类型. 用到的变量的类型还不知道时返回`None`.
+    fn type_of(&self, vars: &HashMap<String, Type>, sigs: &HashMap<Label, Type>) -> Option<Type> {
+        let atom = |a: &Atom| match a {
+            Atom::Int(_) => Some(Type::Int),
+            Atom::Float(_) => Some(Type::Float),
+            Atom::Bool(_) => Some(Type::Bool),
+            Atom::Name(n) => vars.get(n).cloned(),
+        };
+        let ret = |ty: Option<&Type>| match ty {
+            Some(Type::Func { ret, .. }) => Some((**ret).clone()),
+            _ => None,
+        };
+        match self {
+            Self::Atom(a) => atom(a),
+            Self::Str(_) => Some(Type::Str),
+            Self::Prim { op, .. } => match op {
+                Kind::Plus | Kind::Minus | Kind::Star | Kind::Slash | Kind::Percent => {
+                    Some(Type::Int)
+                }
+                _ => Some(Type::Bool),
+            },
+            // 内建函数的结果都不是指针.
+            Self::Call {
+                name: Atom::Name(f),
+                ..
+            } => ret(sigs.get(f)).or(Some(Type::Int)),
+            Self::Call { .. } => None,
+            Self::Closure { func, .. } => match sigs.get(func) {
+                Some(Type::Func { params, ret }) => Some(Type::Func {
+                    params: params[1..].to_vec(),
+                    ret: ret.clone(),
+                }),
+                _ => None,
+            },
+            Self::Capture { closure, index } => match atom(closure)? {
+                Type::Tuple(elems) => elems.get(index + 1).cloned(),
+                _ => None,
+            },
+            Self::Apply { closure, .. } => ret(atom(closure).as_ref()),
+            Self::Tuple(elems) => elems
+                .iter()
+                .map(atom)
+                .collect::<Option<_>>()
+                .map(Type::Tuple),
+            Self::Subscript { tuple, index } => match atom(tuple)? {
+                Type::Tuple(elems) => elems.get(*index).cloned(),
+                _ => None,
+            },
+            Self::GlobalValue(_) => Some(Type::Int),
+            Self::Allocate { ty, .. } => Some(ty.clone()),
+            Self::ListRef { list, .. } => match atom(list)? {
+                Type::List(elem) => Some(*elem),
+                _ => None,
+            },
+            Self::Collect(_) | Self::ListSet { .. } | Self::ListInit { .. } => Some(Type::Unit),
+        }
+    }
+}
+
+/// 运算符在源语言中的写法.
+const OPERATORS: [(Kind, &str); 14] = [
+    (Kind::Plus, "+"),
+    (Kind::Minus, "-"),
+    (Kind::Star, "*"),
+    (Kind::Slash, "/"),
+    (Kind::Percent, "%"),
+    (Kind::Bang, "not"),
+    (Kind::EqualEqual, "=="),
+    (Kind::BangEqual, "!="),
+    (Kind::Less, "<"),
+    (Kind::LessEqual, "<="),
+    (Kind::Greater, ">"),
+    (Kind::GreaterEqual, ">="),
+    (Kind::And, "and"),
+    (Kind::Or, "or"),
+];
+
+fn op_str(op: Kind) -> &'static str {
+    OPERATORS
+        .iter()
+        .find(|(k, _)| *k == op)
+        .map(|(_, s)| *s)
+        .unwrap_or_else(|| unreachable!("{:?} is not an operator", op))
+}
+
+pub fn op_kind(s: &str) -> Option<Kind> {
+    OPERATORS.iter().find(|(_, o)| *o == s).map(|(k, _)| *k)
+}
+
 #[derive(Clone)]
 pub enum Atom {
     Int(i64),
@@ -69,6 +453,17 @@ pub enum Atom {
     Name(String),
 }
 
+impl Display for Atom {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Int(i) => write!(f, "{}", i),
+            Self::Float(n) => write!(f, "{}", n),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Name(n) => write!(f, "{}", n),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ClikeError {
     IntoAtom,
@@ -125,6 +520,7 @@ impl TryFrom<ast::Expr> for Expr {
                         Ok(Expr::Prim {
                             op: op.kind(),
                             operands: atoms,
+                            range: Some(e.range),
                         })
                     }
                     Call { name, args } => {
@@ -136,6 +532,67 @@ impl TryFrom<ast::Expr> for Expr {
                         }
                         Ok(Expr::Call { name, args: atoms })
                     }
+                    Closure { func, captures } => {
+                        let captures = captures
+                            .into_iter()
+                            .map(Atom::try_from)
+                            .collect::<Result<_, _>>()?;
+                        Ok(Expr::Closure {
+                            func: func.lexeme().into(),
+                            captures,
+                        })
+                    }
+                    Capture { closure, index } => Ok(Expr::Capture {
+                        closure: Atom::try_from(*closure)?,
+                        index,
+                    }),
+                    Apply { closure, args } => {
+                        let args = args
+                            .into_iter()
+                            .map(Atom::try_from)
+                            .collect::<Result<_, _>>()?;
+                        Ok(Expr::Apply {
+                            closure: Atom::try_from(*closure)?,
+                            args,
+                        })
+                    }
+                    Tuple { elems } => {
+                        let elems = elems
+                            .into_iter()
+                            .map(Atom::try_from)
+                            .collect::<Result<_, _>>()?;
+                        Ok(Expr::Tuple(elems))
+                    }
+                    Subscript { value, index } => {
+                        let index = index.const_int().ok_or(Self::Error::IntoExpr)?;
+                        Ok(Expr::Subscript {
+                            tuple: Atom::try_from(*value)?,
+                            index: index as usize,
+                        })
+                    }
+                    Str(s) => Ok(Expr::Str(s)),
+                    GlobalValue(name) => Ok(Expr::GlobalValue(name)),
+                    Collect(bytes) => Ok(Expr::Collect(Atom::try_from(*bytes)?)),
+                    AllocateList { len, ty } => Ok(Expr::Allocate {
+                        len: Atom::try_from(*len)?,
+                        ty,
+                    }),
+                    ListRef { list, index } => Ok(Expr::ListRef {
+                        list: Atom::try_from(*list)?,
+                        index: Atom::try_from(*index)?,
+                        range: Some(e.range),
+                    }),
+                    ListSet { list, index, value } => Ok(Expr::ListSet {
+                        list: Atom::try_from(*list)?,
+                        index: Atom::try_from(*index)?,
+                        value: Atom::try_from(*value)?,
+                        range: Some(e.range),
+                    }),
+                    ListInit { list, index, value } => Ok(Expr::ListInit {
+                        list: Atom::try_from(*list)?,
+                        index: Atom::try_from(*index)?,
+                        value: Atom::try_from(*value)?,
+                    }),
                     _ => Err(Self::Error::IntoExpr),
                 }
             }
@@ -0,0 +1,724 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use crate::{
+    reporter::ErrorReporter,
+    token::{self, Kind},
+    type_checking::{Type, BUILTINS, RUNTIME_FUNCTIONS},
+};
+
+use super::clike::{op_kind, Atom, BlockData, CProgrom, Expr, FunDef, Label, Stmt};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// 一行中的一个词法单元.
+#[derive(Clone)]
+struct Word {
+    text: String,
+    line: usize,
+    /// 在整个源文件中的字符位置.
+    pos: usize,
+}
+
+/// 解析`CProgrom`的文本格式(即其`Display`的输出), 每行一个函数头, 标签或语句.
+/// 第一个函数头之前的块属于`main`:
+///
+/// ```text
+/// start:
+///     x = input_int()
+///     if x < 1 goto main.block_0 else goto main.block_1
+/// main.block_0:
+///     return 0
+/// def f(n: int) -> int:
+/// f.start:
+///     return n
+/// ```
+pub struct ClikeParser<'r> {
+    reporter: &'r ErrorReporter,
+    lines: Vec<Vec<Word>>,
+    /// 当前行的行号与行尾位置, 用于报告行尾缺少内容的错误.
+    eol: (usize, usize),
+    current: usize,
+    /// 当前函数中读取的变量. 既不是参数也没有被赋值的只能是函数名, 最后检查.
+    uses: Vec<Word>,
+    /// 当前函数中调用的函数名.
+    calls: Vec<Word>,
+    /// 所有函数结束后检查的函数名, 及其是否被调用.
+    globals: Vec<(Word, bool)>,
+}
+
+impl<'r> ClikeParser<'r> {
+    pub fn new(source: &str, reporter: &'r ErrorReporter) -> Self {
+        let mut lines = vec![];
+        let mut start = 0;
+        for (text, line) in source.split('\n').zip(1..) {
+            let words = Self::split_words(text, line, start);
+            if !words.is_empty() {
+                lines.push(words);
+            }
+            start += text.chars().count() + 1;
+        }
+        Self {
+            reporter,
+            lines,
+            eol: (1, 0),
+            current: 0,
+            uses: vec![],
+            calls: vec![],
+            globals: vec![],
+        }
+    }
+
+    pub fn program(mut self) -> Result<CProgrom> {
+        let lines = std::mem::take(&mut self.lines);
+        let mut defs: Vec<FunDef> = vec![];
+        let mut def = FunDef {
+            name: CProgrom::MAIN.into(),
+            params: vec![],
+            ret: Type::Int,
+            blocks: HashMap::new(),
+        };
+        let mut block: Option<(Word, Vec<Stmt>)> = None;
+        // 跳转只能在函数内部, 所以每个函数结束时检查.
+        let mut gotos = vec![];
+
+        for words in lines {
+            let last = words.last().unwrap();
+            self.eol = (last.line, last.pos + last.text.chars().count());
+            self.current = 0;
+
+            if words[0].text == "def" {
+                if let Some((label, stmts)) = block.take() {
+                    self.finish_block(&mut def.blocks, label, stmts)?;
+                }
+                let next = self.def_header(&words)?;
+                if next.name == CProgrom::MAIN || defs.iter().any(|d| d.name == next.name) {
+                    return self.error(&words[1], "Duplicate function");
+                }
+                defs.push(self.finish_def(def, std::mem::take(&mut gotos))?);
+                def = next;
+                continue;
+            }
+
+            if words.len() == 2 && words[1].text == ":" {
+                if let Some((label, stmts)) = block.take() {
+                    self.finish_block(&mut def.blocks, label, stmts)?;
+                }
+                let label = words.into_iter().next().unwrap();
+                self.expect_name(&label)?;
+                block = Some((label, vec![]));
+                continue;
+            }
+
+            let stmt = self.stmt(&words)?;
+            if let Some(w) = words.get(self.current) {
+                return self.error(w, "Expected the end of the line");
+            }
+            // 跳转目标的标签都在行尾或`else`之前, 记下来最后检查它们是否存在.
+            match &stmt {
+                Stmt::Goto(_) => gotos.push(words[words.len() - 1].clone()),
+                Stmt::If { .. } => {
+                    gotos.push(words[words.len() - 4].clone());
+                    gotos.push(words[words.len() - 1].clone());
+                }
+                _ => (),
+            }
+            match &mut block {
+                Some((_, stmts)) => stmts.push(stmt),
+                None => {
+                    return self.error(&words[0], "Expected a label before the first statement")
+                }
+            }
+        }
+        if let Some((label, stmts)) = block.take() {
+            self.finish_block(&mut def.blocks, label, stmts)?;
+        }
+        defs.push(self.finish_def(def, gotos)?);
+        for (w, called) in &self.globals {
+            let name = w.text.as_str();
+            let is_runtime = BUILTINS.contains(&name) || RUNTIME_FUNCTIONS.contains(&name);
+            let defined = defs.iter().any(|d| d.name == name) || *called && is_runtime;
+            if !defined {
+                let kind = if *called { "function" } else { "variable" };
+                return self.error(w, &format!("Undefined {}", kind));
+            }
+        }
+        Ok(CProgrom::new(defs))
+    }
+}
+
+impl<'r> ClikeParser<'r> {
+    fn split_words(text: &str, line: usize, start: usize) -> Vec<Word> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut words = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            let begin = i;
+            match chars[i] {
+                c if c.is_whitespace() => {
+                    i += 1;
+                    continue;
+                }
+                // 注释
+                '#' => break,
+                // 字符串字面量是一个词, 包括引号和转义.
+                '"' => {
+                    i += 1;
+                    while i < chars.len() && chars[i] != '"' {
+                        i += if chars[i] == '\\' { 2 } else { 1 };
+                    }
+                    i = (i + 1).min(chars.len());
+                }
+                // 紧跟数字的`-`是负数字面量, 一元运算符`-`后面有空格.
+                '-' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                c if c.is_ascii_alphanumeric() || c == '_' || c == '%' => {
+                    i += 1;
+                    // 块的标签中有`.`
+                    while i < chars.len()
+                        && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                    {
+                        i += 1;
+                    }
+                }
+                '=' | '!' | '<' | '>' if chars.get(i + 1) == Some(&'=') => i += 2,
+                '-' if chars.get(i + 1) == Some(&'>') => i += 2,
+                '/' if chars.get(i + 1) == Some(&'/') => i += 2,
+                _ => i += 1,
+            }
+            words.push(Word {
+                text: chars[begin..i].iter().collect(),
+                line,
+                pos: start + begin,
+            });
+        }
+        words
+    }
+
+    fn error<T>(&self, w: &Word, msg: &str) -> Result<T> {
+        Err(self
+            .reporter
+            .error(msg, w.line, w.pos, w.text.chars().count())
+            .unwrap_err())
+    }
+
+    /// def_header = "def" name "(" [param {"," param}] ")" ["->" type] ":"
+    /// param = name ":" type
+    fn def_header(&mut self, words: &[Word]) -> Result<FunDef> {
+        self.current = 1;
+        let name = self.label(words)?;
+        self.expect(words, "(")?;
+        let mut params = vec![];
+        if self.peek(words) != Some(")") {
+            params.push(self.param(words)?);
+            while self.peek(words) == Some(",") {
+                self.current += 1;
+                params.push(self.param(words)?);
+            }
+        }
+        self.expect(words, ")")?;
+        let ret = if self.peek(words) == Some("->") {
+            self.current += 1;
+            self.type_(words)?
+        } else {
+            Type::Unit
+        };
+        self.expect(words, ":")?;
+        if let Some(w) = words.get(self.current) {
+            return self.error(w, "Expected the end of the line");
+        }
+        Ok(FunDef {
+            name,
+            params,
+            ret,
+            blocks: HashMap::new(),
+        })
+    }
+
+    fn param(&mut self, words: &[Word]) -> Result<(String, Type)> {
+        let name = self.label(words)?;
+        self.expect(words, ":")?;
+        Ok((name, self.type_(words)?))
+    }
+
+    /// type = "int" | "bool" | "str" | "(" ")" | "tuple" "[" types "]" | "Callable" "[" "[" [types] "]" "," type "]"
+    /// types = type {"," type}
+    ///
+    /// 与`Type`的`Display`相同.
+    fn type_(&mut self, words: &[Word]) -> Result<Type> {
+        let w = self.next(words)?;
+        match w.text.as_str() {
+            "int" => Ok(Type::Int),
+            "bool" => Ok(Type::Bool),
+            "str" => Ok(Type::Str),
+            "(" => {
+                self.expect(words, ")")?;
+                Ok(Type::Unit)
+            }
+            "tuple" => {
+                self.expect(words, "[")?;
+                let elems = self.types(words)?;
+                self.expect(words, "]")?;
+                Ok(Type::Tuple(elems))
+            }
+            "list" => {
+                self.expect(words, "[")?;
+                let elem = self.type_(words)?;
+                self.expect(words, "]")?;
+                Ok(Type::List(elem.into()))
+            }
+            "Callable" => {
+                self.expect(words, "[")?;
+                self.expect(words, "[")?;
+                let params = if self.peek(words) == Some("]") {
+                    vec![]
+                } else {
+                    self.types(words)?
+                };
+                self.expect(words, "]")?;
+                self.expect(words, ",")?;
+                let ret = self.type_(words)?;
+                self.expect(words, "]")?;
+                Ok(Type::Func {
+                    params,
+                    ret: ret.into(),
+                })
+            }
+            _ => self.error(w, "Expected a type"),
+        }
+    }
+
+    fn types(&mut self, words: &[Word]) -> Result<Vec<Type>> {
+        let mut types = vec![self.type_(words)?];
+        while self.peek(words) == Some(",") {
+            self.current += 1;
+            types.push(self.type_(words)?);
+        }
+        Ok(types)
+    }
+
+    /// 检查函数中的跳转目标和入口块是否存在. 不是局部变量的名字留到最后检查.
+    fn finish_def(&mut self, def: FunDef, gotos: Vec<Word>) -> Result<FunDef> {
+        for w in &gotos {
+            if !def.blocks.contains_key(&w.text) {
+                return self.error(w, "Undefined label");
+            }
+        }
+        if !def.blocks.contains_key(&def.entry()) {
+            return Err(format!("Expected an entry block `{}`", def.entry()).into());
+        }
+        let mut locals: HashSet<&str> = def.params.iter().map(|(p, _)| p.as_str()).collect();
+        for block in def.blocks.values() {
+            for s in &block.0 {
+                if let Stmt::Assign {
+                    name: Atom::Name(n),
+                    ..
+                } = s
+                {
+                    locals.insert(n);
+                }
+            }
+        }
+        let uses = std::mem::take(&mut self.uses)
+            .into_iter()
+            .map(|w| (w, false));
+        let calls = std::mem::take(&mut self.calls)
+            .into_iter()
+            .map(|w| (w, true));
+        self.globals.extend(
+            uses.chain(calls)
+                .filter(|(w, _)| !locals.contains(w.text.as_str())),
+        );
+        Ok(def)
+    }
+
+    fn finish_block(
+        &self,
+        blocks: &mut HashMap<Label, BlockData>,
+        label: Word,
+        stmts: Vec<Stmt>,
+    ) -> Result<()> {
+        if blocks.contains_key(&label.text) {
+            return self.error(&label, "Duplicate label");
+        }
+        let tails = stmts.iter().filter(|s| s.is_tail()).count();
+        if tails != 1 || !stmts.last().is_some_and(Stmt::is_tail) {
+            return self.error(
+                &label,
+                "Expected a block to end with exactly one `goto`, `if` or `return`",
+            );
+        }
+        blocks.insert(label.text, BlockData(stmts));
+        Ok(())
+    }
+
+    fn next<'w>(&mut self, words: &'w [Word]) -> Result<&'w Word> {
+        match words.get(self.current) {
+            Some(w) => {
+                self.current += 1;
+                Ok(w)
+            }
+            None => {
+                let msg = "Unexpected end of the line";
+                Err(self
+                    .reporter
+                    .error(msg, self.eol.0, self.eol.1, 1)
+                    .unwrap_err())
+            }
+        }
+    }
+
+    fn peek<'w>(&self, words: &'w [Word]) -> Option<&'w str> {
+        words.get(self.current).map(|w| w.text.as_str())
+    }
+
+    fn expect(&mut self, words: &[Word], text: &str) -> Result<()> {
+        let w = self.next(words)?;
+        if w.text != text {
+            return self.error(w, &format!("Expected `{}`", text));
+        }
+        Ok(())
+    }
+
+    fn expect_name(&self, w: &Word) -> Result<String> {
+        let mut chars = w.text.chars();
+        match chars.next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '%' => Ok(w.text.clone()),
+            _ => self.error(w, "Expected a name"),
+        }
+    }
+
+    fn label(&mut self, words: &[Word]) -> Result<Label> {
+        let w = self.next(words)?;
+        self.expect_name(w)
+    }
+
+    fn stmt(&mut self, words: &[Word]) -> Result<Stmt> {
+        match words[0].text.as_str() {
+            "goto" => {
+                self.current += 1;
+                Ok(Stmt::Goto(self.label(words)?))
+            }
+            "return" => {
+                self.current += 1;
+                Ok(Stmt::Return(self.exp(words)?))
+            }
+            "tailcall" => {
+                self.current += 1;
+                match self.exp(words)? {
+                    Expr::Call { name, args } => Ok(Stmt::TailCall { name, args }),
+                    Expr::Apply { closure, args } => Ok(Stmt::TailApply { closure, args }),
+                    _ => self.error(&words[0], "Expected a function call"),
+                }
+            }
+            "if" => {
+                self.current += 1;
+                let cond = self.exp(words)?;
+                if !matches!(cond, Expr::Prim { ref operands, .. } if operands.len() == 2) {
+                    return self.error(&words[1], "Expected a comparison");
+                }
+                self.expect(words, "goto")?;
+                let then = self.label(words)?;
+                self.expect(words, "else")?;
+                self.expect(words, "goto")?;
+                let else_ = self.label(words)?;
+                Ok(Stmt::If { cond, then, else_ })
+            }
+            _ if words.get(1).is_some_and(|w| w.text == "=") => {
+                let name = Atom::Name(self.expect_name(&words[0])?);
+                self.current += 2;
+                let binding = self.exp(words)?;
+                Ok(Stmt::Assign { name, binding })
+            }
+            _ => Ok(Stmt::Exp(self.exp(words)?)),
+        }
+    }
+
+    /// exp = string | atom | op atom | atom op atom | name "(" [atoms] ")" | "(" atom "," [atoms] ")"
+    ///     | atom "[" int "]" | "%allocate" "(" atom "," type ")"
+    /// atoms = atom {"," atom}
+    ///
+    /// 闭包和内存管理操作的格式与函数调用相同, 见`Expr::CLOSURE`.
+    fn exp(&mut self, words: &[Word]) -> Result<Expr> {
+        if self.peek(words).is_some_and(|w| w.starts_with('"')) {
+            let w = self.next(words)?;
+            if w.text.len() < 2 || !w.text.ends_with('"') {
+                return self.error(w, "Unterminated string");
+            }
+            return match token::unescape(&w.text) {
+                Ok(s) => Ok(Expr::Str(s)),
+                Err(c) => self.error(w, &format!("Unknown escape sequence `\\{}`", c)),
+            };
+        }
+        if self.peek(words) == Some("(") {
+            self.current += 1;
+            let mut elems = vec![self.atom(words)?];
+            self.expect(words, ",")?;
+            while self.peek(words) != Some(")") {
+                elems.push(self.atom(words)?);
+                if self.peek(words) != Some(",") {
+                    break;
+                }
+                self.current += 1;
+            }
+            self.expect(words, ")")?;
+            return Ok(Expr::Tuple(elems));
+        }
+        if let Some(op) = self.peek(words).and_then(op_kind) {
+            if matches!(op, Kind::Minus | Kind::Bang) {
+                self.current += 1;
+                let a = self.atom(words)?;
+                return Ok(Expr::Prim {
+                    op,
+                    operands: vec![a],
+                    range: None,
+                });
+            }
+        }
+
+        let first = self.current;
+        let a = self.atom(words)?;
+        // 第二个参数是类型, 不是原子.
+        if matches!(&a, Atom::Name(n) if n == Expr::ALLOCATE) {
+            self.uses.pop();
+            self.expect(words, "(")?;
+            let len = self.atom(words)?;
+            self.expect(words, ",")?;
+            let ty = self.type_(words)?;
+            self.expect(words, ")")?;
+            return Ok(Expr::Allocate { len, ty });
+        }
+        match self.peek(words) {
+            Some("(") => {
+                self.current += 1;
+                let mut args = vec![];
+                if self.peek(words) != Some(")") {
+                    args.push(self.atom(words)?);
+                    while self.peek(words) == Some(",") {
+                        self.current += 1;
+                        args.push(self.atom(words)?);
+                    }
+                }
+                self.expect(words, ")")?;
+                // 调用的是函数名或保存函数的变量, 特殊操作的名字不是变量.
+                self.uses.retain(|w| w.pos != words[first].pos);
+                let e = self.special_op(&words[first], a, args)?;
+                if matches!(e, Expr::Call { .. }) {
+                    self.calls.push(words[first].clone());
+                }
+                Ok(e)
+            }
+            Some("[") => {
+                self.current += 1;
+                let w = self.next(words)?;
+                let index = match w.text.parse() {
+                    Ok(i) => i,
+                    Err(_) => return self.error(w, "Expected a tuple index"),
+                };
+                self.expect(words, "]")?;
+                Ok(Expr::Subscript { tuple: a, index })
+            }
+            Some(s) => match op_kind(s) {
+                Some(op) => {
+                    self.current += 1;
+                    let b = self.atom(words)?;
+                    Ok(Expr::Prim {
+                        op,
+                        operands: vec![a, b],
+                        range: None,
+                    })
+                }
+                None => Ok(Expr::Atom(a)),
+            },
+            None => Ok(Expr::Atom(a)),
+        }
+    }
+
+    fn special_op(&mut self, w: &Word, name: Atom, mut args: Vec<Atom>) -> Result<Expr> {
+        let Atom::Name(n) = &name else {
+            return Ok(Expr::Call { name, args });
+        };
+        match (n.as_str(), args.as_slice()) {
+            (Expr::CLOSURE, [Atom::Name(func), ..]) => Ok(Expr::Closure {
+                func: func.clone(),
+                captures: args.split_off(1),
+            }),
+            (Expr::CAPTURE, [closure, Atom::Int(i)]) if *i >= 0 => Ok(Expr::Capture {
+                closure: closure.clone(),
+                index: *i as usize,
+            }),
+            (Expr::APPLY, [_, ..]) => Ok(Expr::Apply {
+                args: args.split_off(1),
+                closure: args.pop().unwrap(),
+            }),
+            (Expr::GLOBAL, [Atom::Name(name)]) => {
+                self.uses.pop();
+                Ok(Expr::GlobalValue(name.clone()))
+            }
+            (Expr::COLLECT, [bytes]) => Ok(Expr::Collect(bytes.clone())),
+            (Expr::LIST_REF, [list, index]) => Ok(Expr::ListRef {
+                list: list.clone(),
+                index: index.clone(),
+                range: None,
+            }),
+            (Expr::LIST_SET, [list, index, value]) => Ok(Expr::ListSet {
+                list: list.clone(),
+                index: index.clone(),
+                value: value.clone(),
+                range: None,
+            }),
+            (Expr::LIST_INIT, [list, index, value]) => Ok(Expr::ListInit {
+                list: list.clone(),
+                index: index.clone(),
+                value: value.clone(),
+            }),
+            (
+                Expr::CLOSURE
+                | Expr::CAPTURE
+                | Expr::APPLY
+                | Expr::GLOBAL
+                | Expr::COLLECT
+                | Expr::LIST_REF
+                | Expr::LIST_SET
+                | Expr::LIST_INIT,
+                _,
+            ) => self.error(w, "Invalid arguments to a special operation"),
+            _ => Ok(Expr::Call { name, args }),
+        }
+    }
+
+    fn atom(&mut self, words: &[Word]) -> Result<Atom> {
+        let w = self.next(words)?;
+        match w.text.as_str() {
+            "true" => Ok(Atom::Bool(true)),
+            "false" => Ok(Atom::Bool(false)),
+            t if t.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => match t.parse() {
+                Ok(i) => Ok(Atom::Int(i)),
+                Err(_) => self.error(w, "Invalid integer literal"),
+            },
+            _ => {
+                let name = self.expect_name(w)?;
+                self.uses.push(w.clone());
+                Ok(Atom::Name(name))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<CProgrom> {
+        let reporter = ErrorReporter::new(None, source.to_string());
+        ClikeParser::new(source, &reporter).program()
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = "\
+start:
+\tx = input_int()
+\t%tmp0 = - x
+\tif x < -1 goto block_0 else goto block_1
+block_0:
+\tprint_int(%tmp0)
+\tgoto block_1
+block_1:
+\tb = not true
+\ts = \"a\\tb \\\"c\\\" # d\"
+\tprint_str(s)
+\treturn x + 1
+";
+        let prog = parse(source).unwrap();
+        assert_eq!(prog.to_string(), source);
+    }
+
+    #[test]
+    fn missing_tail() {
+        assert!(parse("start:\n\tx = 1\n").is_err());
+        assert!(parse("start:\n\treturn 0\n\tx = 1\n").is_err());
+    }
+
+    #[test]
+    fn functions() {
+        let source = "\
+start:
+\tx = f(1, 2)
+\treturn x
+def f(a: int, b: int) -> int:
+f.start:
+\tif a < b goto f.block_0 else goto f.block_1
+f.block_0:
+\treturn a
+f.block_1:
+\ttailcall f(b, a)
+";
+        let prog = parse(source).unwrap();
+        assert_eq!(prog.to_string(), source);
+        let source = "\
+start:
+\tx = input_int()
+\tc = %closure(lambda, x, 1)
+\ty = %capture(c, 1)
+\treturn %apply(c, y)
+def lambda(%clos: tuple[int, int, int], z: Callable[[int], ()]):
+lambda.start:
+\tt = (z, 1)
+\tu = (t,)
+\tv = t[1]
+\tg = %global(free_ptr)
+\tif g < v goto lambda.block_0 else goto lambda.block_0
+lambda.block_0:
+\t%collect(16)
+\ta = %allocate(g, list[list[int]])
+\t%list_init(a, 0, u)
+\tw = %list_ref(a, v)
+\t%list_set(a, v, w)
+\treturn 0
+";
+        let prog = parse(source).unwrap();
+        assert_eq!(prog.to_string(), source);
+        assert!(parse("start:\n\treturn %capture(c, x)\n").is_err());
+        assert!(parse("start:\n\treturn %list_ref(a)\n").is_err());
+        // 不能跳转到其他函数的块.
+        assert!(parse("start:\n\tgoto f.start\ndef f():\nf.start:\n\treturn 0\n").is_err());
+        assert!(parse("start:\n\treturn 0\ndef f():\nstart:\n\treturn 0\n").is_err());
+    }
+
+    #[test]
+    fn missing_entry() {
+        assert!(parse("main:\n\treturn 0\n").is_err());
+    }
+
+    #[test]
+    fn undefined_label() {
+        assert!(parse("start:\n\tgoto block_0\n").is_err());
+    }
+
+    #[test]
+    fn undefined_name() {
+        assert!(parse("start:\n\treturn x\n").is_err());
+        assert!(parse("start:\n\treturn f(1)\n").is_err());
+        // 变量可以在后面的块中赋值, 函数可以在后面定义.
+        let source = "\
+start:
+\tgoto block_1
+block_0:
+\treturn f(x)
+block_1:
+\tx = read_int()
+\tgoto block_0
+def f(n: int) -> int:
+f.start:
+\treturn n
+";
+        assert!(parse(source).is_ok());
+    }
+}
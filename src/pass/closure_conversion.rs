@@ -0,0 +1,512 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{Expr, ExprData, Stmt, StmtData, TypeExpr},
+    token::{Kind, Token},
+    type_checking::{Type, BUILTINS, RUNTIME_FUNCTIONS},
+};
+
+/// 闭包函数的第一个参数, 即闭包本身.
+const CLOSURE_PARAM: &str = "%clos";
+
+/// 顶层函数的参数和返回值类型.
+type Signature = (Vec<(Token, TypeExpr)>, Option<TypeExpr>);
+
+/// 把lambda提升为顶层函数, 函数值都表示为堆上的闭包`[tag, 函数地址, 捕获的值...]`.
+/// 闭包的函数以闭包本身为第一个参数, 从中读取捕获的值; 捕获的是创建闭包时变量的值,
+/// 类型检查保证之后不会再给这些变量赋值.
+/// 这个参数的类型是元组`tuple[int, 捕获的值的类型...]`, 垃圾回收据此知道其中哪些是指针.
+/// 直接调用顶层函数不需要闭包. 顶层函数作为值使用时, 闭包中的函数是转调它的包装函数.
+pub struct ClosureConversion {
+    /// 顶层函数和内建函数. 变量不会与它们同名, 见`TypeChecker::check_binding`.
+    globals: HashSet<String>,
+    /// 用于生成包装函数.
+    defs: HashMap<String, Signature>,
+    /// 已经生成的包装函数.
+    wrappers: HashMap<String, Token>,
+    /// 新生成的顶层函数.
+    lifted: Vec<Stmt>,
+    counter: usize,
+}
+
+impl ClosureConversion {
+    pub fn new() -> Self {
+        Self {
+            globals: BUILTINS
+                .iter()
+                .chain(&RUNTIME_FUNCTIONS)
+                .map(|f| f.to_string())
+                .collect(),
+            defs: HashMap::new(),
+            wrappers: HashMap::new(),
+            lifted: vec![],
+            counter: 0,
+        }
+    }
+
+    /// 新生成的函数放在最后.
+    pub fn convert_stmts(mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        for s in &stmts {
+            if let StmtData::Def {
+                name, params, ret, ..
+            } = &s.stmt
+            {
+                self.globals.insert(name.lexeme().into());
+                self.defs
+                    .insert(name.lexeme().into(), (params.clone(), ret.clone()));
+            }
+        }
+        let mut res: Vec<Stmt> = stmts.into_iter().map(|s| self.convert_stmt(s)).collect();
+        res.append(&mut self.lifted);
+        res
+    }
+
+    /// 不与已有函数重名的函数名.
+    fn fresh_name(&mut self, base: &str) -> Token {
+        let mut name = base.to_string();
+        while self.globals.contains(&name) {
+            name = format!("{}_{}", base, self.counter);
+            self.counter += 1;
+        }
+        self.globals.insert(name.clone());
+        Token::new(Kind::Name, name, 0, 0)
+    }
+
+    fn convert_stmt(&mut self, s: Stmt) -> Stmt {
+        let stmt = match s.stmt {
+            StmtData::Expr(e) => StmtData::Expr(self.convert_exp(e)),
+            StmtData::Assign { name, binding } => StmtData::Assign {
+                name,
+                binding: self.convert_exp(binding),
+            },
+            StmtData::If {
+                condition,
+                then,
+                else_,
+            } => StmtData::If {
+                condition: self.convert_exp(condition),
+                then: self.convert_exp(then),
+                else_: self.convert_exp(else_),
+            },
+            StmtData::While { condition, body } => StmtData::While {
+                condition: self.convert_exp(condition),
+                body: self.convert_exp(body),
+            },
+            StmtData::Def {
+                name,
+                params,
+                ret,
+                body,
+            } => StmtData::Def {
+                name,
+                params,
+                ret,
+                body: self.convert_exp(body),
+            },
+            StmtData::Return(value) => StmtData::Return(value.map(|e| self.convert_exp(e))),
+            StmtData::AnnAssign { .. }
+            | StmtData::AugAssign { .. }
+            | StmtData::MultiAssign { .. }
+            | StmtData::For { .. } => {
+                unreachable!("Statement should have been simplified by Shrink.")
+            }
+        };
+        Stmt {
+            stmt,
+            range: s.range,
+        }
+    }
+
+    fn convert_exp(&mut self, e: Expr) -> Expr {
+        let range = e.range;
+        let data = match e.data {
+            ExprData::Name(n) if self.defs.contains_key(n.lexeme()) => ExprData::Closure {
+                func: self.wrapper(&n),
+                captures: vec![],
+            },
+            ExprData::Call { name, args } => {
+                let args = args.into_iter().map(|a| self.convert_exp(a)).collect();
+                match name.data {
+                    ExprData::Name(ref f) if self.globals.contains(f.lexeme()) => {
+                        ExprData::Call { name, args }
+                    }
+                    _ => ExprData::Apply {
+                        closure: self.convert_exp(*name).into(),
+                        args,
+                    },
+                }
+            }
+            ExprData::Prim { op, operands } => ExprData::Prim {
+                op,
+                operands: operands.into_iter().map(|e| self.convert_exp(e)).collect(),
+            },
+            ExprData::Condition {
+                condition,
+                then,
+                else_,
+            } => ExprData::Condition {
+                condition: self.convert_exp(*condition).into(),
+                then: self.convert_exp(*then).into(),
+                else_: self.convert_exp(*else_).into(),
+            },
+            ExprData::Block { body, result } => ExprData::Block {
+                body: body.into_iter().map(|s| self.convert_stmt(s)).collect(),
+                result: result.map(|r| self.convert_exp(*r).into()),
+            },
+            ExprData::Lambda {
+                params,
+                body,
+                ty,
+                captures,
+            } => {
+                return self.lift(params, *body, ty, captures, range);
+            }
+            ExprData::Tuple { elems } => ExprData::Tuple {
+                elems: elems.into_iter().map(|e| self.convert_exp(e)).collect(),
+            },
+            ExprData::Subscript { value, index } => ExprData::Subscript {
+                value: self.convert_exp(*value).into(),
+                index,
+            },
+            ExprData::List { elems, repeat, ty } => ExprData::List {
+                elems: elems.into_iter().map(|e| self.convert_exp(e)).collect(),
+                repeat: repeat.map(|n| self.convert_exp(*n).into()),
+                ty,
+            },
+            ExprData::ListRef { list, index } => ExprData::ListRef {
+                list: self.convert_exp(*list).into(),
+                index: self.convert_exp(*index).into(),
+            },
+            ExprData::ListSet { list, index, value } => ExprData::ListSet {
+                list: self.convert_exp(*list).into(),
+                index: self.convert_exp(*index).into(),
+                value: self.convert_exp(*value).into(),
+            },
+            d => d,
+        };
+        Expr { data, range }
+    }
+
+    /// `lambda x: body` => `%closure(lambda, a, b)`, 以及新的顶层函数
+    /// ```text
+    /// def lambda(%clos, x) {
+    ///     return body[a := %capture(%clos, 0), b := %capture(%clos, 1)]
+    /// }
+    /// ```
+    fn lift(
+        &mut self,
+        params: Vec<Token>,
+        body: Expr,
+        ty: Option<Type>,
+        captures: Vec<(String, Type)>,
+        range: (usize, usize),
+    ) -> Expr {
+        let Some(Type::Func { params: tys, ret }) = ty else {
+            unreachable!("Lambdas are typed by the type checker.")
+        };
+        let (free, capture_tys): (Vec<String>, Vec<Type>) = captures.into_iter().unzip();
+        let mut body = self.convert_exp(body);
+        let clos = Token::new(Kind::Name, CLOSURE_PARAM.into(), 0, 0);
+        substitute(&mut body, &free, &clos);
+
+        let name = self.fresh_name("lambda");
+        let clos_ty = std::iter::once(Type::Int).chain(capture_tys).collect();
+        let mut def = Stmt::def()
+            .name(name.clone())
+            .param(clos, TypeExpr::Checked(Type::Tuple(clos_ty)));
+        for (p, ty) in params.into_iter().zip(tys) {
+            def = def.param(p, TypeExpr::Checked(ty));
+        }
+        if *ret != Type::Unit {
+            def = def.ret(TypeExpr::Checked(*ret));
+        }
+        self.lifted
+            .push(def.body(Self::return_block(body, range)).build());
+
+        let captures = free
+            .into_iter()
+            .map(|v| Expr::new(ExprData::Name(Token::new(Kind::Name, v, 0, 0)), range))
+            .collect();
+        Expr::new(
+            ExprData::Closure {
+                func: name,
+                captures,
+            },
+            range,
+        )
+    }
+
+    /// 顶层函数`f`作为值使用时的包装函数: `def f_closure(%clos, x) { return f(x) }`.
+    fn wrapper(&mut self, f: &Token) -> Token {
+        if let Some(w) = self.wrappers.get(f.lexeme()) {
+            return w.clone();
+        }
+        let name = self.fresh_name(&format!("{}_closure", f.lexeme()));
+        self.wrappers.insert(f.lexeme().into(), name.clone());
+
+        let (params, ret) = self.defs[f.lexeme()].clone();
+        let clos = Token::new(Kind::Name, CLOSURE_PARAM.into(), 0, 0);
+        let range = (0, 0);
+        let args = params
+            .iter()
+            .map(|(p, _)| Expr::new(ExprData::Name(p.clone()), range))
+            .collect();
+        let call = Expr::new(
+            ExprData::Call {
+                name: Expr::new(ExprData::Name(f.clone()), range).into(),
+                args,
+            },
+            range,
+        );
+        let mut def = Stmt::def()
+            .name(name.clone())
+            .param(clos, TypeExpr::Checked(Type::Tuple(vec![Type::Int])));
+        for (p, ty) in params {
+            def = def.param(p, ty);
+        }
+        if let Some(ret) = ret {
+            def = def.ret(ret);
+        }
+        self.lifted
+            .push(def.body(Self::return_block(call, range)).build());
+        name
+    }
+
+    fn return_block(value: Expr, range: (usize, usize)) -> Expr {
+        let ret = Stmt {
+            stmt: StmtData::Return(Some(value)),
+            range,
+        };
+        Expr::new(
+            ExprData::Block {
+                body: vec![ret],
+                result: None,
+            },
+            range,
+        )
+    }
+}
+
+/// lambda中用到的外层变量, 按第一次出现的顺序. `is_global`判断名字是否是顶层函数或内建函数.
+/// 与编译后的函数一样, lambda中任何位置赋值的变量都是它自己的变量.
+pub fn free_vars(params: &[Token], body: &Expr, is_global: &dyn Fn(&str) -> bool) -> Vec<String> {
+    let mut used = vec![];
+    let mut bound: HashSet<String> = params.iter().map(|p| p.lexeme().to_string()).collect();
+    used_in_exp(body, &mut used, &mut bound, is_global);
+    used.retain(|v| !bound.contains(v) && !is_global(v));
+    used
+}
+
+fn use_name(used: &mut Vec<String>, name: &str) {
+    if !used.iter().any(|v| v == name) {
+        used.push(name.into());
+    }
+}
+
+fn used_in_exp(
+    e: &Expr,
+    used: &mut Vec<String>,
+    bound: &mut HashSet<String>,
+    is_global: &dyn Fn(&str) -> bool,
+) {
+    match &e.data {
+        ExprData::Int(_)
+        | ExprData::Bool(_)
+        | ExprData::Float(_)
+        | ExprData::Str(_)
+        | ExprData::GlobalValue(_)
+        | ExprData::Collect(_)
+        | ExprData::AllocateList { .. }
+        | ExprData::ListInit { .. } => (),
+        ExprData::Name(n) => use_name(used, n.lexeme()),
+        ExprData::Prim { operands: es, .. }
+        | ExprData::Closure { captures: es, .. }
+        | ExprData::Tuple { elems: es } => {
+            for e in es {
+                used_in_exp(e, used, bound, is_global);
+            }
+        }
+        ExprData::Subscript { value, .. } => used_in_exp(value, used, bound, is_global),
+        ExprData::List { elems, repeat, .. } => {
+            for e in elems.iter().chain(repeat.as_deref()) {
+                used_in_exp(e, used, bound, is_global);
+            }
+        }
+        ExprData::ListRef { list, index } => {
+            used_in_exp(list, used, bound, is_global);
+            used_in_exp(index, used, bound, is_global);
+        }
+        ExprData::ListSet { list, index, value } => {
+            for e in [list, index, value] {
+                used_in_exp(e, used, bound, is_global);
+            }
+        }
+        ExprData::Call { name: f, args } | ExprData::Apply { closure: f, args } => {
+            used_in_exp(f, used, bound, is_global);
+            for a in args {
+                used_in_exp(a, used, bound, is_global);
+            }
+        }
+        ExprData::Capture { closure, .. } => used_in_exp(closure, used, bound, is_global),
+        ExprData::Condition {
+            condition,
+            then,
+            else_,
+        } => {
+            for e in [condition, then, else_] {
+                used_in_exp(e, used, bound, is_global);
+            }
+        }
+        ExprData::Block { body, result } => {
+            for s in body {
+                used_in_stmt(s, used, bound, is_global);
+            }
+            if let Some(r) = result {
+                used_in_exp(r, used, bound, is_global);
+            }
+        }
+        ExprData::Lambda { params, body, .. } => {
+            for v in free_vars(params, body, is_global) {
+                use_name(used, &v);
+            }
+        }
+    }
+}
+
+fn used_in_stmt(
+    s: &Stmt,
+    used: &mut Vec<String>,
+    bound: &mut HashSet<String>,
+    is_global: &dyn Fn(&str) -> bool,
+) {
+    let mut exp = |e: &Expr, bound: &mut HashSet<String>| used_in_exp(e, used, bound, is_global);
+    match &s.stmt {
+        StmtData::Expr(e) | StmtData::Return(Some(e)) => exp(e, bound),
+        StmtData::Return(None) | StmtData::Def { .. } => (),
+        StmtData::Assign { name, binding } | StmtData::AnnAssign { name, binding, .. } => {
+            exp(binding, bound);
+            bound.insert(name.lexeme().into());
+        }
+        StmtData::AugAssign { name, binding, .. } => {
+            exp(binding, bound);
+            bound.insert(name.lexeme().into());
+        }
+        StmtData::MultiAssign { names, bindings } => {
+            for b in bindings {
+                exp(b, bound);
+            }
+            bound.extend(names.iter().map(|n| n.lexeme().to_string()));
+        }
+        StmtData::If {
+            condition,
+            then,
+            else_,
+        } => {
+            exp(condition, bound);
+            exp(then, bound);
+            exp(else_, bound);
+        }
+        StmtData::While { condition, body } => {
+            exp(condition, bound);
+            exp(body, bound);
+        }
+        StmtData::For {
+            var,
+            start,
+            stop,
+            step,
+            body,
+        } => {
+            bound.insert(var.lexeme().into());
+            for e in [Some(start), Some(stop), step.as_ref(), Some(body)]
+                .into_iter()
+                .flatten()
+            {
+                exp(e, bound);
+            }
+        }
+    }
+}
+
+/// 把`e`中的自由变量`free[i]`替换为`%capture(clos, i)`.
+fn substitute(e: &mut Expr, free: &[String], clos: &Token) {
+    let sub = |e: &mut Expr| substitute(e, free, clos);
+    match &mut e.data {
+        ExprData::Name(n) => {
+            if let Some(index) = free.iter().position(|v| v == n.lexeme()) {
+                let closure = Expr::new(ExprData::Name(clos.clone()), e.range);
+                e.data = ExprData::Capture {
+                    closure: closure.into(),
+                    index,
+                };
+            }
+        }
+        ExprData::Int(_)
+        | ExprData::Bool(_)
+        | ExprData::Float(_)
+        | ExprData::Str(_)
+        | ExprData::GlobalValue(_)
+        | ExprData::Collect(_)
+        | ExprData::AllocateList { .. }
+        | ExprData::ListInit { .. } => (),
+        ExprData::Prim { operands: es, .. }
+        | ExprData::Closure { captures: es, .. }
+        | ExprData::Tuple { elems: es } => es.iter_mut().for_each(sub),
+        ExprData::Subscript { value, .. } => sub(value),
+        ExprData::List { elems, repeat, .. } => {
+            elems.iter_mut().for_each(sub);
+            if let Some(n) = repeat {
+                sub(n);
+            }
+        }
+        ExprData::ListRef { list, index } => {
+            sub(list);
+            sub(index);
+        }
+        ExprData::ListSet { list, index, value } => {
+            sub(list);
+            sub(index);
+            sub(value);
+        }
+        ExprData::Call { name: f, args } | ExprData::Apply { closure: f, args } => {
+            sub(f);
+            args.iter_mut().for_each(sub);
+        }
+        ExprData::Capture { closure, .. } => sub(closure),
+        ExprData::Condition {
+            condition,
+            then,
+            else_,
+        } => {
+            sub(condition);
+            sub(then);
+            sub(else_);
+        }
+        ExprData::Block { body, result } => {
+            for s in body {
+                match &mut s.stmt {
+                    StmtData::Expr(e) | StmtData::Return(Some(e)) => sub(e),
+                    StmtData::Assign { binding, .. } => sub(binding),
+                    StmtData::If {
+                        condition,
+                        then,
+                        else_,
+                    } => {
+                        sub(condition);
+                        sub(then);
+                        sub(else_);
+                    }
+                    StmtData::While { condition, body } => {
+                        sub(condition);
+                        sub(body);
+                    }
+                    _ => (),
+                }
+            }
+            if let Some(r) = result {
+                sub(r);
+            }
+        }
+        ExprData::Lambda { .. } => unreachable!("Lambdas have been lifted."),
+    }
+}
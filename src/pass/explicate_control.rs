@@ -1,68 +1,177 @@
 use crate::{
     ast::{Expr, ExprData, Stmt, StmtData},
-    pass::clike::Atom,
     token::Kind,
+    type_checking::Type,
 };
-use std::{
-    collections::{HashMap, LinkedList, VecDeque},
-    error::Error,
-    fmt::format,
-};
+use std::{collections::HashMap, error::Error};
 
-use super::clike;
+use super::clike::{self, Atom, BlockData, CProgrom, FunDef};
 
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 
+/// 把RCO之后的语句序列转换为由基本块组成的`CProgrom`, 每个函数分别处理, 顶层语句组成`main`.
+/// 语句从后往前处理, 每个函数都接收当前语句之后要执行的代码(continuation),
+/// 返回包含当前语句在内的新continuation.
 pub struct ExplicateControl {
     basic_blocks: HashMap<String, Vec<clike::Stmt>>,
     block_num: usize,
-
-    continuation : LinkedList<clike::Stmt>,
+    /// 正在处理的函数, 块名以它为前缀.
+    func: String,
 }
 
 impl ExplicateControl {
-    fn explicate_stmt(&mut self, s: Stmt) {
+    pub fn new() -> Self {
+        Self {
+            basic_blocks: HashMap::new(),
+            block_num: 0,
+            func: CProgrom::MAIN.into(),
+        }
+    }
+
+    pub fn explicate_control(mut self, stmts: Vec<Stmt>) -> Result<CProgrom> {
+        let mut main = vec![];
+        let mut defs = vec![];
+        for s in stmts {
+            match s.stmt {
+                StmtData::Def {
+                    name,
+                    params,
+                    ret,
+                    body,
+                } => {
+                    let params = params
+                        .iter()
+                        .map(|(p, ty)| (p.lexeme().to_string(), ty.checked().clone()))
+                        .collect();
+                    let ret = ret.map_or(Type::Unit, |ty| ty.checked().clone());
+                    defs.push((name.lexeme().to_string(), params, ret, body));
+                }
+                _ => main.push(s),
+            }
+        }
+
+        // main的返回值, 即程序的退出码.
+        let cont = self.explicate_body(main, Self::return_zero())?;
+        let mut res = vec![self.finish_def(vec![], Type::Int, cont)];
+        for (name, params, ret, body) in defs {
+            self.func = name;
+            // 没有返回值的函数执行到末尾时返回.
+            let cont = self.explicate_effect(body, Self::return_zero())?;
+            res.push(self.finish_def(params, ret, cont));
+        }
+        Ok(CProgrom::new(res))
+    }
+
+    fn return_zero() -> Vec<clike::Stmt> {
+        vec![clike::Stmt::Return(clike::Expr::Atom(Atom::Int(0)))]
+    }
+
+    /// `entry`是当前函数的入口块. 取出当前函数的所有块.
+    fn finish_def(
+        &mut self,
+        params: Vec<(String, Type)>,
+        ret: Type,
+        entry: Vec<clike::Stmt>,
+    ) -> FunDef {
+        let name = std::mem::take(&mut self.func);
+        self.basic_blocks
+            .insert(CProgrom::label(&name, CProgrom::ENTRY), entry);
+        let blocks = std::mem::take(&mut self.basic_blocks)
+            .into_iter()
+            .map(|(label, stmts)| (label, BlockData(stmts)))
+            .collect();
+        FunDef {
+            name,
+            params,
+            ret,
+            blocks,
+        }
+    }
+
+    fn explicate_stmt(&mut self, s: Stmt, cont: Vec<clike::Stmt>) -> Result<Vec<clike::Stmt>> {
         use StmtData::*;
         match s.stmt {
-            Expr(e) => {}
-            Assign { name, binding } => {}
+            Expr(e) => self.explicate_effect(e, cont),
+            Assign { name, binding } => self.explicate_assign(binding, name.lexeme().into(), cont),
+            AnnAssign { .. } | AugAssign { .. } | MultiAssign { .. } | For { .. } => {
+                unreachable!("Statement should have been simplified by Shrink.")
+            }
+            Def { .. } => unreachable!("Functions can only be defined at the top level."),
+            // 之后的语句不会被执行.
+            Return(Some(e)) => self.explicate_tail(e),
+            Return(None) => Ok(Self::return_zero()),
             If {
                 condition,
                 then,
                 else_,
-            } => {}
+            } => {
+                let (_, goto_cont) = self.create_block(cont);
+                let then = self.explicate_effect(then, goto_cont.clone())?;
+                let else_ = self.explicate_effect(else_, goto_cont)?;
+                self.explicate_pred(condition, then, else_)
+            }
+            // loop: if cond goto body else goto after; body最后跳回loop.
+            While { condition, body } => {
+                let label = self.gen_block_name("loop");
+                let goto_loop = vec![clike::Stmt::Goto(label.clone())];
+                let (_, goto_after) = self.create_block(cont);
+                let body = self.explicate_effect(body, goto_loop.clone())?;
+                let test = self.explicate_pred(condition, body, goto_after)?;
+                self.basic_blocks.insert(label, test);
+                Ok(goto_loop)
+            }
         }
     }
+
+    /// 依次处理块中的语句, 最后接`cont`.
+    fn explicate_body(
+        &mut self,
+        body: Vec<Stmt>,
+        cont: Vec<clike::Stmt>,
+    ) -> Result<Vec<clike::Stmt>> {
+        let mut cont = cont;
+        for s in body.into_iter().rev() {
+            cont = self.explicate_stmt(s, cont)?;
+        }
+        Ok(cont)
+    }
+
     /// generates code for expressions as statements,
     /// so their result is ignored and only their side effects matter.
-    fn explicate_effect(&mut self, e: Expr) -> Result<()> {
+    fn explicate_effect(&mut self, e: Expr, cont: Vec<clike::Stmt>) -> Result<Vec<clike::Stmt>> {
         use ExprData::*;
-
-        let mut stmts = vec![];
         match e.data {
             Condition {
                 condition,
                 then,
                 else_,
-            } => {}
-            Call { name, args } => match name.get_ident() {
-                Some("input_int") | Some("print") | Some("print_int") => {
-                    let name = clike::Atom::try_from(*name)?;
-                    let mut args1 = vec![];
-                    for a in args {
-                        let arg = clike::Atom::try_from(a)?;
-                        args1.push(arg);
-                    }
-                    let s = clike::Stmt::Exp(clike::Expr::Call { name, args: args1 });
-                    stmts.push(s);
-                }
-                _ => (),
-            },
-            Block { body, result } => {}
-            _ => {}
-        };
-
-        todo!()
+            } => {
+                let (_, goto_cont) = self.create_block(cont);
+                let then = self.explicate_effect(*then, goto_cont.clone())?;
+                let else_ = self.explicate_effect(*else_, goto_cont)?;
+                self.explicate_pred(*condition, then, else_)
+            }
+            // 读取列表的元素时可能因越界而报错, 所以也保留.
+            Call { .. }
+            | Apply { .. }
+            | Collect(_)
+            | ListRef { .. }
+            | ListSet { .. }
+            | ListInit { .. } => {
+                let mut res = vec![clike::Stmt::Exp(clike::Expr::try_from(e)?)];
+                res.extend(cont);
+                Ok(res)
+            }
+            Block { body, result } => {
+                let cont = match result {
+                    Some(r) => self.explicate_effect(*r, cont)?,
+                    None => cont,
+                };
+                self.explicate_body(body, cont)
+            }
+            // 原子, 基本运算, 读取元组以及元组和闭包的分配都没有副作用.
+            _ => Ok(cont),
+        }
     }
 
     /// generates code for expressions on the right-hand side of an assignment.
@@ -70,42 +179,67 @@ impl ExplicateControl {
         &mut self,
         rhs: Expr,
         lhs: String,
-
+        cont: Vec<clike::Stmt>,
     ) -> Result<Vec<clike::Stmt>> {
         use ExprData::*;
-
-        match &rhs.data {
+        match rhs.data {
             Condition {
                 condition,
                 then,
                 else_,
             } => {
-		let c = *condition.to_owned();
-		let assign_then =  self.explicate_assign(*then.clone(),lhs.clone())?;
-		let assign_else =  self.explicate_assign(*else_.clone(),lhs)?;
-		// let goto_then = self.create_block(assign_then);
-                // let goto_else = self.create_block(assign_else);
-		let res =  self.explicate_pred(c, assign_then,assign_else)?;
-
-		for st in res.iter().rev() {
-		    self.continuation.push_front(st.clone());
-		} 
-		Ok(res)
-	    }
+                let (_, goto_cont) = self.create_block(cont);
+                let then = self.explicate_assign(*then, lhs.clone(), goto_cont.clone())?;
+                let else_ = self.explicate_assign(*else_, lhs, goto_cont)?;
+                self.explicate_pred(*condition, then, else_)
+            }
             Block { body, result } => {
-		
-		todo!()
-	    }
+                let result = result.ok_or("Expected a block with a result value.")?;
+                let cont = self.explicate_assign(*result, lhs, cont)?;
+                self.explicate_body(body, cont)
+            }
             _ => {
                 let name = Atom::Name(lhs);
                 let binding = clike::Expr::try_from(rhs)?;
-		let s = clike::Stmt::Assign { name, binding };
-                self.continuation.push_front(s.clone());
-		Ok(vec![s])
+                let mut res = vec![clike::Stmt::Assign { name, binding }];
+                res.extend(cont);
+                Ok(res)
             }
         }
+    }
 
-
+    /// generates code for the value of a `return` statement.
+    fn explicate_tail(&mut self, e: Expr) -> Result<Vec<clike::Stmt>> {
+        use ExprData::*;
+        match e.data {
+            Condition {
+                condition,
+                then,
+                else_,
+            } => {
+                let then = self.explicate_tail(*then)?;
+                let else_ = self.explicate_tail(*else_)?;
+                self.explicate_pred(*condition, then, else_)
+            }
+            Block { body, result } => {
+                let cont = match result {
+                    Some(r) => self.explicate_tail(*r)?,
+                    None => Self::return_zero(),
+                };
+                self.explicate_body(body, cont)
+            }
+            _ => match clike::Expr::try_from(e)? {
+                // 栈上传递的参数在调用者的栈帧中, 不能先释放栈帧.
+                // 经过LimitFunctions之后只有C-like输入中的调用可能超过6个参数.
+                clike::Expr::Call { name, args } if args.len() <= 6 => {
+                    Ok(vec![clike::Stmt::TailCall { name, args }])
+                }
+                clike::Expr::Apply { closure, args } if args.len() < 6 => {
+                    Ok(vec![clike::Stmt::TailApply { closure, args }])
+                }
+                e => Ok(vec![clike::Stmt::Return(e)]),
+            },
+        }
     }
 
     /// generates code for an if expression or statement by analyzing the condition expression.
@@ -116,91 +250,82 @@ impl ExplicateControl {
         els: Vec<clike::Stmt>,
     ) -> Result<Vec<clike::Stmt>> {
         use ExprData::*;
-        match &cond.data {
+        match cond.data {
             Bool(b) => {
-                if *b {
+                if b {
                     Ok(thn)
                 } else {
                     Ok(els)
                 }
             }
-            Prim { op, operands } if op.kind() == Kind::Bang => {
-                let cond = clike::Expr::try_from(cond.clone())?;
-                let goto_then = self.create_block(thn);
-                let goto_else = self.create_block(els);
-                Ok(vec![clike::Stmt::If {
-                    cond,
-                    then: goto_else.0,
-                    else_: goto_then.0,
-                }])
-            }
-            Prim { op, operands }
-                if op.kind() == Kind::EqualEqual
-                    || op.kind() == Kind::BangEqual
-                    || op.kind() == Kind::Less
-                    || op.kind() == Kind::LessEqual
-                    || op.kind() == Kind::Greater
-                    || op.kind() == Kind::GreaterEqual =>
+            Prim { op, mut operands } if op.kind() == Kind::Bang => {
+                self.explicate_pred(operands.remove(0), els, thn)
+            }
+            Prim { ref op, .. }
+                if matches!(
+                    op.kind(),
+                    Kind::EqualEqual
+                        | Kind::BangEqual
+                        | Kind::Less
+                        | Kind::LessEqual
+                        | Kind::Greater
+                        | Kind::GreaterEqual
+                ) =>
             {
-                let cond = clike::Expr::try_from(cond.clone())?;
-                let goto_then = self.create_block(thn);
-                let goto_else = self.create_block(els);
-                Ok(vec![clike::Stmt::If {
-                    cond,
-                    then: goto_then.0,
-                    else_: goto_else.0,
-                }])
+                let cond = clike::Expr::try_from(cond)?;
+                let (then, _) = self.create_block(thn);
+                let (else_, _) = self.create_block(els);
+                Ok(vec![clike::Stmt::If { cond, then, else_ }])
             }
             Condition {
                 condition,
                 then,
                 else_,
             } => {
-                let goto_then = self.create_block(thn);
-                let goto_else = self.create_block(els);
+                let (_, goto_then) = self.create_block(thn);
+                let (_, goto_else) = self.create_block(els);
 
                 let inner_then =
-                    self.explicate_pred(*then.clone(), goto_then.1.clone(), goto_else.1.clone())?;
-                let inner_else =
-		    self.explicate_pred(*else_.clone(), goto_then.1, goto_else.1)?;
+                    self.explicate_pred(*then, goto_then.clone(), goto_else.clone())?;
+                let inner_else = self.explicate_pred(*else_, goto_then, goto_else)?;
 
-                let res = self.explicate_pred(*condition.clone(), inner_then, inner_else);
-                res
+                self.explicate_pred(*condition, inner_then, inner_else)
             }
             Block { body, result } => {
-
-		
-                todo!()
+                let result = result.ok_or("Expected a block with a result value.")?;
+                let cont = self.explicate_pred(*result, thn, els)?;
+                self.explicate_body(body, cont)
             }
-            // Call, Name, Prim(+ -),
+            // 布尔变量
             _ => {
-                let c = clike::Atom::try_from(cond.clone())?;
-                let goto_then = self.create_block(thn);
-                let goto_else = self.create_block(els);
+                let c = clike::Atom::try_from(cond)?;
+                let (then, _) = self.create_block(thn);
+                let (else_, _) = self.create_block(els);
                 let cond = clike::Expr::Prim {
                     op: Kind::EqualEqual,
                     operands: vec![c, clike::Atom::Bool(true)],
+                    range: None,
                 };
-                Ok(vec![clike::Stmt::If {
-                    cond,
-                    then: goto_then.0,
-                    else_: goto_else.0,
-                }])
+                Ok(vec![clike::Stmt::If { cond, then, else_ }])
             }
         }
     }
 
+    /// 把`stmts`放入一个新块, 返回块的标签和跳转到该块的语句.
+    /// 若`stmts`本身就是一条跳转, 则不必新建块.
     fn create_block(&mut self, stmts: Vec<clike::Stmt>) -> (String, Vec<clike::Stmt>) {
         if let Some(clike::Stmt::Goto(label)) = stmts.first() {
             return (label.clone(), stmts);
         }
+        debug_assert!(stmts.last().is_some_and(clike::Stmt::is_tail));
         let label = self.gen_block_name("block");
-        self.basic_blocks.insert(label.clone(), stmts.clone());
+        self.basic_blocks.insert(label.clone(), stmts);
         (label.clone(), vec![clike::Stmt::Goto(label)])
     }
 
     fn gen_block_name(&mut self, prefix: &str) -> String {
-        let label = format!("{}_{}", prefix, self.block_num);
+        let name = format!("{}_{}", prefix, self.block_num);
+        let label = CProgrom::label(&self.func, &name);
         self.block_num += 1;
         label
     }
@@ -0,0 +1,378 @@
+use crate::{
+    ast::{Expr, ExprData, Stmt, StmtData},
+    reporter::ErrorReporter,
+    token::{Kind, Token},
+    type_checking::Type,
+};
+
+use super::select_instructions::ERROR_OVERFLOW;
+
+/// 列表的最大长度. 分配的字节数`8 * len + 8`至多为x86-64用户地址空间的大小2^47,
+/// 所以计算时不会溢出, 运行时扩大堆时也不会溢出.
+pub const MAX_LIST_LEN: i64 = (1 << 44) - 1;
+
+/// 把元组和闭包的分配展开为: 先计算不是原子的元素, 空闲空间不够时调用垃圾回收, 最后分配.
+/// 元素都在检查之前计算, 所以分配之后到初始化完成之前不会再发生垃圾回收.
+/// ```text
+/// (f(x), 1)  =>  {
+///     %alloc0 = f(x)
+///     if (24 < %global(fromspace_end) - %global(free_ptr)) {} else %collect(24)
+///     (%alloc0, 1)
+/// }
+/// ```
+/// 列表的长度可能在运行时才知道, 分配之后再逐个初始化元素:
+/// ```text
+/// [x] * n  =>  {
+///     %alloc0 = n
+///     if %alloc0 > MAX_LIST_LEN {arith_error(0, line, col)} else {}
+///     %alloc1 = 0 if %alloc0 < 0 else %alloc0
+///     %alloc2 = 8 * %alloc1 + 8
+///     if (%alloc2 < %global(fromspace_end) - %global(free_ptr)) {} else %collect(%alloc2)
+///     %alloc3 = %allocate(%alloc1, list[int])
+///     %alloc4 = 0
+///     while %alloc4 < %alloc1 {
+///         %list_init(%alloc3, %alloc4, x)
+///         %alloc4 = %alloc4 + 1
+///     }
+///     %alloc3
+/// }
+/// ```
+pub struct ExposeAllocation<'r> {
+    counter: usize,
+    /// 用于换算运行时错误的位置.
+    reporter: &'r ErrorReporter,
+}
+
+impl<'r> ExposeAllocation<'r> {
+    pub fn new(reporter: &'r ErrorReporter) -> Self {
+        Self {
+            counter: 0,
+            reporter,
+        }
+    }
+
+    fn next_temp(&mut self) -> Token {
+        let tmp = format!("%alloc{}", self.counter);
+        self.counter += 1;
+        Token::new(Kind::Name, tmp, 0, 0)
+    }
+
+    pub fn expose_stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().map(|s| self.expose_stmt(s)).collect()
+    }
+
+    fn expose_stmt(&mut self, s: Stmt) -> Stmt {
+        let stmt = match s.stmt {
+            StmtData::Expr(e) => StmtData::Expr(self.expose_exp(e)),
+            StmtData::Assign { name, binding } => StmtData::Assign {
+                name,
+                binding: self.expose_exp(binding),
+            },
+            StmtData::If {
+                condition,
+                then,
+                else_,
+            } => StmtData::If {
+                condition: self.expose_exp(condition),
+                then: self.expose_exp(then),
+                else_: self.expose_exp(else_),
+            },
+            StmtData::While { condition, body } => StmtData::While {
+                condition: self.expose_exp(condition),
+                body: self.expose_exp(body),
+            },
+            StmtData::Def {
+                name,
+                params,
+                ret,
+                body,
+            } => StmtData::Def {
+                name,
+                params,
+                ret,
+                body: self.expose_exp(body),
+            },
+            StmtData::Return(value) => StmtData::Return(value.map(|e| self.expose_exp(e))),
+            StmtData::AnnAssign { .. }
+            | StmtData::AugAssign { .. }
+            | StmtData::MultiAssign { .. }
+            | StmtData::For { .. } => {
+                unreachable!("Statement should have been simplified by Shrink.")
+            }
+        };
+        Stmt {
+            stmt,
+            range: s.range,
+        }
+    }
+
+    fn expose_exp(&mut self, e: Expr) -> Expr {
+        let range = e.range;
+        let data = match e.data {
+            ExprData::Tuple { elems } => {
+                let elems = elems.into_iter().map(|e| self.expose_exp(e)).collect();
+                return self.allocate(elems, 0, range, |elems| ExprData::Tuple { elems });
+            }
+            // 闭包的第一个字段是函数的地址.
+            ExprData::Closure { func, captures } => {
+                let captures = captures.into_iter().map(|e| self.expose_exp(e)).collect();
+                return self.allocate(captures, 1, range, |captures| ExprData::Closure {
+                    func,
+                    captures,
+                });
+            }
+            ExprData::Prim { op, operands } => ExprData::Prim {
+                op,
+                operands: operands.into_iter().map(|e| self.expose_exp(e)).collect(),
+            },
+            ExprData::Call { name, args } => ExprData::Call {
+                name,
+                args: args.into_iter().map(|e| self.expose_exp(e)).collect(),
+            },
+            ExprData::Apply { closure, args } => ExprData::Apply {
+                closure: self.expose_exp(*closure).into(),
+                args: args.into_iter().map(|e| self.expose_exp(e)).collect(),
+            },
+            ExprData::Capture { closure, index } => ExprData::Capture {
+                closure: self.expose_exp(*closure).into(),
+                index,
+            },
+            ExprData::Subscript { value, index } => ExprData::Subscript {
+                value: self.expose_exp(*value).into(),
+                index,
+            },
+            ExprData::List { elems, repeat, ty } => {
+                let elems = elems.into_iter().map(|e| self.expose_exp(e)).collect();
+                let repeat = repeat.map(|n| self.expose_exp(*n));
+                let ty = ty.expect("List types are set by the type checker.");
+                return self.allocate_list(elems, repeat, ty, range);
+            }
+            ExprData::ListRef { list, index } => ExprData::ListRef {
+                list: self.expose_exp(*list).into(),
+                index: self.expose_exp(*index).into(),
+            },
+            ExprData::ListSet { list, index, value } => ExprData::ListSet {
+                list: self.expose_exp(*list).into(),
+                index: self.expose_exp(*index).into(),
+                value: self.expose_exp(*value).into(),
+            },
+            ExprData::Condition {
+                condition,
+                then,
+                else_,
+            } => ExprData::Condition {
+                condition: self.expose_exp(*condition).into(),
+                then: self.expose_exp(*then).into(),
+                else_: self.expose_exp(*else_).into(),
+            },
+            ExprData::Block { body, result } => ExprData::Block {
+                body: self.expose_stmts(body),
+                result: result.map(|r| self.expose_exp(*r).into()),
+            },
+            ExprData::Lambda { .. } => unreachable!("Lambdas have been lifted."),
+            d => d,
+        };
+        Expr { data, range }
+    }
+
+    /// `fields`是对象中要计算的字段, 此外还有tag和`extra`个字段(如闭包中函数的地址).
+    /// `build`用计算得到的原子构造对象.
+    fn allocate<F>(
+        &mut self,
+        fields: Vec<Expr>,
+        extra: usize,
+        range: (usize, usize),
+        build: F,
+    ) -> Expr
+    where
+        F: FnOnce(Vec<Expr>) -> ExprData,
+    {
+        let bytes = 8 * (fields.len() + 1 + extra);
+        let mut body = vec![];
+        let atoms = fields
+            .into_iter()
+            .map(|e| self.atomize(e, &mut body))
+            .collect();
+        let bytes = Expr::new(ExprData::Int(bytes as i64), range);
+        body.push(reserve(bytes, range));
+
+        Expr::new(
+            ExprData::Block {
+                body,
+                result: Some(Expr::new(build(atoms), range).into()),
+            },
+            range,
+        )
+    }
+
+    /// 列表字面量的元素依次写入; 有`repeat`时, 按次数循环写入所有元素.
+    fn allocate_list(
+        &mut self,
+        elems: Vec<Expr>,
+        repeat: Option<Expr>,
+        elem_ty: Type,
+        range: (usize, usize),
+    ) -> Expr {
+        let new = |data| Expr::new(data, range);
+        let int = |i: usize| new(ExprData::Int(i as i64));
+        let mut body = vec![];
+        let atoms: Vec<Expr> = elems
+            .into_iter()
+            .map(|e| self.atomize(e, &mut body))
+            .collect();
+
+        // 重复次数为负数时得到空列表, 太大时报告溢出.
+        let len = match repeat {
+            Some(n) => {
+                let n = self.atomize(n, &mut body);
+                if !atoms.is_empty() {
+                    let (line, col) = self.reporter.line_col(range.0);
+                    let max = MAX_LIST_LEN / atoms.len() as i64;
+                    let error = new(ExprData::Call {
+                        name: Expr::atom(Token::new(Kind::Name, "arith_error".into(), 0, 0)).into(),
+                        args: [ERROR_OVERFLOW, line as i64, col as i64]
+                            .map(|i| new(ExprData::Int(i)))
+                            .into(),
+                    });
+                    let block = |body| new(ExprData::Block { body, result: None });
+                    body.push(Stmt {
+                        stmt: StmtData::If {
+                            condition: new(prim(
+                                Kind::Greater,
+                                ">",
+                                vec![n.clone(), new(ExprData::Int(max))],
+                            )),
+                            then: block(vec![Stmt::expr().expr(error).build()]),
+                            else_: block(vec![]),
+                        },
+                        range,
+                    });
+                }
+                let count = match atoms.len() {
+                    1 => n.clone(),
+                    k => new(prim(Kind::Star, "*", vec![int(k), n.clone()])),
+                };
+                let len = new(ExprData::Condition {
+                    condition: new(prim(Kind::Less, "<", vec![n, int(0)])).into(),
+                    then: int(0).into(),
+                    else_: count.into(),
+                });
+                self.assign_temp(len, &mut body)
+            }
+            None => int(atoms.len()),
+        };
+        let bytes = match len.const_int() {
+            Some(n) => int(8 * (n as usize + 1)),
+            None => {
+                let size = prim(Kind::Star, "*", vec![int(8), len.clone()]);
+                let bytes = prim(Kind::Plus, "+", vec![new(size), int(8)]);
+                self.assign_temp(new(bytes), &mut body)
+            }
+        };
+        body.push(reserve(bytes, range));
+
+        let ty = Type::List(elem_ty.into());
+        let allocate = new(ExprData::AllocateList {
+            len: len.clone().into(),
+            ty,
+        });
+        let list = self.assign_temp(allocate, &mut body);
+        let init = |index: Expr, value: Expr| {
+            Stmt::expr()
+                .expr(new(ExprData::ListInit {
+                    list: list.clone().into(),
+                    index: index.into(),
+                    value: value.into(),
+                }))
+                .build()
+        };
+
+        if len.const_int().is_some() {
+            for (i, a) in atoms.into_iter().enumerate() {
+                body.push(init(int(i), a));
+            }
+        } else {
+            let counter = self.next_temp();
+            body.push(
+                Stmt::assignment()
+                    .name(counter.clone())
+                    .binding(int(0))
+                    .build(),
+            );
+            let i = Expr::atom(counter.clone());
+            let mut loop_body = vec![];
+            for a in atoms {
+                loop_body.push(init(i.clone(), a));
+                let next = new(prim(Kind::Plus, "+", vec![i.clone(), int(1)]));
+                loop_body.push(
+                    Stmt::assignment()
+                        .name(counter.clone())
+                        .binding(next)
+                        .build(),
+                );
+            }
+            body.push(Stmt {
+                stmt: StmtData::While {
+                    condition: new(prim(Kind::Less, "<", vec![i, len])),
+                    body: new(ExprData::Block {
+                        body: loop_body,
+                        result: None,
+                    }),
+                },
+                range,
+            });
+        }
+
+        new(ExprData::Block {
+            body,
+            result: Some(list.into()),
+        })
+    }
+
+    /// 不是原子的表达式先存入临时变量.
+    fn atomize(&mut self, e: Expr, body: &mut Vec<Stmt>) -> Expr {
+        if e.is_atom() {
+            e
+        } else {
+            self.assign_temp(e, body)
+        }
+    }
+
+    fn assign_temp(&mut self, e: Expr, body: &mut Vec<Stmt>) -> Expr {
+        let tmp = self.next_temp();
+        body.push(Stmt::assignment().name(tmp.clone()).binding(e).build());
+        Expr::atom(tmp)
+    }
+}
+
+fn prim(kind: Kind, lexeme: &str, operands: Vec<Expr>) -> ExprData {
+    ExprData::Prim {
+        op: Token::new(kind, lexeme.into(), 0, 0),
+        operands,
+    }
+}
+
+/// 空闲空间不足`bytes`字节时调用垃圾回收.
+/// 比较`bytes`和剩余的空间, 而不是`free_ptr + bytes`和`fromspace_end`, 后者在`bytes`很大时会溢出.
+fn reserve(bytes: Expr, range: (usize, usize)) -> Stmt {
+    let new = |data| Expr::new(data, range);
+    let global = |name: &str| new(ExprData::GlobalValue(name.into()));
+    let free = new(prim(
+        Kind::Minus,
+        "-",
+        vec![global("fromspace_end"), global("free_ptr")],
+    ));
+    let condition = new(prim(Kind::Less, "<", vec![bytes.clone(), free]));
+    let then = new(ExprData::Block {
+        body: vec![],
+        result: None,
+    });
+    Stmt {
+        stmt: StmtData::If {
+            condition,
+            then,
+            else_: new(ExprData::Collect(bytes.into())),
+        },
+        range,
+    }
+}
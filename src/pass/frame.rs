@@ -6,17 +6,49 @@ pub struct Frame {
     saved_callee: Vec<Reg>,
 
     real_size: usize, //
+    /// 根栈帧中的位置个数. 根栈的栈顶在%r15中, 向高地址增长.
+    roots: usize,
 }
 
 impl Frame {
     pub fn new(saved_callee: HashSet<Reg>) -> Self {
         let real_size = saved_callee.len() * 8;
+        let mut saved_callee: Vec<Reg> = saved_callee.into_iter().collect();
+        saved_callee.sort();
         Self {
-            saved_callee: saved_callee.into_iter().collect(),
+            saved_callee,
             real_size,
+            roots: 0,
         }
     }
 
+    /// 在根栈帧中分配一个位置, 用于存放指向堆的指针.
+    pub fn alloc_root(&mut self) -> Arg {
+        self.roots += 1;
+        Arg::Deref(Reg::R15, -8 * self.roots as i64)
+    }
+
+    /// 分配根栈帧. 其中的位置都先清零, 以免垃圾回收把残留的值当作指针.
+    pub fn alloc_roots(&self) -> Vec<Instr> {
+        if self.roots == 0 {
+            return vec![];
+        }
+        let size = 8 * self.roots as i64;
+        let mut instrs = vec![Instr::Addq(Arg::Imm(size), Arg::Reg(Reg::R15))];
+        for i in 1..=self.roots as i64 {
+            instrs.push(Instr::Movq(Arg::Imm(0), Arg::Deref(Reg::R15, -8 * i)));
+        }
+        instrs
+    }
+
+    pub fn free_roots(&self) -> Vec<Instr> {
+        if self.roots == 0 {
+            return vec![];
+        }
+        let size = 8 * self.roots as i64;
+        vec![Instr::Subq(Arg::Imm(size), Arg::Reg(Reg::R15))]
+    }
+
     pub fn alloc_local(&mut self, size: usize) -> Arg {
         if size == 0 {
             return Arg::Reg(Reg::Rbp);
@@ -27,21 +59,23 @@ impl Frame {
             res <<= 1;
         }
         self.real_size += res;
-        Arg::Deref(Reg::Rbp, -1 * self.real_size as i64)
+        Arg::Deref(Reg::Rbp, -(self.real_size as i64))
     }
 
     pub fn alloc_frame(&self) -> Vec<Instr> {
         let mut instrs = vec![];
         for r in &self.saved_callee {
-            instrs.push(Instr::Pushq(Arg::Reg(r.clone())));
+            instrs.push(Instr::Pushq(Arg::Reg(*r)));
         }
         let offset = self.rsp_offset() as i64;
-        instrs.push(Instr::Subq(Arg::Imm(offset), Arg::Reg(Reg::Rsp)));
+        if offset != 0 {
+            instrs.push(Instr::Subq(Arg::Imm(offset), Arg::Reg(Reg::Rsp)));
+        }
         instrs
     }
 
     fn rsp_offset(&self) -> usize {
-        let align16 = if self.real_size % 16 != 0 {
+        let align16 = if !self.real_size.is_multiple_of(16) {
             (self.real_size / 16 + 1) * 16
         } else {
             self.real_size
@@ -54,10 +88,13 @@ impl Frame {
         let mut instrs = vec![];
 
         let offset = self.rsp_offset() as i64;
-
-        instrs.push(Instr::Addq(Arg::Imm(offset), Arg::Reg(Reg::Rsp)));
-        for r in &self.saved_callee {
-            instrs.push(Instr::Popq(Arg::Reg(r.clone())));
+        // 栈帧大小为0时不需要调整%rsp.
+        if offset != 0 {
+            instrs.push(Instr::Addq(Arg::Imm(offset), Arg::Reg(Reg::Rsp)));
+        }
+        // 与压栈的顺序相反.
+        for r in self.saved_callee.iter().rev() {
+            instrs.push(Instr::Popq(Arg::Reg(*r)));
         }
         instrs
     }
@@ -1,32 +1,33 @@
-use std::collections::VecDeque;
-
 use crate::pass::x86::Reg;
 
 use super::{
     frame::Frame,
-    x86::{Arg, Instr},
+    x86::{Arg, Instr, Program},
 };
 
+/// 运行时初始化时根栈和堆的大小(字节). 堆不够用时由垃圾回收扩大.
+const ROOTSTACK_SIZE: i64 = 1 << 16;
+const HEAP_SIZE: i64 = 1 << 16;
+
 pub struct CodeGen {
+    /// 函数名, 也是序言所在块的标签.
+    name: String,
     frame: Frame,
     prelude: Vec<Instr>,
-    instrs: Vec<Instr>,
+    prog: Program,
     conclusion: Vec<Instr>,
 }
 
 impl CodeGen {
-    pub fn new(instrs: Vec<Instr>, frame: Frame) -> Self {
+    pub fn new(name: &str, prog: Program, frame: Frame) -> Self {
         Self {
+            name: name.into(),
             frame,
-            instrs: instrs,
+            prog,
             prelude: Vec::new(),
             conclusion: Vec::new(),
         }
     }
-    fn restore_frame_pointer(&mut self) {
-        self.conclusion.push(Instr::Popq(Arg::Reg(Reg::Rbp)));
-    }
-
     fn alloc_frame_pointer(&mut self) {
         let instrs = vec![
             Instr::Pushq(Arg::Reg(Reg::Rbp)),
@@ -35,25 +36,78 @@ impl CodeGen {
         self.prelude.extend(instrs);
     }
 
+    fn is_main(&self) -> bool {
+        self.name == Program::PRELUDE
+    }
+
+    /// `main`先初始化运行时, 再把根栈的起始地址放入%r15. %r15是callee-saved寄存器,
+    /// 所以要先保存它, 并多减去8字节使%rsp仍按16字节对齐.
+    fn initialize_runtime(&mut self) {
+        let instrs = vec![
+            Instr::Pushq(Arg::Reg(Reg::R15)),
+            Instr::Subq(Arg::Imm(8), Arg::Reg(Reg::Rsp)),
+            Instr::Movq(Arg::Imm(ROOTSTACK_SIZE), Arg::Reg(Reg::Rdi)),
+            Instr::Movq(Arg::Imm(HEAP_SIZE), Arg::Reg(Reg::Rsi)),
+            Instr::Callq("initialize".into(), 2),
+            Instr::Movq(Arg::Global("rootstack_begin".into()), Arg::Reg(Reg::R15)),
+        ];
+        self.prelude.extend(instrs);
+    }
+
     fn gen_prelude(&mut self) {
         self.alloc_frame_pointer();
         self.prelude.extend(self.frame.alloc_frame());
+        if self.is_main() {
+            self.initialize_runtime();
+        }
+        self.prelude.extend(self.frame.alloc_roots());
+        self.prelude
+            .push(Instr::Jump(Program::entry_of(&self.name)));
+    }
+
+    /// 与序言相反: 释放根栈帧和栈帧, 恢复%rbp, 之后栈顶是返回地址.
+    fn teardown(&self) -> Vec<Instr> {
+        let mut instrs = self.frame.free_roots();
+        if self.is_main() {
+            instrs.push(Instr::Addq(Arg::Imm(8), Arg::Reg(Reg::Rsp)));
+            instrs.push(Instr::Popq(Arg::Reg(Reg::R15)));
+        }
+        instrs.extend(self.frame.free_frame());
+        instrs.push(Instr::Popq(Arg::Reg(Reg::Rbp)));
+        instrs
     }
 
     fn gen_conclusion(&mut self) {
-        self.conclusion.extend(self.frame.free_frame());
-        self.restore_frame_pointer();
+        self.conclusion.extend(self.teardown());
         self.conclusion.push(Instr::Retq);
     }
 
-    pub fn code_gen(mut self) -> Vec<Instr> {
+    /// 尾调用之前释放栈帧, 与结尾一样只保留返回地址.
+    fn gen_tail_calls(&mut self) {
+        let teardown = self.teardown();
+        let prog = std::mem::take(&mut self.prog);
+        self.prog = prog.map_blocks(|instrs| {
+            let mut res = vec![];
+            for inst in instrs {
+                if let Instr::TailJmp(..) = inst {
+                    res.extend(teardown.iter().cloned());
+                }
+                res.push(inst);
+            }
+            res
+        });
+    }
+
+    /// 加上序言和结尾两个块, 并在尾调用之前释放栈帧, 得到完整的程序.
+    pub fn code_gen(mut self) -> Program {
         self.gen_prelude();
         self.gen_conclusion();
+        self.gen_tail_calls();
 
-        let mut instrs = vec![];
-        instrs.extend(self.prelude);
-        instrs.extend(self.instrs);
-        instrs.extend(self.conclusion);
-        instrs
+        let mut prog = self.prog;
+        prog.blocks.insert(self.name.clone(), self.prelude);
+        let conclusion = Program::conclusion_of(&self.name);
+        prog.blocks.insert(conclusion, self.conclusion);
+        prog
     }
 }
@@ -244,10 +244,7 @@ impl<N: Hash + PartialEq + Eq + Clone> UndirectedAdjList<N> {
         list
     }
     pub fn add_vertex(&mut self, v: N) {
-        if !self.out.contains_key(&v) {
-            self.out.insert(v, HashSet::new());
-       //     self.ins.insert(v, HashSet::new());
-        }
+        self.out.entry(v).or_default();
     }
     pub fn add_edge(&mut self, s: N, d: N) -> UEdge<N> {
         self.add_vertex(s.clone());
@@ -0,0 +1,177 @@
+use crate::{
+    ast::{Expr, ExprData, Stmt, StmtData, TypeExpr},
+    token::{Kind, Token},
+    type_checking::Type,
+};
+
+/// 参数都在寄存器中传递, 尾调用才能先释放调用者的栈帧. 超过6个参数时,
+/// 第6个及之后的参数放在一个元组中, 作为第6个参数传递:
+/// ```text
+/// def f(a, b, c, d, e, g, h) { body }  =>  def f(a, b, c, d, e, %args) {
+///                                              g = %args[0]
+///                                              h = %args[1]
+///                                              body
+///                                          }
+/// f(1, 2, 3, 4, 5, 6, 7)  =>  f(1, 2, 3, 4, 5, (6, 7))
+/// ```
+/// 闭包本身也是参数, 调用闭包时一起计算.
+pub struct LimitFunctions;
+
+/// 在寄存器中传递的参数的个数.
+const MAX_REG_ARGS: usize = 6;
+
+/// 打包其余参数的元组.
+const ARGS_PARAM: &str = "%args";
+
+impl LimitFunctions {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn limit_stmts(&self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts.into_iter().map(|s| self.limit_stmt(s)).collect()
+    }
+
+    fn limit_stmt(&self, s: Stmt) -> Stmt {
+        let stmt = match s.stmt {
+            StmtData::Expr(e) => StmtData::Expr(self.limit_exp(e)),
+            StmtData::Assign { name, binding } => StmtData::Assign {
+                name,
+                binding: self.limit_exp(binding),
+            },
+            StmtData::If {
+                condition,
+                then,
+                else_,
+            } => StmtData::If {
+                condition: self.limit_exp(condition),
+                then: self.limit_exp(then),
+                else_: self.limit_exp(else_),
+            },
+            StmtData::While { condition, body } => StmtData::While {
+                condition: self.limit_exp(condition),
+                body: self.limit_exp(body),
+            },
+            StmtData::Def {
+                name,
+                mut params,
+                ret,
+                body,
+            } => {
+                let mut body = self.limit_exp(body);
+                if params.len() > MAX_REG_ARGS {
+                    let rest = params.split_off(MAX_REG_ARGS - 1);
+                    let args = Token::new(Kind::Name, ARGS_PARAM.into(), 0, 0);
+                    let tys = rest.iter().map(|(_, ty)| ty.checked().clone()).collect();
+                    params.push((args.clone(), TypeExpr::Checked(Type::Tuple(tys))));
+                    let ExprData::Block { body: stmts, .. } = &mut body.data else {
+                        unreachable!("Function bodies are blocks.")
+                    };
+                    let unpack = rest.into_iter().enumerate().map(|(i, (p, _))| {
+                        let index = Expr::new(ExprData::Int(i as i64), body.range);
+                        Stmt::assignment()
+                            .name(p)
+                            .binding(
+                                Expr::subscript()
+                                    .value(Expr::atom(args.clone()))
+                                    .index(index)
+                                    .build(),
+                            )
+                            .build()
+                    });
+                    stmts.splice(0..0, unpack);
+                }
+                StmtData::Def {
+                    name,
+                    params,
+                    ret,
+                    body,
+                }
+            }
+            StmtData::Return(value) => StmtData::Return(value.map(|e| self.limit_exp(e))),
+            StmtData::AnnAssign { .. }
+            | StmtData::AugAssign { .. }
+            | StmtData::MultiAssign { .. }
+            | StmtData::For { .. } => {
+                unreachable!("Statement should have been simplified by Shrink.")
+            }
+        };
+        Stmt {
+            stmt,
+            range: s.range,
+        }
+    }
+
+    fn limit_exp(&self, e: Expr) -> Expr {
+        let range = e.range;
+        let data = match e.data {
+            ExprData::Call { name, args } => ExprData::Call {
+                name,
+                args: self.pack(args, 0, range),
+            },
+            ExprData::Apply { closure, args } => ExprData::Apply {
+                closure: self.limit_exp(*closure).into(),
+                args: self.pack(args, 1, range),
+            },
+            ExprData::Prim { op, operands } => ExprData::Prim {
+                op,
+                operands: operands.into_iter().map(|e| self.limit_exp(e)).collect(),
+            },
+            ExprData::Closure { func, captures } => ExprData::Closure {
+                func,
+                captures: captures.into_iter().map(|e| self.limit_exp(e)).collect(),
+            },
+            ExprData::Capture { closure, index } => ExprData::Capture {
+                closure: self.limit_exp(*closure).into(),
+                index,
+            },
+            ExprData::Tuple { elems } => ExprData::Tuple {
+                elems: elems.into_iter().map(|e| self.limit_exp(e)).collect(),
+            },
+            ExprData::Subscript { value, index } => ExprData::Subscript {
+                value: self.limit_exp(*value).into(),
+                index,
+            },
+            ExprData::List { elems, repeat, ty } => ExprData::List {
+                elems: elems.into_iter().map(|e| self.limit_exp(e)).collect(),
+                repeat: repeat.map(|n| self.limit_exp(*n).into()),
+                ty,
+            },
+            ExprData::ListRef { list, index } => ExprData::ListRef {
+                list: self.limit_exp(*list).into(),
+                index: self.limit_exp(*index).into(),
+            },
+            ExprData::ListSet { list, index, value } => ExprData::ListSet {
+                list: self.limit_exp(*list).into(),
+                index: self.limit_exp(*index).into(),
+                value: self.limit_exp(*value).into(),
+            },
+            ExprData::Condition {
+                condition,
+                then,
+                else_,
+            } => ExprData::Condition {
+                condition: self.limit_exp(*condition).into(),
+                then: self.limit_exp(*then).into(),
+                else_: self.limit_exp(*else_).into(),
+            },
+            ExprData::Block { body, result } => ExprData::Block {
+                body: self.limit_stmts(body),
+                result: result.map(|r| self.limit_exp(*r).into()),
+            },
+            ExprData::Lambda { .. } => unreachable!("Lambdas have been lifted."),
+            d => d,
+        };
+        Expr { data, range }
+    }
+
+    /// 调用时已有`extra`个参数(闭包本身)在`args`之前.
+    fn pack(&self, args: Vec<Expr>, extra: usize, range: (usize, usize)) -> Vec<Expr> {
+        let mut args: Vec<Expr> = args.into_iter().map(|a| self.limit_exp(a)).collect();
+        if args.len() + extra > MAX_REG_ARGS {
+            let rest = args.split_off(MAX_REG_ARGS - 1 - extra);
+            args.push(Expr::new(ExprData::Tuple { elems: rest }, range));
+        }
+        args
+    }
+}
@@ -1,26 +1,24 @@
 use std::{
-    collections::{hash_set::Iter, HashSet},
+    collections::{hash_set::Iter, HashMap, HashSet, VecDeque},
     fmt::Display,
 };
 
-use crate::pass::x86::Reg;
-
-use super::x86::{Arg, Instr, ReadWriteSet};
-
-enum Location {
-    Reg(Reg),
-    Var(String),
-    Stack(isize),
-}
+use super::{
+    graph::DirectedAdjList,
+    x86::{Arg, Instr, Label, Program, ReadWriteSet, Reg},
+};
 
 pub struct UncoverLive {}
 
+/// 每个块中的指令及其live-after集合.
+pub type LiveBlocks = HashMap<Label, Vec<(Instr, LiveAfter)>>;
+
 pub struct LiveAfter(HashSet<Arg>);
 
 impl Display for LiveAfter {
     fn fmt(&self, f: &'_ mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use Arg::*;
-        write!(f, "{}", '{')?;
+        write!(f, "{{")?;
         for a in &self.0 {
             match a {
                 Reg(r) => write!(f, "{},", r)?,
@@ -28,41 +26,87 @@ impl Display for LiveAfter {
                 _ => unreachable!(),
             }
         }
-        write!(f, "{}", '}')
+        write!(f, "}}")
     }
 }
 
 impl LiveAfter {
-    pub fn new() -> Self {
-        Self(HashSet::new())
-    }
-
-    pub fn iter(&self) -> Iter<Arg> {
+    pub fn iter(&self) -> Iter<'_, Arg> {
         self.0.iter()
     }
 }
 
 impl UncoverLive {
-    pub fn uncover_live(mut instrs: Vec<Instr>) -> Vec<(Instr, LiveAfter)> {
-        let mut live_before = HashSet::new();
+    /// 分析一个函数. 在控制流图上迭代求不动点: 循环使得块之间可能互为后继, 没有可用的拓扑序.
+    /// 每个块的live-before集合初始为空, 某个块的集合变大后, 重新分析它的所有前驱,
+    /// 直到没有集合再变化.
+    pub fn uncover_live(prog: &Program) -> LiveBlocks {
+        let mut cfg = DirectedAdjList::empty();
+        for (label, instrs) in &prog.blocks {
+            cfg.add_vertex(label.clone());
+            for inst in instrs {
+                if let Instr::Jump(target) | Instr::JmpIf(_, target) = inst {
+                    cfg.add_edge(label.clone(), target.clone());
+                }
+            }
+        }
 
-        let mut res = vec![];
-        if let Some(last) = instrs.pop() {
-            live_before = last.read_set();
-            res.push((last, LiveAfter::new()));
+        // 不在函数中的跳转目标只有结尾块, 其中%rax存放返回值, %rsp用于恢复栈帧.
+        let mut live_before_block: HashMap<Label, HashSet<Arg>> = HashMap::new();
+        for target in cfg.vertices() {
+            let live = if prog.blocks.contains_key(&target) {
+                HashSet::new()
+            } else {
+                HashSet::from([Arg::Reg(Reg::Rax), Arg::Reg(Reg::Rsp)])
+            };
+            live_before_block.insert(target, live);
+        }
+
+        let preds = cfg.transpose();
+        // 从后往前的块先分析, 无环时每个块只需分析一次.
+        let mut worklist: VecDeque<Label> = prog.block_order().into_iter().rev().collect();
+        let mut res = HashMap::new();
+        while let Some(label) = worklist.pop_front() {
+            let Some(instrs) = prog.blocks.get(&label) else {
+                continue;
+            };
+            let (live_before, block) = Self::uncover_block(instrs, &live_before_block);
+            res.insert(label.clone(), block);
+            if live_before_block.get(&label) != Some(&live_before) {
+                live_before_block.insert(label.clone(), live_before);
+                for p in preds.adjacent(&label).unwrap_or_default() {
+                    if !worklist.contains(&p) {
+                        worklist.push_back(p);
+                    }
+                }
+            }
         }
+        res
+    }
+
+    /// 从块的最后一条指令向前计算每条指令的live-after集合, 同时返回整个块的live-before集合.
+    fn uncover_block(
+        instrs: &[Instr],
+        live_before_block: &HashMap<Label, HashSet<Arg>>,
+    ) -> (HashSet<Arg>, Vec<(Instr, LiveAfter)>) {
+        let mut live = HashSet::new();
+        let mut res = vec![];
 
-        for inst in instrs.into_iter().rev() {
-            let previous_live_before = live_before.clone();
-            res.push((inst.clone(), LiveAfter(previous_live_before.clone())));
+        for inst in instrs.iter().rev() {
+            res.push((inst.clone(), LiveAfter(live.clone())));
 
-            let tmp: HashSet<Arg> = previous_live_before
-                .difference(&inst.write_set())
-                .cloned()
-                .collect();
-            live_before = tmp.union(&inst.read_set()).cloned().collect();
+            // 条件跳转之后的live集合还要并上不跳转时(即后续指令)的live集合.
+            let read_set = match inst {
+                Instr::Jump(target) | Instr::JmpIf(_, target) => {
+                    live_before_block.get(target).cloned().unwrap_or_default()
+                }
+                i => i.read_set(),
+            };
+            let tmp: HashSet<Arg> = live.difference(&inst.write_set()).cloned().collect();
+            live = tmp.union(&read_set).cloned().collect();
         }
 
-        res.into_iter().rev().collect()
+        res.reverse();
+        (live, res)
     }
 }
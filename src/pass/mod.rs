@@ -2,12 +2,18 @@ pub mod allocate;
 pub mod assign_homes;
 pub mod build_interference;
 pub mod clike;
+pub mod clike_parser;
+pub mod closure_conversion;
 pub mod explicate_control;
-mod frame;
+pub mod expose_allocation;
+pub mod frame;
 pub mod gen;
+#[allow(dead_code)]
+mod graph;
+pub mod limit_functions;
 pub mod liveness;
 pub mod patch;
 pub mod rco;
 pub mod select_instructions;
 pub mod shrink;
-mod x86;
+pub mod x86;
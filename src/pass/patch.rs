@@ -1,21 +1,26 @@
-use super::x86::{Arg, Instr, Reg};
+use super::x86::{Arg, Instr, Program};
 
 pub struct PatchInstructions {
-    instrs: Vec<Instr>,
+    prog: Program,
 }
 
 impl PatchInstructions {
-    pub fn new(instrs: Vec<Instr>) -> Self {
-        Self { instrs }
+    pub fn new(prog: Program) -> Self {
+        Self { prog }
     }
 
-    pub fn patch_instructions(self) -> Vec<Instr> {
-        let mut res = vec![];
-
-        for inst in self.instrs {
-            res.extend(Self::patch_instr(inst));
-        }
-        res
+    pub fn patch_instructions(self) -> Program {
+        self.prog.map_blocks(|instrs| {
+            let mut res = vec![];
+            for inst in instrs {
+                res.extend(
+                    Self::patch_instr(inst)
+                        .into_iter()
+                        .flat_map(Self::widen_imm),
+                );
+            }
+            res
+        })
     }
 
     fn patch_instr(inst: Instr) -> Vec<Instr> {
@@ -37,6 +42,41 @@ impl PatchInstructions {
                     Movq(Reg(Rax), d),
                 ]
             }
+            // imulq的目标必须是寄存器.
+            Imulq(s, d) if d.is_mem() => {
+                vec![
+                    Movq(d.clone(), Reg(Rax)),
+                    Imulq(s, Reg(Rax)),
+                    Movq(Reg(Rax), d),
+                ]
+            }
+            // idivq的操作数不能是立即数. 此时%rax和%rdx都被占用, 所以用%r11.
+            Idivq(Imm(i)) => {
+                vec![Movq(Imm(i), Reg(R11)), Idivq(Reg(R11))]
+            }
+            Xorq(s, d) if s.is_mem() && d.is_mem() => {
+                vec![
+                    Movq(d.clone(), Reg(Rax)),
+                    Xorq(s, Reg(Rax)),
+                    Movq(Reg(Rax), d),
+                ]
+            }
+            // cmpq的第二个操作数不能是立即数.
+            Cmpq(s, d) if matches!(d, Imm(_)) || (s.is_mem() && d.is_mem()) => {
+                vec![Movq(d, Reg(Rax)), Cmpq(s, Reg(Rax))]
+            }
+            // movzbq的目标必须是寄存器.
+            Movzbq(s, d) if d.is_mem() => {
+                vec![Movzbq(s, Reg(Rax)), Movq(Reg(Rax), d)]
+            }
+            // leaq的目标必须是寄存器.
+            Leaq(s, d) if d.is_mem() => {
+                vec![Leaq(s, Reg(Rax)), Movq(Reg(Rax), d)]
+            }
+            // 释放栈帧时只有%rax不会被改写.
+            TailJmp(a, n) if a != Reg(Rax) => {
+                vec![Movq(a, Reg(Rax)), TailJmp(Reg(Rax), n)]
+            }
             Movq(s, d) if s == d => {
                 vec![]
             }
@@ -46,4 +86,25 @@ impl PatchInstructions {
             o => vec![o],
         }
     }
+
+    /// 除`movabsq`外, 指令中的立即数都是符号扩展的32位数. 更宽的立即数先用`movabsq`放入寄存器,
+    /// 一般是%rax, 目标就是%rax时用%r11.
+    fn widen_imm(inst: Instr) -> Vec<Instr> {
+        use super::x86::Reg::*;
+        use Arg::*;
+        use Instr::*;
+        let is_wide = |a: &Arg| matches!(a, Imm(i) if i32::try_from(*i).is_err());
+        let tmp = |d: &Arg| if *d == Reg(Rax) { Reg(R11) } else { Reg(Rax) };
+        match inst {
+            Movq(s, Reg(r)) if is_wide(&s) => vec![Movabsq(s, Reg(r))],
+            Movq(s, d) if is_wide(&s) => vec![Movabsq(s, Reg(Rax)), Movq(Reg(Rax), d)],
+            Addq(s, d) if is_wide(&s) => vec![Movabsq(s, tmp(&d)), Addq(tmp(&d), d)],
+            Subq(s, d) if is_wide(&s) => vec![Movabsq(s, tmp(&d)), Subq(tmp(&d), d)],
+            Imulq(s, d) if is_wide(&s) => vec![Movabsq(s, tmp(&d)), Imulq(tmp(&d), d)],
+            Xorq(s, d) if is_wide(&s) => vec![Movabsq(s, tmp(&d)), Xorq(tmp(&d), d)],
+            Cmpq(s, d) if is_wide(&s) => vec![Movabsq(s, tmp(&d)), Cmpq(tmp(&d), d)],
+            Pushq(a) if is_wide(&a) => vec![Movabsq(a, Reg(Rax)), Pushq(Reg(Rax))],
+            o => vec![o],
+        }
+    }
 }
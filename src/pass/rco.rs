@@ -50,17 +50,75 @@ impl RemoveComplexOperands {
                 then,
                 else_,
             } => {
-                // let then = self.rco_stmts(then);
-                // let else_ = self.rco_stmts(else_);
-                let then = self.rco_exp(then).0;
-                let else_ = self.rco_exp(else_).0;
-                vec![Stmt {
+                let (condition, mut stmts) = self.rco_condition(condition);
+                let then = self.rco_block(then);
+                let else_ = self.rco_block(else_);
+                stmts.push(Stmt {
                     stmt: StmtData::If {
                         condition,
                         then,
                         else_,
                     },
                     range,
+                });
+                stmts
+            }
+            StmtData::Def {
+                name,
+                params,
+                ret,
+                body,
+            } => {
+                let body = self.rco_block(body);
+                vec![Stmt {
+                    stmt: StmtData::Def {
+                        name,
+                        params,
+                        ret,
+                        body,
+                    },
+                    range,
+                }]
+            }
+            StmtData::Return(value) => {
+                let (value, mut stmts) = match value {
+                    Some(e) => {
+                        let (e, stmts) = self.rco_exp(e);
+                        (Some(e), stmts)
+                    }
+                    None => (None, vec![]),
+                };
+                stmts.push(Stmt {
+                    stmt: StmtData::Return(value),
+                    range,
+                });
+                stmts
+            }
+            StmtData::AnnAssign { .. }
+            | StmtData::AugAssign { .. }
+            | StmtData::MultiAssign { .. }
+            | StmtData::For { .. } => {
+                unreachable!("Statement should have been simplified by Shrink.")
+            }
+            // 条件在每次循环时都要重新求值, 其临时变量不能提到循环外面.
+            StmtData::While { condition, body } => {
+                let cond_range = condition.range();
+                let (condition, cond_stmts) = self.rco_condition(condition);
+                let condition = if cond_stmts.is_empty() {
+                    condition
+                } else {
+                    Expr {
+                        data: ExprData::Block {
+                            body: cond_stmts,
+                            result: Some(condition.into()),
+                        },
+                        range: cond_range,
+                    }
+                };
+                let body = self.rco_block(body);
+                vec![Stmt {
+                    stmt: StmtData::While { condition, body },
+                    range,
                 }]
             }
         }
@@ -82,6 +140,134 @@ impl RemoveComplexOperands {
                     stmts,
                 )
             }
+            ExprData::Apply { closure, args } => {
+                let (mut atoms, stmts) =
+                    self.rco_operands([*closure].into_iter().chain(args).collect());
+                let closure = atoms.remove(0).into();
+                (
+                    Expr {
+                        data: ExprData::Apply {
+                            closure,
+                            args: atoms,
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::Closure { func, captures } => {
+                let (captures, stmts) = self.rco_operands(captures);
+                (
+                    Expr {
+                        data: ExprData::Closure { func, captures },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::Capture { closure, index } => {
+                let (mut atoms, stmts) = self.rco_operands(vec![*closure]);
+                (
+                    Expr {
+                        data: ExprData::Capture {
+                            closure: atoms.remove(0).into(),
+                            index,
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::Tuple { elems } => {
+                let (elems, stmts) = self.rco_operands(elems);
+                (
+                    Expr {
+                        data: ExprData::Tuple { elems },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::Subscript { value, index } => {
+                let (mut atoms, stmts) = self.rco_operands(vec![*value]);
+                (
+                    Expr {
+                        data: ExprData::Subscript {
+                            value: atoms.remove(0).into(),
+                            index,
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::ListRef { list, index } => {
+                let (atoms, stmts) = self.rco_operands(vec![*list, *index]);
+                let [list, index] = atoms.try_into().unwrap();
+                (
+                    Expr {
+                        data: ExprData::ListRef {
+                            list: list.into(),
+                            index: index.into(),
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::ListSet { list, index, value } => {
+                let (atoms, stmts) = self.rco_operands(vec![*list, *index, *value]);
+                let [list, index, value] = atoms.try_into().unwrap();
+                (
+                    Expr {
+                        data: ExprData::ListSet {
+                            list: list.into(),
+                            index: index.into(),
+                            value: value.into(),
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::ListInit { list, index, value } => {
+                let (atoms, stmts) = self.rco_operands(vec![*list, *index, *value]);
+                let [list, index, value] = atoms.try_into().unwrap();
+                (
+                    Expr {
+                        data: ExprData::ListInit {
+                            list: list.into(),
+                            index: index.into(),
+                            value: value.into(),
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::AllocateList { len, ty } => {
+                let (mut atoms, stmts) = self.rco_operands(vec![*len]);
+                (
+                    Expr {
+                        data: ExprData::AllocateList {
+                            len: atoms.remove(0).into(),
+                            ty,
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
+            ExprData::Collect(bytes) => {
+                let (mut atoms, stmts) = self.rco_operands(vec![*bytes]);
+                (
+                    Expr {
+                        data: ExprData::Collect(atoms.remove(0).into()),
+                        range,
+                    },
+                    stmts,
+                )
+            }
             ExprData::Prim { op, operands } => {
                 let (operands, stmts) = self.rco_operands(operands);
                 (
@@ -92,6 +278,26 @@ impl RemoveComplexOperands {
                     stmts,
                 )
             }
+            ExprData::Condition {
+                condition,
+                then,
+                else_,
+            } => {
+                let (condition, stmts) = self.rco_condition(*condition);
+                let then = self.rco_block(*then);
+                let else_ = self.rco_block(*else_);
+                (
+                    Expr {
+                        data: ExprData::Condition {
+                            condition: condition.into(),
+                            then: then.into(),
+                            else_: else_.into(),
+                        },
+                        range,
+                    },
+                    stmts,
+                )
+            }
             ExprData::Block { body, result } => {
                 let mut body = self.rco_stmts(body);
                 let result = if let Some(r) = result {
@@ -114,6 +320,39 @@ impl RemoveComplexOperands {
         }
     }
 
+    /// 条件总会被求值, 其临时变量放在外面; 但条件本身可以是比较或嵌套的条件表达式.
+    /// 函数调用和读取闭包, 元组或列表的结果要先存入临时变量.
+    fn rco_condition(&mut self, cond: Expr) -> (Expr, Vec<Stmt>) {
+        if let ExprData::Call { .. }
+        | ExprData::Apply { .. }
+        | ExprData::Capture { .. }
+        | ExprData::Subscript { .. }
+        | ExprData::ListRef { .. } = cond.data
+        {
+            let (mut atoms, stmts) = self.rco_operands(vec![cond]);
+            (atoms.remove(0), stmts)
+        } else {
+            self.rco_exp(cond)
+        }
+    }
+
+    /// 分支不一定被执行, 所以它的临时变量必须留在分支内部.
+    fn rco_block(&mut self, e: Expr) -> Expr {
+        let range = e.range();
+        let (e, stmts) = self.rco_exp(e);
+        if stmts.is_empty() {
+            e
+        } else {
+            Expr {
+                data: ExprData::Block {
+                    body: stmts,
+                    result: Some(e.into()),
+                },
+                range,
+            }
+        }
+    }
+
     fn rco_operands(&mut self, exprs: Vec<Expr>) -> (Vec<Expr>, Vec<Stmt>) {
         let mut new_args = vec![];
         let mut stmts = vec![];
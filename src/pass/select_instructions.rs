@@ -1,126 +1,516 @@
-use crate::{
-    ast::{Expr, ExprData, Stmt, StmtData},
-    token::Kind,
+use std::collections::HashMap;
+
+use crate::{reporter::ErrorReporter, token::Kind, type_checking::Type};
+
+use super::{
+    clike::{Atom, BlockData, CProgrom, Expr, FunDef, Stmt},
+    x86::*,
 };
 
-use super::x86::*;
+/// 运行时`arith_error`的第一个参数.
+pub const ERROR_OVERFLOW: i64 = 0;
+pub const ERROR_DIVIDE_BY_ZERO: i64 = 1;
+pub const ERROR_ZERO_STEP: i64 = 2;
+
+/// 堆上对象的tag, 格式与运行时(runtime.c)相同: 最低位为1, 接着6位是长度,
+/// 再往上的第i位表示第i个字段是否是指针.
+fn tag(len: i64, ptr_mask: i64) -> i64 {
+    1 | len << 1 | ptr_mask << 7
+}
 
-pub struct SelectInstructions {}
+/// 列表的tag中除长度外的部分: 第62位为1, 第1位表示元素是否是指针. 长度从第2位开始.
+fn list_tag(elem_is_ptr: bool) -> i64 {
+    1 << 62 | (elem_is_ptr as i64) << 1 | 1
+}
 
-impl SelectInstructions {
-    pub fn new() -> Self {
-        Self {}
+pub struct SelectInstructions<'r> {
+    /// 用于把表达式的位置换算为运行时错误中的行列号.
+    reporter: &'r ErrorReporter,
+    /// 为算术运算生成溢出和除零检查. 列表下标的检查总会生成.
+    checked_arith: bool,
+    /// 检查失败时跳转到的块, 每个块调用一次运行时中报告错误的函数.
+    error_blocks: HashMap<Label, Vec<Instr>>,
+    /// 正在处理的函数.
+    func: Label,
+    /// 每个函数的类型.
+    sigs: HashMap<Label, Type>,
+    /// 正在处理的函数中每个变量的类型, 用于生成tag.
+    types: HashMap<String, Type>,
+    /// 正在处理的函数中的字符串字面量, 放在只读数据段中.
+    strings: Vec<(Label, String)>,
+}
+
+impl<'r> SelectInstructions<'r> {
+    pub fn new(reporter: &'r ErrorReporter) -> Self {
+        Self {
+            reporter,
+            checked_arith: false,
+            error_blocks: HashMap::new(),
+            func: CProgrom::MAIN.into(),
+            sigs: HashMap::new(),
+            types: HashMap::new(),
+            strings: vec![],
+        }
     }
 
-    pub fn select_stmts(&self, stmts: Vec<Stmt>) -> Vec<Instr> {
-        let mut res: Vec<Instr> = vec![];
-        for s in stmts {
-            res.extend(self.select_stmt(s));
+    pub fn checked_arith(mut self) -> Self {
+        self.checked_arith = true;
+        self
+    }
+
+    /// 每个函数分别生成一个`Program`, 之后的pass也分别处理每个函数.
+    pub fn select_program(mut self, prog: CProgrom) -> Vec<(Label, Program)> {
+        self.sigs = prog.signatures();
+        prog.defs
+            .into_iter()
+            .map(|def| (def.name.clone(), self.select_def(def)))
+            .collect()
+    }
+
+    fn select_def(&mut self, def: FunDef) -> Program {
+        self.func = def.name.clone();
+        self.types = def.var_types(&self.sigs);
+        let entry = def.entry();
+        let mut blocks: HashMap<Label, Vec<Instr>> = HashMap::new();
+        // 按标签的顺序处理, 使生成的错误块和字符串的编号是确定的.
+        let mut def_blocks: Vec<_> = def.blocks.into_iter().collect();
+        def_blocks.sort_by(|a, b| a.0.cmp(&b.0));
+        for (label, BlockData(stmts)) in def_blocks {
+            let mut instrs = vec![];
+            if label == entry {
+                instrs.extend(Self::load_params(&def.params));
+            }
+            instrs.extend(stmts.into_iter().flat_map(|s| self.select_stmt(s)));
+            blocks.insert(label, instrs);
         }
-        res
+        blocks.extend(std::mem::take(&mut self.error_blocks));
+        let mut prog = Program::new(blocks);
+        prog.pointers = self
+            .types
+            .iter()
+            .filter(|(_, ty)| ty.is_heap())
+            .map(|(v, _)| v.clone())
+            .collect();
+        prog.strings = std::mem::take(&mut self.strings);
+        prog
     }
 
-    fn select_stmt(&self, s: Stmt) -> Vec<Instr> {
+    /// 前6个参数在寄存器中, 其余参数由调用者从右往左压栈, 位于返回地址和旧的%rbp之上.
+    fn load_params(params: &[(String, Type)]) -> Vec<Instr> {
         let mut instrs = vec![];
-        match s.stmt {
-            StmtData::Assign { name, binding } => {
-                let dest = Arg::Var(name.lexeme().into());
-                match binding.data {
-                    ExprData::Name(v) => {
-                        let src = Arg::Var(v.lexeme().into());
-                        instrs.push(Instr::Movq(src, dest));
-                    }
-                    ExprData::Int(i) => {
-                        let src = Arg::Imm(i);
-                        instrs.push(Instr::Movq(src, dest));
+        for ((p, _), r) in params.iter().zip(Reg::args_passing()) {
+            instrs.push(Instr::Movq(Arg::Reg(r), Arg::Var(p.clone())));
+        }
+        for ((p, _), i) in params.iter().skip(6).zip(0..) {
+            let src = Arg::Deref(Reg::Rbp, 16 + 8 * i);
+            instrs.push(Instr::Movq(src, Arg::Var(p.clone())));
+        }
+        instrs
+    }
+
+    /// 原子的值是否是指向堆上对象的指针.
+    fn is_pointer(&self, a: &Atom) -> bool {
+        match a {
+            Atom::Name(v) => self.types.get(v).is_some_and(Type::is_heap),
+            _ => false,
+        }
+    }
+
+    /// 在`free_ptr`处分配对象, 写入tag和各个字段, 再把地址存入`dest`.
+    /// 对象的地址暂存在%r11中. 字段可以是函数的地址.
+    fn allocate(&self, fields: Vec<Arg>, ptr_mask: i64, dest: Arg) -> Vec<Instr> {
+        let len = fields.len() as i64;
+        let free_ptr = Arg::Global("free_ptr".into());
+        let mut instrs = vec![
+            Instr::Movq(free_ptr.clone(), Arg::Reg(Reg::R11)),
+            Instr::Addq(Arg::Imm(8 * (len + 1)), free_ptr),
+        ];
+        // 大于32位的tag由PatchInstructions经%rax写入.
+        let tag = Arg::Imm(tag(len, ptr_mask));
+        instrs.push(Instr::Movq(tag, Arg::Deref(Reg::R11, 0)));
+        for (f, i) in fields.into_iter().zip(1..) {
+            let field = Arg::Deref(Reg::R11, 8 * i);
+            if let Arg::FunRef(_) = f {
+                instrs.push(Instr::Leaq(f, Arg::Reg(Reg::Rax)));
+                instrs.push(Instr::Movq(Arg::Reg(Reg::Rax), field));
+            } else {
+                instrs.push(Instr::Movq(f, field));
+            }
+        }
+        instrs.push(Instr::Movq(Arg::Reg(Reg::R11), dest));
+        instrs
+    }
+
+    /// 生成调用运行时中报告错误的函数`func`的块, 返回其标签.
+    /// 参数是`args`和出错位置的行列号. `args`可以是寄存器, 按顺序传入, 不会被先改写.
+    fn error_block(&mut self, func: &str, args: Vec<Arg>, range: Option<(usize, usize)>) -> Label {
+        let (line, col) = range.map_or((0, 0), |r| self.reporter.line_col(r.0));
+        let name = format!("{}_{}", func, self.error_blocks.len());
+        let label = CProgrom::label(&self.func, &name);
+        let args: Vec<Arg> = args
+            .into_iter()
+            .chain([Arg::Imm(line as i64), Arg::Imm(col as i64)])
+            .collect();
+        let mut instrs: Vec<Instr> = args
+            .iter()
+            .zip(Reg::args_passing())
+            .map(|(a, r)| Instr::Movq(a.clone(), Arg::Reg(r)))
+            .collect();
+        // 不会返回.
+        instrs.push(Instr::Callq(func.into(), args.len()));
+        self.error_blocks.insert(label.clone(), instrs);
+        label
+    }
+
+    /// 算术运算出错时跳转到的块. 未开启检查时返回`None`.
+    fn arith_error_block(&mut self, kind: i64, range: Option<(usize, usize)>) -> Option<Label> {
+        if !self.checked_arith {
+            return None;
+        }
+        Some(self.error_block("arith_error", vec![Arg::Imm(kind)], range))
+    }
+
+    /// 紧跟在可能溢出的指令之后.
+    fn overflow_guard(&mut self, range: Option<(usize, usize)>) -> Option<Instr> {
+        self.arith_error_block(ERROR_OVERFLOW, range)
+            .map(|l| Instr::JmpIf(CondCode::O, l))
+    }
+
+    /// 把列表的长度读入`dest`, 列表的地址留在%r11中.
+    /// 长度在tag的第2到61位, 左移再算术右移去掉其他的位.
+    fn list_len(&self, list: &Atom, dest: Arg) -> Vec<Instr> {
+        vec![
+            Instr::Movq(self.select_atom(list), Arg::Reg(Reg::R11)),
+            Instr::Movq(Arg::Deref(Reg::R11, 0), dest.clone()),
+            Instr::Salq(Arg::Imm(2), dest.clone()),
+            Instr::Sarq(Arg::Imm(4), dest),
+        ]
+    }
+
+    /// 检查下标, 越界时调用运行时的`index_error`. 之后元素的地址是`8(%r11)`.
+    /// 长度在%rax中, 下标在%rdx中, 出错时作为`index_error`的参数.
+    fn list_elem(
+        &mut self,
+        list: &Atom,
+        index: &Atom,
+        range: Option<(usize, usize)>,
+    ) -> Vec<Instr> {
+        let error = self.error_block(
+            "index_error",
+            vec![Arg::Reg(Reg::Rdx), Arg::Reg(Reg::Rax)],
+            range,
+        );
+        let mut instrs = self.list_len(list, Arg::Reg(Reg::Rax));
+        instrs.extend([
+            Instr::Movq(self.select_atom(index), Arg::Reg(Reg::Rdx)),
+            Instr::Cmpq(Arg::Imm(0), Arg::Reg(Reg::Rdx)),
+            Instr::JmpIf(CondCode::L, error.clone()),
+            Instr::Cmpq(Arg::Reg(Reg::Rax), Arg::Reg(Reg::Rdx)),
+            Instr::JmpIf(CondCode::Ge, error),
+            Instr::Salq(Arg::Imm(3), Arg::Reg(Reg::Rdx)),
+            Instr::Addq(Arg::Reg(Reg::Rdx), Arg::Reg(Reg::R11)),
+        ]);
+        instrs
+    }
+
+    fn select_stmt(&mut self, s: Stmt) -> Vec<Instr> {
+        match s {
+            Stmt::Assign { name, binding } => {
+                let dest = self.select_atom(&name);
+                self.select_exp(binding, dest)
+            }
+            // 表达式语句的值放在%rax中, 随后被丢弃.
+            Stmt::Exp(e) => self.select_exp(e, Arg::Reg(Reg::Rax)),
+            // 返回值放在%rax中, 再跳转到结尾.
+            Stmt::Return(e) => {
+                let mut instrs = self.select_exp(e, Arg::Reg(Reg::Rax));
+                instrs.push(Instr::Jump(Program::conclusion_of(&self.func)));
+                instrs
+            }
+            // 参数都在寄存器中, 见`ExplicateControl::explicate_tail`.
+            Stmt::TailCall { name, args } => {
+                let mut instrs = self.pass_args_in_regs(&args);
+                let func = Arg::FunRef(Self::runtime_name(&name));
+                instrs.push(Instr::Leaq(func, Arg::Reg(Reg::Rax)));
+                instrs.push(Instr::TailJmp(Arg::Reg(Reg::Rax), args.len()));
+                instrs
+            }
+            // 闭包是第一个参数, 在%rdi中.
+            Stmt::TailApply { closure, args } => {
+                let args: Vec<Atom> = std::iter::once(closure).chain(args).collect();
+                let mut instrs = self.pass_args_in_regs(&args);
+                instrs.push(Instr::Movq(Arg::Deref(Reg::Rdi, 8), Arg::Reg(Reg::Rax)));
+                instrs.push(Instr::TailJmp(Arg::Reg(Reg::Rax), args.len()));
+                instrs
+            }
+            Stmt::Goto(label) => vec![Instr::Jump(label)],
+            Stmt::If { cond, then, else_ } => match cond {
+                Expr::Prim { op, operands, .. } => {
+                    let cc = CondCode::from_kind(op).expect("Expected a comparison.");
+                    let arg0 = self.select_atom(&operands[0]);
+                    let arg1 = self.select_atom(&operands[1]);
+                    vec![
+                        Instr::Cmpq(arg1, arg0),
+                        Instr::JmpIf(cc, then),
+                        Instr::Jump(else_),
+                    ]
+                }
+                _ => unreachable!("Expected a comparison."),
+            },
+        }
+    }
+
+    /// 计算表达式`e`, 把结果存入`dest`.
+    fn select_exp(&mut self, e: Expr, dest: Arg) -> Vec<Instr> {
+        let mut instrs = vec![];
+        match e {
+            Expr::Atom(a) => {
+                let src = self.select_atom(&a);
+                instrs.push(Instr::Movq(src, dest));
+            }
+            Expr::Str(s) => {
+                let name = format!("str_{}", self.strings.len());
+                let label = CProgrom::label(&self.func, &name);
+                instrs.push(Instr::Leaq(Arg::StrRef(label.clone()), dest));
+                self.strings.push((label, s));
+            }
+            Expr::Prim {
+                op,
+                operands,
+                range,
+            } if operands.len() == 1 => {
+                let arg = self.select_atom(&operands[0]);
+                match op {
+                    Kind::Minus => {
+                        instrs.push(Instr::Movq(arg, dest.clone()));
+                        instrs.push(Instr::Negq(dest));
+                        instrs.extend(self.overflow_guard(range));
                     }
-                    ExprData::Prim { op, mut operands } if operands.len() == 1 => {
-                        let arg = self.select_atom(&operands.remove(0));
-                        match op.kind() {
-                            Kind::Minus => {
-                                instrs.push(Instr::Movq(arg, dest.clone()));
-                                instrs.push(Instr::Negq(dest));
-                            }
-                            _ => unreachable!(),
+                    Kind::Bang => {
+                        if arg != dest {
+                            instrs.push(Instr::Movq(arg, dest.clone()));
                         }
+                        instrs.push(Instr::Xorq(Arg::Imm(1), dest));
                     }
-                    ExprData::Prim { op, operands } if operands.len() == 2 => {
-                        let mut args: Vec<Arg> =
-                            operands.into_iter().map(|e| self.select_atom(&e)).collect();
-                        let arg0 = args.remove(0);
-                        let arg1 = args.remove(0);
-                        match op.kind() {
-                            Kind::Plus => {
-                                if arg0.get_var().map_or(false, |v| v == name.lexeme()) {
-                                    instrs.push(Instr::Addq(arg1, arg0));
-                                } else if arg1.get_var().map_or(false, |v| v == name.lexeme()) {
-                                    instrs.push(Instr::Addq(arg0, arg1));
-                                } else {
-                                    instrs.push(Instr::Movq(arg0, dest.clone()));
-                                    instrs.push(Instr::Addq(arg1, dest));
-                                }
-                            }
-                            Kind::Minus => {
-                                if arg0.get_var().map_or(false, |v| v == name.lexeme()) {
-                                    instrs.push(Instr::Subq(arg1, arg0));
-                                } else {
-                                    instrs.push(Instr::Movq(arg0, dest.clone()));
-                                    instrs.push(Instr::Subq(arg1, dest));
-                                }
-                            }
-                            _ => unimplemented!(),
-                        }
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Prim {
+                op,
+                operands,
+                range,
+            } if operands.len() == 2 => {
+                let arg0 = self.select_atom(&operands[0]);
+                let arg1 = self.select_atom(&operands[1]);
+                if matches!(op, Kind::Slash | Kind::Percent) {
+                    if let Some(l) = self.arith_error_block(ERROR_DIVIDE_BY_ZERO, range) {
+                        instrs.push(Instr::Cmpq(Arg::Imm(0), arg1.clone()));
+                        instrs.push(Instr::JmpIf(CondCode::E, l));
                     }
-                    ExprData::Call { name: func, args } => {
-                        instrs.extend(self.select_function_call(&func, &args));
-                        instrs.push(Instr::Movq(Arg::Reg(Reg::Rax), dest));
+                    // 只有`MIN / -1`会溢出: 除数是-1时%rdx为1, 被除数减1溢出当且仅当它是`MIN`.
+                    if !matches!(arg1, Arg::Imm(i) if i != -1) {
+                        if let Some(l) = self.arith_error_block(ERROR_OVERFLOW, range) {
+                            instrs.extend([
+                                Instr::Cmpq(Arg::Imm(-1), arg1.clone()),
+                                Instr::Set(CondCode::E, ByteReg::Al),
+                                Instr::Movzbq(ByteReg::Al, Arg::Reg(Reg::Rdx)),
+                                Instr::Movq(arg0.clone(), Arg::Reg(Reg::Rax)),
+                                Instr::Subq(Arg::Reg(Reg::Rdx), Arg::Reg(Reg::Rax)),
+                                Instr::JmpIf(CondCode::O, l),
+                            ]);
+                        }
                     }
-                    _ => unimplemented!(),
                 }
-            }
-
-            StmtData::Expr(e) => {
-                let dest = Arg::Reg(Reg::Rax);
-                match e.data {
-                    ExprData::Name(v) => {
-                        let src = Arg::Var(v.lexeme().into());
-                        instrs.push(Instr::Movq(src, dest));
+                match op {
+                    Kind::Plus => {
+                        if arg0 == dest {
+                            instrs.push(Instr::Addq(arg1, arg0));
+                        } else if arg1 == dest {
+                            instrs.push(Instr::Addq(arg0, arg1));
+                        } else {
+                            instrs.push(Instr::Movq(arg0, dest.clone()));
+                            instrs.push(Instr::Addq(arg1, dest));
+                        }
                     }
-                    ExprData::Int(i) => {
-                        let src = Arg::Imm(i);
-                        instrs.push(Instr::Movq(src, dest));
+                    Kind::Minus => {
+                        if arg0 == dest {
+                            instrs.push(Instr::Subq(arg1, arg0));
+                        } else {
+                            instrs.push(Instr::Movq(arg0, dest.clone()));
+                            instrs.push(Instr::Subq(arg1, dest));
+                        }
                     }
-                    ExprData::Prim { op, mut operands } if operands.len() == 1 => {
-                        let arg = self.select_atom(&operands.remove(0));
-                        match op.kind() {
-                            Kind::Minus => {
-                                instrs.push(Instr::Movq(arg, dest.clone()));
-                                instrs.push(Instr::Negq(dest));
-                            }
-                            _ => unreachable!(),
+                    Kind::Star => {
+                        if arg0 == dest {
+                            instrs.push(Instr::Imulq(arg1, arg0));
+                        } else if arg1 == dest {
+                            instrs.push(Instr::Imulq(arg0, arg1));
+                        } else {
+                            instrs.push(Instr::Movq(arg0, dest.clone()));
+                            instrs.push(Instr::Imulq(arg1, dest));
                         }
                     }
-                    ExprData::Prim { op, operands } if operands.len() == 2 => {
-                        let mut args: Vec<Arg> =
-                            operands.into_iter().map(|e| self.select_atom(&e)).collect();
-                        let arg0 = args.remove(0);
-                        let arg1 = args.remove(0);
-                        match op.kind() {
-                            Kind::Plus => {
-                                instrs.push(Instr::Movq(arg0, dest.clone()));
-                                instrs.push(Instr::Addq(arg1, dest));
-                            }
-                            Kind::Minus => {
-                                instrs.push(Instr::Movq(arg0, dest.clone()));
-                                instrs.push(Instr::Subq(arg1, dest));
-                            }
-                            _ => unimplemented!(),
+                    // 被除数放在%rdx:%rax中, 商在%rax, 余数在%rdx. idivq向零取整,
+                    // 余数不为零且与除数异号时改为向下取整: 商减1, 余数加上除数.
+                    // %rdx中算出这时为-1, 否则为0的掩码, 结果暂存在%r11中.
+                    Kind::Slash | Kind::Percent => {
+                        let (rax, rdx, r11) =
+                            (Arg::Reg(Reg::Rax), Arg::Reg(Reg::Rdx), Arg::Reg(Reg::R11));
+                        instrs.extend([
+                            Instr::Movq(arg0, rax.clone()),
+                            Instr::Cqto,
+                            Instr::Idivq(arg1.clone()),
+                        ]);
+                        let res = if op == Kind::Slash { &rax } else { &rdx };
+                        instrs.extend([
+                            Instr::Movq(res.clone(), r11.clone()),
+                            Instr::Cmpq(Arg::Imm(0), rdx.clone()),
+                            Instr::Set(CondCode::Ne, ByteReg::Al),
+                            Instr::Movzbq(ByteReg::Al, rax.clone()),
+                            Instr::Xorq(arg1.clone(), rdx.clone()),
+                            Instr::Sarq(Arg::Imm(63), rdx.clone()),
+                            Instr::Imulq(rax, rdx.clone()),
+                        ]);
+                        if op == Kind::Slash {
+                            instrs.push(Instr::Addq(rdx, r11.clone()));
+                        } else {
+                            instrs.push(Instr::Imulq(arg1, rdx.clone()));
+                            instrs.push(Instr::Subq(rdx, r11.clone()));
                         }
+                        instrs.push(Instr::Movq(r11, dest));
+                    }
+                    k => {
+                        let cc = CondCode::from_kind(k).expect("Unexpected binary operator.");
+                        // cmpq b, a 按 a - b 设置标志位.
+                        instrs.push(Instr::Cmpq(arg1, arg0));
+                        instrs.push(Instr::Set(cc, ByteReg::Al));
+                        instrs.push(Instr::Movzbq(ByteReg::Al, dest));
                     }
-                    ExprData::Call { name: func, args } => {
-                        instrs.extend(self.select_function_call(&func, &args));
+                }
+                if matches!(op, Kind::Plus | Kind::Minus | Kind::Star) {
+                    instrs.extend(self.overflow_guard(range));
+                }
+            }
+            // 元组的长度在类型中, 列表的长度在tag中.
+            Expr::Call {
+                name: Atom::Name(f),
+                args,
+            } if f == "len" => {
+                let Atom::Name(v) = &args[0] else {
+                    unreachable!("Expected a tuple or a list.")
+                };
+                match self.types.get(v) {
+                    Some(Type::Tuple(elems)) => {
+                        instrs.push(Instr::Movq(Arg::Imm(elems.len() as i64), dest));
                     }
-                    _ => unimplemented!(),
+                    Some(Type::List(_)) => instrs.extend(self.list_len(&args[0], dest)),
+                    _ => unreachable!("Expected a tuple or a list."),
+                }
+            }
+            Expr::Call { name: func, args } => {
+                let call = Instr::Callq(Self::runtime_name(&func), args.len());
+                instrs.extend(self.select_function_call(&args, vec![call]));
+                if dest != Arg::Reg(Reg::Rax) {
+                    instrs.push(Instr::Movq(Arg::Reg(Reg::Rax), dest));
+                }
+            }
+            Expr::Tuple(elems) => {
+                let mask = elems
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, e)| self.is_pointer(e))
+                    .fold(0, |mask, (i, _)| mask | 1 << i);
+                let fields = elems.iter().map(|e| self.select_atom(e)).collect();
+                instrs.extend(self.allocate(fields, mask, dest));
+            }
+            Expr::Subscript { tuple, index } => {
+                let tuple = self.select_atom(&tuple);
+                instrs.push(Instr::Movq(tuple, Arg::Reg(Reg::R11)));
+                let src = Arg::Deref(Reg::R11, 8 * (index as i64 + 1));
+                instrs.push(Instr::Movq(src, dest));
+            }
+            // 闭包是`[tag, 函数地址, 捕获的值...]`, 见`ClosureConversion`.
+            // 函数的地址不是指向堆的指针.
+            Expr::Closure { func, captures } => {
+                let mask = captures
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| self.is_pointer(c))
+                    .fold(0, |mask, (i, _)| mask | 1 << (i + 1));
+                let fields = std::iter::once(Arg::FunRef(func))
+                    .chain(captures.iter().map(|c| self.select_atom(c)))
+                    .collect();
+                instrs.extend(self.allocate(fields, mask, dest));
+            }
+            Expr::GlobalValue(name) => {
+                instrs.push(Instr::Movq(Arg::Global(name), dest));
+            }
+            // 根栈的栈顶在%r15中.
+            Expr::Collect(bytes) => {
+                instrs.push(Instr::Movq(Arg::Reg(Reg::R15), Arg::Reg(Reg::Rdi)));
+                instrs.push(Instr::Movq(self.select_atom(&bytes), Arg::Reg(Reg::Rsi)));
+                instrs.push(Instr::Callq("collect".into(), 2));
+            }
+            // tag放在%rax中计算, 大于32位的部分先放入%rdx.
+            Expr::Allocate { len, ty } => {
+                let elem_is_ptr = matches!(&ty, Type::List(elem) if elem.is_heap());
+                let len = self.select_atom(&len);
+                let free_ptr = Arg::Global("free_ptr".into());
+                instrs.extend([
+                    Instr::Movq(len.clone(), Arg::Reg(Reg::Rax)),
+                    Instr::Salq(Arg::Imm(2), Arg::Reg(Reg::Rax)),
+                    Instr::Movq(Arg::Imm(list_tag(elem_is_ptr)), Arg::Reg(Reg::Rdx)),
+                    Instr::Addq(Arg::Reg(Reg::Rdx), Arg::Reg(Reg::Rax)),
+                    Instr::Movq(free_ptr.clone(), Arg::Reg(Reg::R11)),
+                    Instr::Movq(Arg::Reg(Reg::Rax), Arg::Deref(Reg::R11, 0)),
+                    Instr::Movq(len, Arg::Reg(Reg::Rax)),
+                    Instr::Addq(Arg::Imm(1), Arg::Reg(Reg::Rax)),
+                    Instr::Salq(Arg::Imm(3), Arg::Reg(Reg::Rax)),
+                    Instr::Addq(Arg::Reg(Reg::Rax), free_ptr),
+                    Instr::Movq(Arg::Reg(Reg::R11), dest),
+                ]);
+            }
+            Expr::ListRef { list, index, range } => {
+                instrs.extend(self.list_elem(&list, &index, range));
+                instrs.push(Instr::Movq(Arg::Deref(Reg::R11, 8), dest));
+            }
+            Expr::ListSet {
+                list,
+                index,
+                value,
+                range,
+            } => {
+                instrs.extend(self.list_elem(&list, &index, range));
+                instrs.push(Instr::Movq(
+                    self.select_atom(&value),
+                    Arg::Deref(Reg::R11, 8),
+                ));
+            }
+            Expr::ListInit { list, index, value } => {
+                instrs.extend([
+                    Instr::Movq(self.select_atom(&list), Arg::Reg(Reg::R11)),
+                    Instr::Movq(self.select_atom(&index), Arg::Reg(Reg::Rdx)),
+                    Instr::Salq(Arg::Imm(3), Arg::Reg(Reg::Rdx)),
+                    Instr::Addq(Arg::Reg(Reg::Rdx), Arg::Reg(Reg::R11)),
+                    Instr::Movq(self.select_atom(&value), Arg::Deref(Reg::R11, 8)),
+                ]);
+            }
+            Expr::Capture { closure, index } => {
+                let closure = self.select_atom(&closure);
+                instrs.push(Instr::Movq(closure, Arg::Reg(Reg::R11)));
+                let src = Arg::Deref(Reg::R11, 16 + 8 * index as i64);
+                instrs.push(Instr::Movq(src, dest));
+            }
+            // 闭包是第一个参数, 在%rdi中.
+            Expr::Apply { closure, args } => {
+                let args: Vec<Atom> = std::iter::once(closure).chain(args).collect();
+                let call = vec![
+                    Instr::Movq(Arg::Deref(Reg::Rdi, 8), Arg::Reg(Reg::Rax)),
+                    Instr::IndirectCallq(Arg::Reg(Reg::Rax), args.len()),
+                ];
+                instrs.extend(self.select_function_call(&args, call));
+                if dest != Arg::Reg(Reg::Rax) {
+                    instrs.push(Instr::Movq(Arg::Reg(Reg::Rax), dest));
                 }
             }
             _ => unimplemented!(),
@@ -128,33 +518,48 @@ impl SelectInstructions {
         instrs
     }
 
-    fn select_atom(&self, e: &Expr) -> Arg {
-        match &e.data {
-            ExprData::Name(v) => Arg::Var(v.lexeme().into()),
-            ExprData::Int(i) => Arg::Imm(*i),
-            _ => unreachable!(),
+    fn select_atom(&self, a: &Atom) -> Arg {
+        match a {
+            Atom::Name(v) => Arg::Var(v.clone()),
+            Atom::Int(i) => Arg::Imm(*i),
+            Atom::Bool(b) => Arg::Imm(*b as i64),
+            Atom::Float(_) => unimplemented!(),
         }
     }
 
-    fn select_function_call(&self, func: &Expr, args: &Vec<Expr>) -> Vec<Instr> {
-        let mut instrs = vec![];
-        let arity = args.len();
-        let args_in_reg = args.iter().take(6).collect::<Vec<_>>();
-        for (e, r) in args_in_reg.into_iter().zip(Reg::args_passing().into_iter()) {
-            instrs.push(Instr::Movq(self.select_atom(e), Arg::Reg(r)));
+    /// 函数在汇编中的名字. 内建函数在运行时(runtime.c)中的名字可能不同.
+    fn runtime_name(func: &Atom) -> Label {
+        match func {
+            Atom::Name(f) if f == "input_int" => "read_int".into(),
+            Atom::Name(f) => f.clone(),
+            _ => unreachable!("Expected function name."),
         }
+    }
+
+    /// 前6个参数.
+    fn pass_args_in_regs(&self, args: &[Atom]) -> Vec<Instr> {
+        args.iter()
+            .zip(Reg::args_passing())
+            .map(|(e, r)| Instr::Movq(self.select_atom(e), Arg::Reg(r)))
+            .collect()
+    }
+
+    /// 传递参数, 执行`call`中的调用指令, 再弹出栈上的参数.
+    fn select_function_call(&self, args: &[Atom], call: Vec<Instr>) -> Vec<Instr> {
+        let mut instrs = self.pass_args_in_regs(args);
 
         let args_in_stack = args.iter().skip(6).rev().collect::<Vec<_>>();
-        let size = args_in_stack.len() as i64 * 8;
+        let mut size = args_in_stack.len() as i64 * 8;
+        // 调用时%rsp要按16字节对齐.
+        if size % 16 != 0 {
+            instrs.push(Instr::Subq(Arg::Imm(8), Arg::Reg(Reg::Rsp)));
+            size += 8;
+        }
         for a in args_in_stack {
             instrs.push(Instr::Pushq(self.select_atom(a)));
         }
 
-        let func = func
-            .get_ident()
-            .expect("Expected function name.")
-            .to_string();
-        instrs.push(Instr::Callq(func, arity));
+        instrs.extend(call);
         if size != 0 {
             instrs.push(Instr::Addq(Arg::Imm(size), Arg::Reg(Reg::Rsp)));
         }
@@ -1,48 +1,265 @@
 use crate::{
-    ast::{Expr, ExprData, Stmt, StmtData},
-    token::Kind,
+    ast::{Expr, ExprData, Range, Stmt, StmtData},
+    reporter::ErrorReporter,
+    token::{Kind, Token},
 };
 
-pub struct Shrink {}
+use super::select_instructions::ERROR_ZERO_STEP;
 
-impl Shrink {
-    pub fn shrink_stmts(stmts: Vec<Stmt>) -> Vec<Stmt> {
-        stmts.into_iter().map(|s| Self::shrink_stmt(s)).collect()
+pub struct Shrink<'r> {
+    temp: usize,
+    /// 用于换算运行时错误的位置.
+    reporter: &'r ErrorReporter,
+}
+
+impl<'r> Shrink<'r> {
+    pub fn new(reporter: &'r ErrorReporter) -> Self {
+        Self { temp: 0, reporter }
+    }
+
+    fn next_temp(&mut self, prefix: &str) -> Token {
+        let tmp = format!("%{}{}", prefix, self.temp);
+        self.temp += 1;
+        Token::new(Kind::Name, tmp, 0, 0)
     }
 
-    fn shrink_stmt(s: Stmt) -> Stmt {
+    pub fn shrink_stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        let mut res = vec![];
+        for s in stmts {
+            res.extend(self.shrink_stmt(s));
+        }
+        res
+    }
+
+    fn shrink_stmt(&mut self, s: Stmt) -> Vec<Stmt> {
         let stmt = match s.stmt {
-            StmtData::Expr(e) => StmtData::Expr(Self::shrink_expr(e)),
-            StmtData::Assign { name, binding } => StmtData::Assign {
-                name,
-                binding: Self::shrink_expr(binding),
-            },
+            StmtData::Expr(e) => StmtData::Expr(self.shrink_expr(e)),
+            StmtData::Assign { name, binding } | StmtData::AnnAssign { name, binding, .. } => {
+                StmtData::Assign {
+                    name,
+                    binding: self.shrink_expr(binding),
+                }
+            }
+            // x += e => x = x + e
+            StmtData::AugAssign { name, op, binding } => {
+                let binding = Expr::binary()
+                    .left(Expr::atom(name.clone()))
+                    .op(op)
+                    .right(self.shrink_expr(binding))
+                    .build();
+                StmtData::Assign { name, binding }
+            }
+            // a, b = e1, e2 => t0 = e1; t1 = e2; a = t0; b = t1
+            // a, b = t  =>  %assign = t; a = %assign[0]; b = %assign[1]
+            StmtData::MultiAssign {
+                names,
+                mut bindings,
+            } if bindings.len() == 1 && names.len() > 1 => {
+                let tmp = self.next_temp("assign");
+                let binding = self.shrink_expr(bindings.pop().unwrap());
+                let mut res = vec![Stmt::assignment()
+                    .name(tmp.clone())
+                    .binding(binding)
+                    .build()];
+                for (i, name) in names.into_iter().enumerate() {
+                    let range = name.range();
+                    let mut binding = Expr::subscript()
+                        .value(Expr::atom(tmp.clone()))
+                        .index(Expr::new(ExprData::Int(i as i64), range))
+                        .build();
+                    binding.range = range;
+                    res.push(Stmt {
+                        stmt: StmtData::Assign { name, binding },
+                        range,
+                    });
+                }
+                return res;
+            }
+            StmtData::MultiAssign { names, bindings } => {
+                let mut temps = vec![];
+                let mut res = vec![];
+                for b in bindings {
+                    let tmp = self.next_temp("assign");
+                    let binding = self.shrink_expr(b);
+                    res.push(
+                        Stmt::assignment()
+                            .name(tmp.clone())
+                            .binding(binding)
+                            .build(),
+                    );
+                    temps.push(tmp);
+                }
+                for (name, tmp) in names.into_iter().zip(temps) {
+                    let range = name.range();
+                    let mut binding = Expr::atom(tmp);
+                    binding.range = range;
+                    res.push(Stmt {
+                        stmt: StmtData::Assign { name, binding },
+                        range,
+                    });
+                }
+                return res;
+            }
+            StmtData::For {
+                var,
+                start,
+                stop,
+                step,
+                body,
+            } => return self.shrink_for(s.range, var, start, stop, step, body),
             StmtData::If {
                 condition,
                 then,
                 else_,
             } => {
                 StmtData::If {
-                    condition: Self::shrink_expr(condition),
+                    condition: self.shrink_expr(condition),
                     // then :Self::shrink_stmts(then),
                     // else_: Self::shrink_stmts(else_),
-                    then: Self::shrink_expr(then),
-                    else_: Self::shrink_expr(else_),
+                    then: self.shrink_expr(then),
+                    else_: self.shrink_expr(else_),
                 }
             }
+            StmtData::While { condition, body } => StmtData::While {
+                condition: self.shrink_expr(condition),
+                body: self.shrink_expr(body),
+            },
+            StmtData::Def {
+                name,
+                params,
+                ret,
+                body,
+            } => StmtData::Def {
+                name,
+                params,
+                ret,
+                body: self.shrink_expr(body),
+            },
+            StmtData::Return(value) => StmtData::Return(value.map(|e| self.shrink_expr(e))),
         };
 
-        Stmt {
+        vec![Stmt {
             stmt,
             range: s.range,
-        }
+        }]
+    }
+    /// for i in range(a, b, s) { body } =>
+    /// ```text
+    /// %for = a
+    /// %stop = b
+    /// %step = s
+    /// while (%for < %stop if %step > 0 else %for > %stop) {
+    ///     i = %for
+    ///     %for = %for + %step
+    ///     body
+    /// }
+    /// ```
+    /// 步长是常量时直接选择比较运算符, 也不需要`%step`.
+    fn shrink_for(
+        &mut self,
+        range: (usize, usize),
+        var: Token,
+        start: Expr,
+        stop: Expr,
+        step: Option<Expr>,
+        body: Expr,
+    ) -> Vec<Stmt> {
+        let (line, pos) = (var.line(), var.pos());
+        let op = |kind, lexeme: &str| Token::new(kind, lexeme.into(), line, pos);
+        let name = |t: &Token| Expr::new(ExprData::Name(t.clone()), range);
+        let prim = |op: Token, l: Expr, r: Expr| {
+            Expr::new(
+                ExprData::Prim {
+                    op,
+                    operands: vec![l, r],
+                },
+                range,
+            )
+        };
+        let assign = |name: Token, binding: Expr| Stmt {
+            stmt: StmtData::Assign { name, binding },
+            range,
+        };
+
+        let counter = self.next_temp("for");
+        let limit = self.next_temp("stop");
+        let mut res = vec![
+            assign(counter.clone(), self.shrink_expr(start)),
+            assign(limit.clone(), self.shrink_expr(stop)),
+        ];
+        let less = prim(op(Kind::Less, "<"), name(&counter), name(&limit));
+        let greater = prim(op(Kind::Greater, ">"), name(&counter), name(&limit));
+        let (condition, step) = match step.as_ref().map(Expr::const_int) {
+            None => (less, Expr::new(ExprData::Int(1), range)),
+            Some(Some(s)) if s > 0 => (less, step.unwrap()),
+            Some(Some(_)) => (greater, step.unwrap()),
+            Some(None) => {
+                let step = step.unwrap();
+                let (line, col) = self.reporter.line_col(step.range.0);
+                let s = self.next_temp("step");
+                res.push(assign(s.clone(), self.shrink_expr(step)));
+                let int = |i| Expr::new(ExprData::Int(i), range);
+                let error = Expr::new(
+                    ExprData::Call {
+                        name: name(&op(Kind::Name, "arith_error")).into(),
+                        args: vec![int(ERROR_ZERO_STEP), int(line as i64), int(col as i64)],
+                    },
+                    range,
+                );
+                let block = |body| Expr::new(ExprData::Block { body, result: None }, range);
+                res.push(Stmt {
+                    stmt: StmtData::If {
+                        condition: prim(op(Kind::EqualEqual, "=="), name(&s), int(0)),
+                        then: block(vec![Stmt {
+                            stmt: StmtData::Expr(error),
+                            range,
+                        }]),
+                        else_: block(vec![]),
+                    },
+                    range,
+                });
+                let positive = prim(op(Kind::Greater, ">"), name(&s), int(0));
+                let condition = Expr::new(
+                    ExprData::Condition {
+                        condition: positive.into(),
+                        then: less.into(),
+                        else_: greater.into(),
+                    },
+                    range,
+                );
+                (condition, name(&s))
+            }
+        };
+
+        let next = prim(op(Kind::Plus, "+"), name(&counter), step);
+        let body = Expr::new(
+            ExprData::Block {
+                body: vec![
+                    assign(var, name(&counter)),
+                    assign(counter, next),
+                    Stmt {
+                        range: body.range,
+                        stmt: StmtData::Expr(self.shrink_expr(body)),
+                    },
+                ],
+                result: None,
+            },
+            range,
+        );
+        res.push(Stmt {
+            stmt: StmtData::While { condition, body },
+            range,
+        });
+        res
     }
-    fn shrink_expr(e: Expr) -> Expr {
+
+    fn shrink_expr(&mut self, e: Expr) -> Expr {
         match e.data {
+            // a and b => b if a else false
             ExprData::Prim { op, mut operands } if op.kind() == Kind::And => Expr {
                 data: ExprData::Condition {
-                    condition: operands.remove(0).into(),
-                    then: operands.remove(0).into(),
+                    condition: self.shrink_expr(operands.remove(0)).into(),
+                    then: self.shrink_expr(operands.remove(0)).into(),
                     else_: Expr {
                         data: ExprData::Bool(false),
                         range: (e.range.0, e.range.0 + 4),
@@ -51,26 +268,103 @@ impl Shrink {
                 },
                 range: e.range,
             },
+            // a or b => true if a else b
             ExprData::Prim { op, mut operands } if op.kind() == Kind::Or => Expr {
                 data: ExprData::Condition {
-                    condition: Expr {
+                    condition: self.shrink_expr(operands.remove(0)).into(),
+                    then: Expr {
                         data: ExprData::Bool(true),
-                        range: (e.range.0, e.range.0 + 3),
+                        range: (e.range.0, e.range.0 + 4),
                     }
                     .into(),
-                    then: operands.remove(0).into(),
-                    else_: operands.remove(0).into(),
+                    else_: self.shrink_expr(operands.remove(0)).into(),
+                },
+                range: e.range,
+            },
+            ExprData::Prim { op, operands } => Expr {
+                data: ExprData::Prim {
+                    op,
+                    operands: operands.into_iter().map(|e| self.shrink_expr(e)).collect(),
+                },
+                range: e.range,
+            },
+            ExprData::Call { name, args } => Expr {
+                data: ExprData::Call {
+                    name,
+                    args: args.into_iter().map(|e| self.shrink_expr(e)).collect(),
+                },
+                range: e.range,
+            },
+            ExprData::Condition {
+                condition,
+                then,
+                else_,
+            } => Expr {
+                data: ExprData::Condition {
+                    condition: self.shrink_expr(*condition).into(),
+                    then: self.shrink_expr(*then).into(),
+                    else_: self.shrink_expr(*else_).into(),
                 },
                 range: e.range,
             },
             ExprData::Block { body, result } => {
-                let body = Self::shrink_stmts(body);
-                let result = result.and_then(|r| Some(Box::new(Self::shrink_expr(*r))));
+                let body = self.shrink_stmts(body);
+                let result = result.map(|r| Box::new(self.shrink_expr(*r)));
                 Expr {
                     data: ExprData::Block { body, result },
                     range: e.range,
                 }
             }
+            ExprData::Lambda {
+                params,
+                body,
+                ty,
+                captures,
+            } => Expr {
+                data: ExprData::Lambda {
+                    params,
+                    body: self.shrink_expr(*body).into(),
+                    ty,
+                    captures,
+                },
+                range: e.range,
+            },
+            ExprData::Tuple { elems } => Expr {
+                data: ExprData::Tuple {
+                    elems: elems.into_iter().map(|e| self.shrink_expr(e)).collect(),
+                },
+                range: e.range,
+            },
+            ExprData::Subscript { value, index } => Expr {
+                data: ExprData::Subscript {
+                    value: self.shrink_expr(*value).into(),
+                    index,
+                },
+                range: e.range,
+            },
+            ExprData::List { elems, repeat, ty } => Expr {
+                data: ExprData::List {
+                    elems: elems.into_iter().map(|e| self.shrink_expr(e)).collect(),
+                    repeat: repeat.map(|n| self.shrink_expr(*n).into()),
+                    ty,
+                },
+                range: e.range,
+            },
+            ExprData::ListRef { list, index } => Expr {
+                data: ExprData::ListRef {
+                    list: self.shrink_expr(*list).into(),
+                    index: self.shrink_expr(*index).into(),
+                },
+                range: e.range,
+            },
+            ExprData::ListSet { list, index, value } => Expr {
+                data: ExprData::ListSet {
+                    list: self.shrink_expr(*list).into(),
+                    index: self.shrink_expr(*index).into(),
+                    value: self.shrink_expr(*value).into(),
+                },
+                range: e.range,
+            },
             o => Expr {
                 data: o,
                 range: e.range,
@@ -1,20 +1,215 @@
-use std::{collections::HashSet, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
-pub struct Program(Vec<Instr>);
+use crate::token::{self, Kind};
+
+use super::clike::CProgrom;
 
 pub type Label = String;
 
+/// 由带标签的基本块组成的x86程序, 可以是一个函数, 也可以是合并在一起的所有函数.
+/// 块之间只通过跳转指令相连, 所以输出时块的先后顺序不影响语义.
+#[derive(Clone, Default)]
+pub struct Program {
+    pub blocks: HashMap<Label, Vec<Instr>>,
+    /// 值是指向堆上对象的指针的变量. 垃圾回收时它们必须在根栈中.
+    pub pointers: HashSet<String>,
+    /// 只读数据段中的字符串及其标签, 按出现的顺序.
+    pub strings: Vec<(Label, String)>,
+}
+
+impl Program {
+    /// `main`的序言所在的块, 也是整个程序的入口. 其他函数的序言以函数名为标签.
+    pub const PRELUDE: &'static str = "main";
+    /// `main`的第一个块, 序言结束后跳转到这里.
+    pub const ENTRY: &'static str = "start";
+    /// `main`的结尾(释放栈帧并返回)所在的块.
+    pub const CONCLUSION: &'static str = "conclusion";
+
+    pub fn new(blocks: HashMap<Label, Vec<Instr>>) -> Self {
+        Self {
+            blocks,
+            pointers: HashSet::new(),
+            strings: vec![],
+        }
+    }
+
+    /// 函数`func`的第一个块.
+    pub fn entry_of(func: &str) -> Label {
+        CProgrom::label(func, Self::ENTRY)
+    }
+
+    /// 函数`func`的结尾块.
+    pub fn conclusion_of(func: &str) -> Label {
+        if func == Self::PRELUDE {
+            Self::CONCLUSION.into()
+        } else {
+            CProgrom::label(func, Self::CONCLUSION)
+        }
+    }
+
+    /// 块所属的函数.
+    fn func_of(label: &str) -> &str {
+        match label {
+            Self::ENTRY | Self::CONCLUSION => Self::PRELUDE,
+            l => l.split('.').next().unwrap(),
+        }
+    }
+
+    /// 把各个函数的块合并为一个程序.
+    pub fn merge(progs: impl IntoIterator<Item = Program>) -> Self {
+        let mut res = Self::default();
+        for p in progs {
+            res.blocks.extend(p.blocks);
+            res.pointers.extend(p.pointers);
+            res.strings.extend(p.strings);
+        }
+        res
+    }
+
+    /// 输出时块的顺序: `main`在最前, 其余函数按名字排序.
+    /// 每个函数中依次是序言, 入口, 按标签排序的其余块, 最后是结尾.
+    pub fn block_order(&self) -> Vec<Label> {
+        let rank = |l: &str| {
+            let func = Self::func_of(l);
+            let pos = if l == func {
+                0
+            } else if l == Self::entry_of(func) {
+                1
+            } else if l == Self::conclusion_of(func) {
+                3
+            } else {
+                2
+            };
+            (func != Self::PRELUDE, func.to_string(), pos)
+        };
+        let mut order: Vec<Label> = self.blocks.keys().cloned().collect();
+        order.sort_by(|a, b| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+        order
+    }
+
+    /// 对每条指令做变换, 保持块结构不变.
+    pub fn map_blocks<F: FnMut(Vec<Instr>) -> Vec<Instr>>(self, mut f: F) -> Self {
+        let blocks = self
+            .blocks
+            .into_iter()
+            .map(|(label, instrs)| (label, f(instrs)))
+            .collect();
+        Self {
+            blocks,
+            pointers: self.pointers,
+            strings: self.strings,
+        }
+    }
+}
+
+/// 输出完整的GNU AT&T汇编文件.
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "\t.text")?;
+        writeln!(f, "\t.globl {}", Self::PRELUDE)?;
+        for label in self.block_order() {
+            writeln!(f, "{}:", label)?;
+            for inst in &self.blocks[&label] {
+                writeln!(f, "\t{}", inst)?;
+            }
+        }
+        if !self.strings.is_empty() {
+            writeln!(f, "\t.section .rodata")?;
+            for (label, s) in &self.strings {
+                writeln!(f, "{}:", label)?;
+                writeln!(f, "\t.string {}", token::quote(s))?;
+            }
+        }
+        writeln!(f, "\t.section .note.GNU-stack,\"\",@progbits")
+    }
+}
+
 #[derive(Clone)]
 pub enum Instr {
     Retq,
     Jump(Label),
+    /// 条件跳转, 条件不成立时继续执行下一条指令.
+    JmpIf(CondCode, Label),
     Callq(Label, usize), // 这个整数是参数个数
+    /// 调用操作数中地址处的函数, 整数是参数个数.
+    IndirectCallq(Arg, usize),
+    /// 尾调用: 跳转到操作数中的函数地址, 被调用的函数直接返回到当前函数的调用者.
+    /// 整数是参数个数. 在CodeGen中被展开为释放栈帧的指令和`jmp *%rax`.
+    TailJmp(Arg, usize),
+    /// 把第一个操作数的地址写入第二个操作数, 目标必须是寄存器.
+    Leaq(Arg, Arg),
     Pushq(Arg),
     Popq(Arg),
     Negq(Arg),
     Addq(Arg, Arg),
     Subq(Arg, Arg),
+    /// 第二个操作数必须是寄存器.
+    Imulq(Arg, Arg),
+    /// 用%rdx:%rax除以操作数, 商写入%rax, 余数写入%rdx. 操作数不能是立即数.
+    Idivq(Arg),
+    /// 把%rax符号扩展到%rdx:%rax.
+    Cqto,
+    Xorq(Arg, Arg),
+    /// 把第二个操作数左移, 位数是第一个操作数(立即数).
+    Salq(Arg, Arg),
+    /// 把第二个操作数算术右移, 位数是第一个操作数(立即数).
+    Sarq(Arg, Arg),
+    /// 比较第二个操作数与第一个操作数, 结果只写入标志寄存器.
+    Cmpq(Arg, Arg),
+    /// 按标志寄存器把比较结果(0或1)写入字节寄存器.
+    Set(CondCode, ByteReg),
+    /// 把字节寄存器零扩展到64位.
+    Movzbq(ByteReg, Arg),
     Movq(Arg, Arg),
+    /// 把64位立即数写入寄存器, 由PatchInstructions生成. 其他指令中的立即数都是符号扩展的32位数.
+    Movabsq(Arg, Arg),
+}
+
+/// `setcc`/`jcc`中的条件码.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum CondCode {
+    /// 溢出
+    O,
+    E,
+    Ne,
+    L,
+    Le,
+    G,
+    Ge,
+}
+
+impl CondCode {
+    /// 比较运算符对应的条件码.
+    pub fn from_kind(kind: Kind) -> Option<Self> {
+        use CondCode::*;
+        match kind {
+            Kind::EqualEqual => Some(E),
+            Kind::BangEqual => Some(Ne),
+            Kind::Less => Some(L),
+            Kind::LessEqual => Some(Le),
+            Kind::Greater => Some(G),
+            Kind::GreaterEqual => Some(Ge),
+            _ => None,
+        }
+    }
+}
+
+impl Display for CondCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use CondCode::*;
+        match self {
+            O => write!(f, "o"),
+            E => write!(f, "e"),
+            Ne => write!(f, "ne"),
+            L => write!(f, "l"),
+            Le => write!(f, "le"),
+            G => write!(f, "g"),
+            Ge => write!(f, "ge"),
+        }
+    }
 }
 
 impl Display for Instr {
@@ -22,15 +217,28 @@ impl Display for Instr {
         use Instr::*;
         match self {
             Retq => write!(f, "retq"),
-            Jump(l) => write!(f, "jmpq {}", l),
+            Jump(l) => write!(f, "jmp {}", l),
+            JmpIf(cc, l) => write!(f, "j{} {}", cc, l),
             Callq(l, _) => write!(f, "callq {}", l),
+            IndirectCallq(a, _) => write!(f, "callq *{}", a),
+            TailJmp(a, _) => write!(f, "jmp *{}", a),
+            Leaq(s, d) => write!(f, "leaq {}, {}", s, d),
             Pushq(a) => write!(f, "pushq {}", a),
             Popq(a) => write!(f, "popq {}", a),
             Addq(s, d) => write!(f, "addq {}, {}", s, d),
             Subq(s, d) => write!(f, "subq {}, {}", s, d),
             Negq(a) => write!(f, "negq {}", a),
+            Imulq(s, d) => write!(f, "imulq {}, {}", s, d),
+            Idivq(a) => write!(f, "idivq {}", a),
+            Cqto => write!(f, "cqto"),
+            Xorq(s, d) => write!(f, "xorq {}, {}", s, d),
+            Salq(s, d) => write!(f, "salq {}, {}", s, d),
+            Sarq(s, d) => write!(f, "sarq {}, {}", s, d),
+            Cmpq(s, d) => write!(f, "cmpq {}, {}", s, d),
+            Set(cc, b) => write!(f, "set{} %{}", cc, b),
+            Movzbq(b, d) => write!(f, "movzbq %{}, {}", b, d),
             Movq(s, d) => write!(f, "movq {}, {}", s, d),
-            _ => unimplemented!(),
+            Movabsq(s, d) => write!(f, "movabsq {}, {}", s, d),
         }
     }
 }
@@ -53,7 +261,8 @@ impl ReadWriteSet for Instr {
         }
         match self {
             Retq => set,
-            Jump(_) => set,
+            // 跳转目标处的活跃变量由活跃性分析负责.
+            Jump(_) | JmpIf(_, _) => set,
             Callq(_, len) => {
                 let regs: Vec<Reg> = Reg::args_passing().into_iter().take(*len).collect();
                 for r in regs {
@@ -61,6 +270,14 @@ impl ReadWriteSet for Instr {
                 }
                 set
             }
+            TailJmp(a, len) | IndirectCallq(a, len) => {
+                for r in Reg::args_passing().into_iter().take(*len) {
+                    set.insert(Arg::Reg(r));
+                }
+                insert_loc!(set, a);
+                set
+            }
+            Leaq(_s, _d) => set,
             Pushq(loc) => {
                 set.insert(Arg::Reg(Reg::Rsp));
                 insert_loc!(set, loc);
@@ -80,11 +297,31 @@ impl ReadWriteSet for Instr {
                 insert_loc!(set, d);
                 set
             }
-            Negq(a) => {
+            Negq(a) | Salq(_, a) | Sarq(_, a) => {
                 insert_loc!(set, a);
                 set
             }
-            Movq(s, _d) => {
+            Imulq(s, d) | Xorq(s, d) | Cmpq(s, d) => {
+                insert_loc!(set, s);
+                insert_loc!(set, d);
+                set
+            }
+            Idivq(a) => {
+                insert_loc!(set, a);
+                set.insert(Arg::Reg(Reg::Rax));
+                set.insert(Arg::Reg(Reg::Rdx));
+                set
+            }
+            Cqto => {
+                set.insert(Arg::Reg(Reg::Rax));
+                set
+            }
+            Set(_, _) => set,
+            Movzbq(b, _d) => {
+                set.insert(Arg::Reg(b.full_reg()));
+                set
+            }
+            Movq(s, _d) | Movabsq(s, _d) => {
                 insert_loc!(set, s);
                 set
             }
@@ -103,15 +340,20 @@ impl ReadWriteSet for Instr {
         let mut set = HashSet::new();
         match self {
             Retq => set,
-            Jump(_) => set,
-            Callq(_, len) => {
+            Jump(_) | JmpIf(_, _) => set,
+            TailJmp(_, _) => set,
+            Leaq(_s, d) => {
+                insert_loc!(set, d);
+                set
+            }
+            Callq(_, _) | IndirectCallq(_, _) => {
                 let regs: Vec<Reg> = Reg::caller_saved();
                 for r in regs {
                     set.insert(Arg::Reg(r));
                 }
                 set
             }
-            Pushq(loc) => {
+            Pushq(_loc) => {
                 set.insert(Arg::Reg(Reg::Rsp));
                 set
             }
@@ -120,12 +362,11 @@ impl ReadWriteSet for Instr {
                 insert_loc!(set, loc);
                 set
             }
-            Addq(s, d) => {
+            Addq(_s, d) => {
                 insert_loc!(set, d);
-
                 set
             }
-            Subq(s, d) => {
+            Subq(_s, d) => {
                 insert_loc!(set, d);
                 set
             }
@@ -133,7 +374,30 @@ impl ReadWriteSet for Instr {
                 insert_loc!(set, a);
                 set
             }
-            Movq(s, d) => {
+            Imulq(_s, d) | Xorq(_s, d) | Salq(_s, d) | Sarq(_s, d) => {
+                insert_loc!(set, d);
+                set
+            }
+            Idivq(_) => {
+                set.insert(Arg::Reg(Reg::Rax));
+                set.insert(Arg::Reg(Reg::Rdx));
+                set
+            }
+            Cqto => {
+                set.insert(Arg::Reg(Reg::Rdx));
+                set
+            }
+            Cmpq(_, _) => set,
+            // 写字节寄存器相当于写它所在的64位寄存器.
+            Set(_, b) => {
+                set.insert(Arg::Reg(b.full_reg()));
+                set
+            }
+            Movzbq(_b, d) => {
+                insert_loc!(set, d);
+                set
+            }
+            Movq(_s, d) | Movabsq(_s, d) => {
                 insert_loc!(set, d);
                 set
             }
@@ -147,6 +411,12 @@ pub enum Arg {
     Reg(Reg),
     Deref(Reg, i64),
     Var(String),
+    /// 函数的地址, 只能作为`leaq`的源操作数.
+    FunRef(Label),
+    /// 只读数据段中字符串的地址, 只能作为`leaq`的源操作数.
+    StrRef(Label),
+    /// 运行时中的全局变量, 是内存操作数.
+    Global(Label),
 }
 
 impl Display for Arg {
@@ -157,16 +427,14 @@ impl Display for Arg {
             Reg(r) => write!(f, "%{}", r),
             Deref(r, offset) => write!(f, "{}(%{})", offset, r),
             Var(v) => write!(f, "{}", v),
+            FunRef(l) | StrRef(l) | Global(l) => write!(f, "{}(%rip)", l),
         }
     }
 }
 
 impl Arg {
     pub fn is_mem(&self) -> bool {
-        match self {
-            Self::Deref(_, _) => true,
-            _ => false,
-        }
+        matches!(self, Self::Deref(_, _) | Self::Global(_))
     }
 
     pub fn get_location(&self) -> Option<Arg> {
@@ -185,7 +453,7 @@ impl Arg {
     }
 }
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Reg {
     Rsp,
     Rbp,
@@ -216,11 +484,7 @@ impl Reg {
     }
 
     pub fn is_callee_saved(&self) -> bool {
-        use Reg::*;
-        match self {
-            Rsp | Rbp | Rbx | R12 | R13 | R14 | R15 => true,
-            _ => false,
-        }
+        Self::callee_saved().contains(self)
     }
     pub fn callee_saved() -> Vec<Reg> {
         use Reg::*;
@@ -228,6 +492,30 @@ impl Reg {
     }
 }
 
+/// 64位寄存器的最低字节, 用于`setcc`和`movzbq`.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum ByteReg {
+    Al,
+}
+
+impl ByteReg {
+    pub fn full_reg(&self) -> Reg {
+        use ByteReg::*;
+        match self {
+            Al => Reg::Rax,
+        }
+    }
+}
+
+impl Display for ByteReg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ByteReg::*;
+        match self {
+            Al => write!(f, "al"),
+        }
+    }
+}
+
 impl Display for Reg {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use Reg::*;
@@ -29,20 +29,20 @@ pub struct ErrorReporter {
 }
 
 impl ErrorReporter {
+    /// 位置都以字符而不是字节计.
     pub fn new(file: Option<PathBuf>, source: String) -> Self {
         let mut lines: Vec<(usize, usize)> = vec![];
         let mut start = 0_usize;
+        let mut len = 0;
         for (pos, ch) in source.chars().enumerate() {
-            match ch {
-                '\n' => {
-                    lines.push((start, pos));
-                    start = pos + 1;
-                }
-                _ => (),
+            if ch == '\n' {
+                lines.push((start, pos));
+                start = pos + 1;
             }
+            len = pos + 1;
         }
-        if start < source.len() {
-            lines.push((start, source.len()))
+        if start < len {
+            lines.push((start, len))
         }
         Self {
             file,
@@ -95,6 +95,15 @@ impl ErrorReporter {
         self.report("Error", lineno, message, file_pos, len)
     }
 
+    /// 把已经报告过的多个错误合并为一个. 只有一个错误时原样返回.
+    pub fn collect(&self, mut errors: Vec<Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            n => Err(ParsingError::new(&format!("{} syntax errors", n)).into()),
+        }
+    }
+
     pub fn report<S: AsRef<str> + ?Sized>(
         &self,
         level: &str,
@@ -114,6 +123,14 @@ impl ErrorReporter {
         Err(ParsingError::new(message).into())
     }
 
+    /// 源文件中字符位置`pos`所在的行号和列号, 都从1开始.
+    pub fn line_col(&self, pos: usize) -> (usize, usize) {
+        match self.range2lineno((pos, pos)) {
+            Some((line, _)) => (line, self.inline_coordinates(line, pos).1 + 1),
+            None => (0, 0),
+        }
+    }
+
     // 行内坐标
     #[inline]
     fn inline_coordinates(&self, lineno: usize, file_pos: usize) -> (usize, usize) {
@@ -121,7 +138,7 @@ impl ErrorReporter {
 
         for i in 0..(lineno - 1) {
             let len = self.lines[i].1 - self.lines[i].0;
-            start = start - (len + 1);
+            start -= len + 1;
         }
 
         (lineno, start)
@@ -173,8 +190,8 @@ impl ErrorReporter {
         );
     }
     #[inline]
-    fn get_line(&self, lineno: usize) -> &str {
+    fn get_line(&self, lineno: usize) -> String {
         let (start, end) = self.lines[lineno - 1];
-        &self.source[start..end]
+        self.source.chars().skip(start).take(end - start).collect()
     }
 }
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, error::Error, fmt::format};
+use std::{collections::HashMap, error::Error};
 
 use crate::{
     reporter::ErrorReporter,
@@ -17,6 +17,14 @@ pub struct Scanner<'r> {
 
     /// 生成的Token序列.
     tokens: Vec<Token>,
+    /// 未闭合的圆括号和方括号的个数. 括号中的换行不产生`NewLine`, 所以参数列表可以跨行.
+    paren_depth: usize,
+    /// 缩进模式: 用行首的缩进代替`{`和`}`划分块.
+    layout: bool,
+    /// 缩进模式中外层各个块的缩进宽度, 栈底是0.
+    indents: Vec<usize>,
+    /// 下一个字符是否在逻辑行的开头.
+    at_line_start: bool,
 
     keywords: HashMap<&'static str, Kind>,
 }
@@ -28,7 +36,14 @@ impl<'r> Scanner<'r> {
         keywords.insert("and", Kind::And);
         keywords.insert("or", Kind::Or);
         keywords.insert("if", Kind::If);
+        keywords.insert("elif", Kind::Elif);
         keywords.insert("else", Kind::Else);
+        keywords.insert("while", Kind::While);
+        keywords.insert("for", Kind::For);
+        keywords.insert("def", Kind::Func);
+        keywords.insert("return", Kind::Return);
+        keywords.insert("in", Kind::In);
+        keywords.insert("lambda", Kind::Lambda);
         keywords.insert("true", Kind::True);
         keywords.insert("false", Kind::False);
         keywords
@@ -41,9 +56,19 @@ impl<'r> Scanner<'r> {
             start: 0,
             current: 0,
             tokens: vec![],
+            paren_depth: 0,
+            layout: false,
+            indents: vec![0],
+            at_line_start: true,
             keywords: Self::init_keywords(),
         }
     }
+
+    /// 开启缩进模式: 缩进增加时产生`Indent`, 减少时每退出一层产生一个`Dedent`.
+    pub fn layout(mut self) -> Self {
+        self.layout = true;
+        self
+    }
     pub fn peek(&self) -> Option<char> {
         self.source.get(self.current).copied()
     }
@@ -56,15 +81,24 @@ impl<'r> Scanner<'r> {
 
     pub fn scan_tokens(mut self) -> Result<Vec<Token>, Box<dyn Error>> {
         while !self.is_end() {
+            if self.layout && self.at_line_start {
+                self.indentation()?;
+                if self.is_end() {
+                    break;
+                }
+            }
             self.start = self.current;
             self.scan_token()?;
         }
-        self.tokens.push(Token::new(
-            Kind::Eof,
-            "<EOF>".to_string(),
-            self.line,
-            self.current,
-        ));
+        // 文件结束时关闭所有的块. 这些记号放在最后一行的末尾, 报错时才能指向实际的代码.
+        let (line, pos) = self.end_position();
+        while self.indents.len() > 1 {
+            self.indents.pop();
+            self.tokens
+                .push(Token::new(Kind::Dedent, String::new(), line, pos));
+        }
+        self.tokens
+            .push(Token::new(Kind::Eof, "<EOF>".to_string(), line, pos));
 
         Ok(self.tokens)
     }
@@ -74,18 +108,53 @@ impl<'r> Scanner<'r> {
 
         match next_char {
             ' ' | '\t' | '\r' => (), // 跳过
+            // `NewLine`属于它结束的那一行.
             '\n' => {
+                if self.paren_depth == 0 {
+                    self.add_token(Kind::NewLine);
+                    self.at_line_start = true;
+                }
                 self.line += 1;
-                self.add_token(Kind::NewLine);
             }
-            '+' => self.add_token(Kind::Plus),
+            // 注释到行尾为止, 换行符仍然产生`NewLine`.
+            '#' => {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.advance();
+                }
+            }
+            // 续行: 反斜杠和紧跟的换行符都被忽略.
+            '\\' => {
+                if self.peek() == Some('\r') {
+                    self.advance();
+                }
+                match self.advance() {
+                    Some('\n') => self.line += 1,
+                    _ => self.reporter.error(
+                        "Expected a newline after `\\`",
+                        self.line,
+                        self.start,
+                        1,
+                    )?,
+                }
+            }
+            '+' => self.op_or_opeq(Kind::Plus, Kind::PlusEqual),
             '-' => {
-                self.add_token(Kind::Minus);
+                if self.peek() == Some('>') {
+                    self.advance();
+                    self.add_token(Kind::Arrow);
+                } else {
+                    self.op_or_opeq(Kind::Minus, Kind::MinusEqual);
+                }
             }
-            '*' => self.add_token(Kind::Plus),
+            '*' => self.op_or_opeq(Kind::Star, Kind::StarEqual),
+            // `//`与`/`一样都是整数除法.
             '/' => {
-                self.add_token(Kind::Minus);
+                if self.peek() == Some('/') {
+                    self.advance();
+                }
+                self.op_or_opeq(Kind::Slash, Kind::SlashEqual);
             }
+            '%' => self.op_or_opeq(Kind::Percent, Kind::PercentEqual),
             ',' => self.add_token(Kind::Comma),
             '=' => self.op_or_opeq(Kind::Equal, Kind::EqualEqual),
 
@@ -99,12 +168,15 @@ impl<'r> Scanner<'r> {
             '<' => self.op_or_opeq(Kind::Less, Kind::LessEqual),
 
             ':' => self.add_token(Kind::Colon),
-            '(' => self.add_token(Kind::LeftParen),
-            ')' => self.add_token(Kind::RightParen),
+            '(' => self.open_paren(Kind::LeftParen),
+            ')' => self.close_paren(Kind::RightParen),
             '{' => self.add_token(Kind::LeftBrace),
             '}' => self.add_token(Kind::RightBrace),
+            '[' => self.open_paren(Kind::LeftBracket),
+            ']' => self.close_paren(Kind::RightBracket),
             'A'..='Z' | 'a'..='z' | '_' => self.expect_ident()?,
             '0'..='9' => self.expect_number()?,
+            '"' | '\'' => self.expect_string(next_char)?,
             o => self.reporter.error(
                 &format!("Unexpected character: `{}`", o),
                 self.line,
@@ -115,6 +187,43 @@ impl<'r> Scanner<'r> {
         Ok(())
     }
 
+    /// 读入行首的缩进, tab对齐到8的倍数. 空行和只有注释的行不影响块的划分.
+    fn indentation(&mut self) -> Result<(), Box<dyn Error>> {
+        self.at_line_start = false;
+        self.start = self.current;
+        let mut width = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' => width += 1,
+                '\t' => width = (width / 8 + 1) * 8,
+                _ => break,
+            }
+            self.advance();
+        }
+        if matches!(self.peek(), None | Some('\n' | '\r' | '#')) {
+            return Ok(());
+        }
+        if width > *self.indents.last().unwrap() {
+            self.indents.push(width);
+            self.add_token(Kind::Indent);
+            return Ok(());
+        }
+        while width < *self.indents.last().unwrap() {
+            self.indents.pop();
+            self.add_token(Kind::Dedent);
+        }
+        if width != *self.indents.last().unwrap() {
+            let msg = "Unindent does not match any outer indentation level";
+            self.reporter.error(
+                msg,
+                self.line,
+                self.start,
+                (self.current - self.start).max(1),
+            )?;
+        }
+        Ok(())
+    }
+
     fn op_or_opeq(&mut self, op: Kind, opeq: Kind) {
         match self.peek() {
             Some('=') => {
@@ -124,6 +233,17 @@ impl<'r> Scanner<'r> {
             _ => self.add_token(op),
         }
     }
+    fn open_paren(&mut self, kind: Kind) {
+        self.paren_depth += 1;
+        self.add_token(kind);
+    }
+
+    /// 多余的右括号留给Parser报错.
+    fn close_paren(&mut self, kind: Kind) {
+        self.paren_depth = self.paren_depth.saturating_sub(1);
+        self.add_token(kind);
+    }
+
     fn add_token(&mut self, kind: token::Kind) {
         let tk = Token::new(kind, self.current_lexeme(), self.line, self.start);
         self.tokens.push(tk)
@@ -131,15 +251,21 @@ impl<'r> Scanner<'r> {
 
     #[inline]
     fn current_lexeme(&self) -> String {
-        unsafe {
-            String::from_utf8_unchecked(
-                self.source[self.start..self.current]
-                    .iter()
-                    .map(|c| *c as u8)
-                    .collect(),
-            )
+        self.source[self.start..self.current].iter().collect()
+    }
+    /// 最后一个非空白字符之后的位置及其行号. 末尾的空行和空白不算在内.
+    fn end_position(&self) -> (usize, usize) {
+        let mut line = self.line;
+        let mut pos = self.source.len();
+        while pos > 0 && self.source[pos - 1].is_whitespace() {
+            pos -= 1;
+            if self.source[pos] == '\n' {
+                line -= 1;
+            }
         }
+        (line, pos)
     }
+
     #[inline]
     fn is_end(&self) -> bool {
         self.current >= self.source.len()
@@ -163,41 +289,121 @@ impl<'r> Scanner<'r> {
         self.keywords.get(self.current_lexeme().as_str()).cloned()
     }
 
-    fn expect_number(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut can_end = true;
-        let mut kind = Kind::Integer;
-
-        while !self.is_end() {
-            match self.peek().unwrap() {
-                '0'..='9' => {
-                    self.advance();
-                    can_end = true
+    /// 字符串不能跨行. Token的lexeme包括引号和转义, 它的值由`token::unescape`得到.
+    fn expect_string(&mut self, quote: char) -> Result<(), Box<dyn Error>> {
+        loop {
+            match self.peek() {
+                Some(c) if c == quote => break,
+                None | Some('\n') => {
+                    let len = self.current - self.start;
+                    return self
+                        .reporter
+                        .error("Unterminated string", self.line, self.start, len);
                 }
-                '.' => {
-                    if let Kind::Integer = kind {
+                // 转义的引号不结束字符串.
+                Some('\\') => {
+                    self.advance();
+                    if self.peek().is_some_and(|c| c != '\n') {
                         self.advance();
-                        kind = Kind::Float;
-                        can_end = false;
-                    } else {
-                        self.reporter
-                            .error("Unexpected `.`", self.line, self.current, 1)?
                     }
                 }
-                _ => {
-                    if can_end {
-                        break;
-                    } else {
-                        self.reporter.error(
-                            "Expected a digit after `.`",
-                            self.line,
-                            self.current,
-                            1,
-                        )?
-                    }
+                Some(_) => {
+                    self.advance();
                 }
             }
         }
-        self.add_token(kind);
+        self.advance();
+        if let Err(c) = token::unescape(&self.current_lexeme()) {
+            let msg = format!("Unknown escape sequence `\\{}`", c);
+            let len = self.current - self.start;
+            self.reporter.error(&msg, self.line, self.start, len)?;
+        }
+        self.add_token(Kind::Str);
+        Ok(())
+    }
+
+    /// 十进制数可以是浮点数. `0x`, `0b`, `0o`开头的是十六, 二, 八进制整数.
+    /// 数字之间可以用`_`分隔, 如`1_000_000`.
+    fn expect_number(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.source[self.start] == '0' {
+            let radix = match self.peek() {
+                Some('x' | 'X') => Some((16, "hexadecimal")),
+                Some('b' | 'B') => Some((2, "binary")),
+                Some('o' | 'O') => Some((8, "octal")),
+                _ => None,
+            };
+            if let Some((radix, name)) = radix {
+                self.advance();
+                if self.expect_digits(radix)? == 0 {
+                    let msg = format!("Expected a digit in {} literal", name);
+                    self.reporter.error(&msg, self.line, self.current, 1)?;
+                }
+                // 紧跟的字母或数字不属于这个进制.
+                if let Some(c) = self.peek().filter(char::is_ascii_alphanumeric) {
+                    let msg = format!("Invalid digit `{}` in {} literal", c, name);
+                    self.reporter.error(&msg, self.line, self.current, 1)?;
+                }
+                return self.add_integer();
+            }
+        }
+
+        self.expect_digits(10)?;
+        if self.peek() != Some('.') {
+            return self.add_integer();
+        }
+        self.advance();
+        if self.expect_digits(10)? == 0 {
+            self.reporter
+                .error("Expected a digit after `.`", self.line, self.current, 1)?;
+        }
+        if self.peek() == Some('.') {
+            self.reporter
+                .error("Unexpected `.`", self.line, self.current, 1)?;
+        }
+        self.add_token(Kind::Float);
+        Ok(())
+    }
+
+    /// 读入`radix`进制的数字和分隔数字的`_`, 返回数字的个数.
+    fn expect_digits(&mut self, radix: u32) -> Result<usize, Box<dyn Error>> {
+        let mut count = 0;
+        while let Some(c) = self.peek() {
+            if c.is_digit(radix) {
+                count += 1;
+            } else if c == '_' {
+                if !self
+                    .source
+                    .get(self.current + 1)
+                    .is_some_and(|c| c.is_digit(radix))
+                {
+                    self.reporter.error(
+                        "Expected a digit after `_`",
+                        self.line,
+                        self.current,
+                        1,
+                    )?;
+                }
+            } else {
+                break;
+            }
+            self.advance();
+        }
+        Ok(count)
+    }
+
+    /// 超出64位整数范围的字面量在这里报错. 例外是`-`之后的2^63, 由`Parser::unary`处理.
+    fn add_integer(&mut self) -> Result<(), Box<dyn Error>> {
+        self.add_token(Kind::Integer);
+        let (tk, prev) = match self.tokens.as_slice() {
+            [.., prev, tk] => (tk, Some(prev)),
+            [tk] => (tk, None),
+            [] => unreachable!(),
+        };
+        let negated = prev.is_some_and(|p| p.kind() == Kind::Minus) && tk.negated_int().is_some();
+        if i64::try_from(tk.clone()).is_err() && !negated {
+            self.reporter
+                .error_token("Integer literal is out of range for a 64-bit integer", tk)?;
+        }
         Ok(())
     }
 }
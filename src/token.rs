@@ -4,19 +4,25 @@ use crate::ast::Range;
 pub enum Kind {
     /// Single character
     LeftParen, // (
-    RightParen, // )
-    LeftBrace,  // {
-    RightBrace, // }
-    Comma,      // ,
-    Dot,        // .
-    Minus,      // -
-    Plus,       // +
-    Semicolon,  // ;
-    Slash,      // /
-    Star,       // *
-    Question,   // ?
-    Colon,      // :
-    NewLine,    // \n
+    RightParen,   // )
+    LeftBrace,    // {
+    RightBrace,   // }
+    LeftBracket,  // [
+    RightBracket, // ]
+    Comma,        // ,
+    Dot,          // .
+    Minus,        // -
+    Plus,         // +
+    Semicolon,    // ;
+    Slash,        // /
+    Star,         // *
+    Percent,      // %
+    Question,     // ?
+    Colon,        // :
+    NewLine,      // \n
+    /// 缩进模式中缩进增加和减少一层.
+    Indent,
+    Dedent,
 
     /// One or Two character
     Bang, // !
@@ -27,6 +33,12 @@ pub enum Kind {
     GreaterEqual, // >
     Less,         // <
     LessEqual,    // <=
+    PlusEqual,    // +=
+    MinusEqual,   // -=
+    StarEqual,    // *=
+    SlashEqual,   // /= //=
+    PercentEqual, // %=
+    Arrow,        // ->
 
     /// literals
     Name, //(String),
@@ -37,11 +49,14 @@ pub enum Kind {
     /// Keywords
     And,
     Class,
+    Elif,
     Else,
     False,
     Func,
     For,
     If,
+    In,
+    Lambda,
     Nil,
     Or,
     Print,
@@ -66,21 +81,73 @@ pub struct Token {
 
 impl Range for Token {
     fn range(&self) -> (usize, usize) {
-        (self.pos, self.pos + self.lexeme.len() - 1)
+        (self.pos, self.pos + self.len().max(1) - 1)
     }
 }
 
+/// 整数字面量可以有`0x`, `0b`, `0o`前缀, 数字之间可以有`_`.
 impl TryFrom<Token> for i64 {
     type Error = std::num::ParseIntError;
     fn try_from(value: Token) -> Result<Self, Self::Error> {
-        value.lexeme().parse::<i64>()
+        let (digits, radix) = int_digits(value.lexeme());
+        i64::from_str_radix(&digits, radix)
     }
 }
+
+/// 去掉前缀和`_`之后的数字, 以及进制.
+fn int_digits(lexeme: &str) -> (String, u32) {
+    let digits = lexeme.replace('_', "");
+    let radix = match digits.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0b" | "0B") => 2,
+        Some("0o" | "0O") => 8,
+        _ => return (digits, 10),
+    };
+    (digits[2..].to_string(), radix)
+}
 impl TryFrom<Token> for f64 {
     type Error = std::num::ParseFloatError;
     fn try_from(value: Token) -> Result<Self, Self::Error> {
-        value.lexeme().parse::<f64>()
+        value.lexeme().replace('_', "").parse::<f64>()
+    }
+}
+
+/// 字符串字面量的值: 去掉两边的引号, 处理转义. 遇到不支持的转义时返回反斜杠之后的字符.
+pub fn unescape(lexeme: &str) -> Result<String, char> {
+    let mut chars = lexeme[1..lexeme.len() - 1].chars();
+    let mut s = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => s.push('\n'),
+            Some('t') => s.push('\t'),
+            Some(c @ ('"' | '\'' | '\\')) => s.push(c),
+            Some(c) => return Err(c),
+            None => return Err('\\'),
+        }
     }
+    Ok(s)
+}
+
+/// `unescape`的逆运算, 总是使用双引号. 结果也是合法的GNU汇编字符串.
+pub fn quote(s: &str) -> String {
+    let mut res = String::from('"');
+    for c in s.chars() {
+        match c {
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '"' | '\\' => {
+                res.push('\\');
+                res.push(c);
+            }
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
 }
 
 impl Token {
@@ -100,14 +167,21 @@ impl Token {
         &self.lexeme
     }
 
+    /// 字符个数, 与源文件中的位置一致.
     pub fn len(&self) -> usize {
-        self.lexeme.len()
+        self.lexeme.chars().count()
     }
 
     pub fn line(&self) -> usize {
         self.line
     }
 
+    /// 整数字面量取负之后的值. `-9223372036854775808`中的字面量本身超出了`i64`的范围.
+    pub fn negated_int(&self) -> Option<i64> {
+        let (digits, radix) = int_digits(self.lexeme());
+        i64::from_str_radix(&format!("-{}", digits), radix).ok()
+    }
+
     pub fn pos(&self) -> usize {
         self.pos
     }
@@ -1,9 +1,16 @@
-use std::{error::Error, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    mem,
+};
 
 use crate::{
-    ast::{Expr, Stmt},
+    ast::{Expr, ExprData, Stmt, StmtData, TypeExpr},
     env::Env,
+    pass::closure_conversion::free_vars,
     reporter::ErrorReporter,
+    token::{Kind, Token},
 };
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
@@ -13,7 +20,16 @@ pub enum Type {
     Int,
     Float,
     Bool,
-    Func { params: Vec<Type>, ret: Box<Type> },
+    /// 只读数据段中的字符串.
+    Str,
+    Func {
+        params: Vec<Type>,
+        ret: Box<Type>,
+    },
+    /// 在堆上分配的元组.
+    Tuple(Vec<Type>),
+    /// 在堆上分配的列表, 元素的类型相同. 长度在分配时确定, 之后不能增长, 只能修改元素.
+    List(Box<Type>),
 }
 
 impl Type {
@@ -22,6 +38,7 @@ impl Type {
         match (self, other) {
             (Any, _) | (_, Any) => true,
             (Unit, Unit) | (Int, Int) | (Float, Float) | (Bool, Bool) => true,
+            (Str, Str) => true,
             (
                 Func {
                     params: p1,
@@ -31,10 +48,23 @@ impl Type {
                     params: p2,
                     ret: r2,
                 },
-            ) => r1.is_compatible(r2) && p1.iter().zip(p2.iter()).all(|(a, b)| a.is_compatible(b)),
+            ) => {
+                p1.len() == p2.len()
+                    && r1.is_compatible(r2)
+                    && p1.iter().zip(p2).all(|(a, b)| a.is_compatible(b))
+            }
+            (Tuple(t1), Tuple(t2)) => {
+                t1.len() == t2.len() && t1.iter().zip(t2).all(|(a, b)| a.is_compatible(b))
+            }
+            (List(t1), List(t2)) => t1.is_compatible(t2),
             _ => false,
         }
     }
+
+    /// 这个类型的值是否是指向堆上对象的指针. 函数值都是堆上的闭包.
+    pub fn is_heap(&self) -> bool {
+        matches!(self, Type::Tuple(_) | Type::List(_) | Type::Func { .. })
+    }
 }
 impl Display for Type {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -46,13 +76,16 @@ impl Display for Type {
             Int => write!(f, "int"),
             Float => write!(f, "float"),
             Bool => write!(f, "bool"),
+            Str => write!(f, "str"),
             Func { params, ret } => {
-                write!(f, "(")?;
-                for p in params {
-                    write!(f, "{},", p)?;
-                }
-                write!(f, ") => {}", ret)
+                let params: Vec<String> = params.iter().map(ToString::to_string).collect();
+                write!(f, "Callable[[{}], {}]", params.join(", "), ret)
+            }
+            Tuple(elems) => {
+                let elems: Vec<String> = elems.iter().map(ToString::to_string).collect();
+                write!(f, "tuple[{}]", elems.join(", "))
             }
+            List(elem) => write!(f, "list[{}]", elem),
         }
     }
 }
@@ -60,10 +93,56 @@ impl Display for Type {
 type Result<T> = std::result::Result<T, Box<dyn Error>>;
 type TypeEnv = Env<Type>;
 
+/// 不能用作函数名: 运行时中的函数和全局变量, 以及`main`和它的入口块, 结尾块的标签.
+const RESERVED: [&str; 16] = [
+    "main",
+    "start",
+    "conclusion",
+    "read_int",
+    "print_int",
+    "print_bool",
+    "print_str",
+    "arith_error",
+    "index_error",
+    "initialize",
+    "collect",
+    "free_ptr",
+    "fromspace_begin",
+    "fromspace_end",
+    "rootstack_begin",
+    "rootstack_end",
+];
+
+/// 内建函数, 只能被调用, 不能作为值使用. `print_bool`和`print_str`是类型检查改写`print`得到的.
+pub const BUILTINS: [&str; 6] = [
+    "print",
+    "print_int",
+    "print_bool",
+    "print_str",
+    "input_int",
+    "len",
+];
+
+/// 除了`BUILTINS`之外, 编译器生成的代码直接调用的运行时中的函数.
+pub const RUNTIME_FUNCTIONS: [&str; 3] = ["read_int", "arith_error", "index_error"];
+
+/// 元组的最大长度, 由运行时中tag的格式决定.
+pub const MAX_TUPLE_LEN: usize = 50;
+
 pub struct TypeChecker<'r> {
     reporter: &'r ErrorReporter,
 
     env: TypeEnv,
+    /// 内建函数和所有顶层函数. 函数体只能看到这些名字, 看不到顶层的变量.
+    globals: TypeEnv,
+    /// 当前所在函数的返回值类型, 在顶层时为`None`.
+    ret: Option<Type>,
+    /// 当前函数(或顶层)中赋值过的变量第一次赋值时的类型, 不论在哪个作用域中.
+    locals: HashMap<String, Type>,
+    /// 当前函数中被lambda捕获过的变量. 闭包捕获的是变量当时的值, 所以之后不能再给它赋值.
+    captured: HashSet<String>,
+    /// 当前所在的循环中赋值的变量, 不在循环中时为`None`.
+    loop_assigned: Option<Vec<Token>>,
 }
 
 impl<'r> TypeChecker<'r> {
@@ -90,13 +169,177 @@ impl<'r> TypeChecker<'r> {
                 ret: Type::Unit.into(),
             },
         );
-        Self { reporter: r, env }
+        env.insert(
+            "len".into(),
+            Type::Func {
+                params: vec![Type::Any],
+                ret: Type::Int.into(),
+            },
+        );
+        Self {
+            reporter: r,
+            globals: env.clone(),
+            env,
+            ret: None,
+            locals: HashMap::new(),
+            captured: HashSet::new(),
+            loop_assigned: None,
+        }
     }
-    pub fn check(mut self, ast: &Vec<Stmt>) -> Result<()> {
+    /// 检查整个程序. 同时会把内建的`print`改写为与其参数类型对应的运行时函数.
+    /// 函数可以在定义之前调用, 所以先收集所有顶层函数的类型.
+    pub fn check(mut self, ast: &mut [Stmt]) -> Result<()> {
+        for s in ast.iter() {
+            if let StmtData::Def {
+                name, params, ret, ..
+            } = &s.stmt
+            {
+                self.declare(name, params, ret.as_ref())?;
+            }
+        }
+        self.globals = self.env.clone();
         self.check_stmts(ast)?;
         Ok(())
     }
 
+    fn declare(
+        &mut self,
+        name: &Token,
+        params: &[(Token, TypeExpr)],
+        ret: Option<&TypeExpr>,
+    ) -> Result<()> {
+        if RESERVED.contains(&name.lexeme()) || self.env.lookup(name.lexeme()).is_some() {
+            let msg = format!("`{}` is already defined", name.lexeme());
+            self.reporter.error_token(&msg, name)?;
+        }
+        let mut param_tys = vec![];
+        for (i, (p, ty)) in params.iter().enumerate() {
+            if params[..i].iter().any(|(q, _)| q.lexeme() == p.lexeme()) {
+                let msg = format!("Duplicate parameter `{}`", p.lexeme());
+                self.reporter.error_token(&msg, p)?;
+            }
+            param_tys.push(self.resolve_type(ty)?);
+        }
+        let ret = match ret {
+            Some(ty) => self.resolve_type(ty)?,
+            None => Type::Unit,
+        };
+        let ty = Type::Func {
+            params: param_tys,
+            ret: ret.into(),
+        };
+        self.env.insert(name.lexeme().into(), ty);
+        Ok(())
+    }
+
+    /// 类型标注表示的类型.
+    fn resolve_type(&self, ty: &TypeExpr) -> Result<Type> {
+        match ty {
+            TypeExpr::Name(t) => match t.lexeme() {
+                "int" => Ok(Type::Int),
+                "bool" => Ok(Type::Bool),
+                "str" => Ok(Type::Str),
+                _ => Err(self.reporter.error_token("Unknown type", t).unwrap_err()),
+            },
+            TypeExpr::Generic { name, args, .. } if name.lexeme() == "tuple" => {
+                if args.len() > MAX_TUPLE_LEN {
+                    let msg = format!("Tuples can have at most {} elements", MAX_TUPLE_LEN);
+                    self.reporter.error_range(ty, &msg)?;
+                }
+                let mut elems = vec![];
+                for a in args {
+                    elems.push(self.resolve_type(a)?);
+                }
+                Ok(Type::Tuple(elems))
+            }
+            TypeExpr::Generic { name, args, .. } if name.lexeme() == "list" => {
+                if args.len() != 1 {
+                    self.reporter
+                        .error_range(ty, "Expected one type argument for `list`")?;
+                }
+                Ok(Type::List(self.resolve_type(&args[0])?.into()))
+            }
+            TypeExpr::Generic { name, .. } => {
+                Err(self.reporter.error_token("Unknown type", name).unwrap_err())
+            }
+            TypeExpr::Callable { params, ret, .. } => {
+                let mut param_tys = vec![];
+                for p in params {
+                    param_tys.push(self.resolve_type(p)?);
+                }
+                Ok(Type::Func {
+                    params: param_tys,
+                    ret: self.resolve_type(ret)?.into(),
+                })
+            }
+            TypeExpr::Checked(ty) => Ok(ty.clone()),
+        }
+    }
+
+    /// 变量不能与函数同名, 这样`f(x)`中的`f`总能确定是顶层函数还是保存着闭包的变量.
+    fn check_binding(&self, name: &Token) -> Result<()> {
+        if self.globals.lookup(name.lexeme()).is_some() {
+            let msg = format!("`{}` is already defined as a function", name.lexeme());
+            self.reporter.error_token(&msg, name)?;
+        }
+        Ok(())
+    }
+
+    /// 给变量赋值. 垃圾回收要知道变量中是不是指针, 所以堆上的对象不能与其他类型的值共用一个变量.
+    fn bind(&mut self, name: &Token, ty: Type) -> Result<()> {
+        self.check_assign(name)?;
+        match self.locals.get(name.lexeme()) {
+            Some(old) if *old != ty && (old.is_heap() || ty.is_heap()) => {
+                let msg = format!("`{}` is already a variable of type {}", name.lexeme(), old);
+                self.reporter.error_token(&msg, name)?;
+            }
+            Some(_) => (),
+            None => {
+                self.locals.insert(name.lexeme().into(), ty.clone());
+            }
+        }
+        self.env.insert(name.lexeme().into(), ty);
+        Ok(())
+    }
+
+    /// 已经被lambda捕获的变量不能再赋值, 否则闭包中看到的是旧的值.
+    fn check_assign(&mut self, name: &Token) -> Result<()> {
+        if self.captured.contains(name.lexeme()) {
+            let msg = format!(
+                "Cannot assign to `{}` after it is captured by a lambda",
+                name.lexeme()
+            );
+            self.reporter.error_token(&msg, name)?;
+        }
+        if let Some(assigned) = &mut self.loop_assigned {
+            assigned.push(name.clone());
+        }
+        Ok(())
+    }
+
+    /// 检查循环的条件和循环体. 下一次迭代中的赋值发生在这一次迭代中的捕获之后,
+    /// 所以循环中捕获的变量也不能在同一个循环中赋值.
+    fn check_loop<F>(&mut self, check: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        let outer = self.loop_assigned.replace(vec![]);
+        let res = check(self);
+        let assigned = mem::replace(&mut self.loop_assigned, outer).unwrap_or_default();
+        res?;
+        if let Some(name) = assigned.iter().find(|n| self.captured.contains(n.lexeme())) {
+            let msg = format!(
+                "Cannot assign to `{}` in a loop that captures it in a lambda",
+                name.lexeme()
+            );
+            self.reporter.error_token(&msg, name)?;
+        }
+        if let Some(outer) = &mut self.loop_assigned {
+            outer.extend(assigned);
+        }
+        Ok(())
+    }
+
     fn expect_same_type(&mut self, t1: &Type, t2: &Type, e: &Expr) -> Result<()> {
         if !t1.is_compatible(t2) {
             let msg = format!("{} != {}", t1, t2);
@@ -105,7 +348,7 @@ impl<'r> TypeChecker<'r> {
         Ok(())
     }
 
-    fn check_stmts(&mut self, stmts: &Vec<Stmt>) -> Result<Type> {
+    fn check_stmts(&mut self, stmts: &mut [Stmt]) -> Result<Type> {
         let mut res = Ok(Type::Unit);
         for s in stmts {
             if let Err(e) = self.check_stmt(s) {
@@ -115,9 +358,10 @@ impl<'r> TypeChecker<'r> {
         res
     }
 
-    fn check_stmt(&mut self, s: &Stmt) -> Result<Type> {
+    fn check_stmt(&mut self, s: &mut Stmt) -> Result<Type> {
         use super::ast::StmtData::*;
-        match &s.stmt {
+        let range = s.range;
+        match &mut s.stmt {
             Expr(e) => {
                 self.check_exp(e)?;
             }
@@ -140,44 +384,396 @@ impl<'r> TypeChecker<'r> {
                 let t2 = self.check_exp(else_)?;
                 self.env.exit_scope();
 
-                self.expect_same_type(&t1, &t2, then);
+                let _ = self.expect_same_type(&t1, &t2, then);
                 self.expect_same_type(&t2, &t2, else_)?;
             }
+            While { condition, body } => self.check_loop(|this| {
+                let cond = this.check_exp(condition)?;
+                this.expect_same_type(&cond, &Type::Bool, condition)?;
+
+                this.env.init_scope();
+                let res = this.check_exp(body);
+                this.env.exit_scope();
+                res.map(|_| ())
+            })?,
+            For {
+                var,
+                start,
+                stop,
+                step,
+                body,
+            } => {
+                for e in [Some(start), Some(stop), step.as_mut()]
+                    .into_iter()
+                    .flatten()
+                {
+                    let ty = self.check_exp(e)?;
+                    self.expect_same_type(&ty, &Type::Int, e)?;
+                }
+                if let Some(s) = step.as_ref().filter(|s| s.const_int() == Some(0)) {
+                    self.reporter
+                        .error_range(s, "`range` step must not be zero")?;
+                }
+
+                // 循环变量只在循环体中可见.
+                self.check_binding(var)?;
+                self.check_loop(|this| {
+                    this.env.init_scope();
+                    let res = this.bind(var, Type::Int).and_then(|_| this.check_exp(body));
+                    this.env.exit_scope();
+                    res.map(|_| ())
+                })?;
+            }
             Assign { name, binding } => {
+                self.check_binding(name)?;
+                let val_ty = self.check_exp(binding)?;
+                self.bind(name, val_ty)?;
+            }
+            AnnAssign { name, ty, binding } => {
+                self.check_binding(name)?;
+                let ty = self.resolve_type(ty)?;
+                let val_ty = self.check_exp_as(binding, &ty)?;
+                self.expect_same_type(&val_ty, &ty, binding)?;
+                self.bind(name, ty)?;
+            }
+            // 参数和返回值的类型标注改写为解析后的类型, 之后的pass直接使用.
+            Def {
+                name,
+                params,
+                ret: ret_ty,
+                body,
+            } => {
+                if self.env.level() != 0 {
+                    let msg = "Functions can only be defined at the top level";
+                    self.reporter.error_token(msg, name)?;
+                }
+                let Some(Type::Func { params: tys, ret }) = self.env.lookup(name.lexeme()).cloned()
+                else {
+                    unreachable!("Functions are declared before checking.")
+                };
+
+                for ((p, te), ty) in params.iter_mut().zip(&tys) {
+                    self.check_binding(p)?;
+                    *te = TypeExpr::Checked(ty.clone());
+                }
+                if let Some(r) = ret_ty {
+                    *r = TypeExpr::Checked(*ret.clone());
+                }
+
+                let outer = mem::replace(&mut self.env, self.globals.clone());
+                let outer_locals = mem::take(&mut self.locals);
+                let outer_captured = mem::take(&mut self.captured);
+                self.env.init_scope();
+                for ((p, _), ty) in params.iter().zip(tys) {
+                    self.locals.insert(p.lexeme().into(), ty.clone());
+                    self.env.insert(p.lexeme().into(), ty);
+                }
+                self.ret = Some(*ret.clone());
+                let res = self.check_exp(body);
+                self.ret = None;
+                self.env = outer;
+                self.locals = outer_locals;
+                self.captured = outer_captured;
+                res?;
+
+                if *ret != Type::Unit && !always_returns(body) {
+                    let msg = "Missing `return` at the end of the function";
+                    self.reporter.error_token(msg, name)?;
+                }
+            }
+            Return(value) => {
+                let Some(expected) = self.ret.clone() else {
+                    return Err(self
+                        .reporter
+                        .error_range(&range, "`return` outside of a function")
+                        .unwrap_err());
+                };
+                let ty = match value {
+                    Some(e) => self.check_exp_as(e, &expected)?,
+                    None => Type::Unit,
+                };
+                if !ty.is_compatible(&expected) {
+                    let msg = format!("{} != {}", ty, expected);
+                    self.reporter.error_range(&range, &msg)?;
+                }
+            }
+            // 目标必须已经定义, 且与右边一样都是整数.
+            AugAssign { name, binding, .. } => {
                 let val_ty = self.check_exp(binding)?;
-                self.env.insert(name.lexeme().into(), val_ty);
+                self.expect_same_type(&val_ty, &Type::Int, binding)?;
+                self.check_assign(name)?;
+                match self.env.lookup(name.lexeme()) {
+                    None => self.reporter.error_token("cannot find name", name)?,
+                    Some(ty) if !ty.is_compatible(&Type::Int) => {
+                        let msg = format!("{} != {}", ty, Type::Int);
+                        self.reporter.error_token(&msg, name)?
+                    }
+                    Some(_) => (),
+                }
+            }
+            // 右边的值都在赋值之前求出, 所以先检查完所有的值.
+            // `a, b = t`: 右边是一个元组时解构它.
+            MultiAssign { names, bindings } if bindings.len() == 1 && names.len() > 1 => {
+                let ty = self.check_exp(&mut bindings[0])?;
+                let elems = match ty {
+                    Type::Tuple(elems) if elems.len() == names.len() => elems,
+                    ty => {
+                        let msg = format!("Expected {} value(s), found {}", names.len(), ty);
+                        return self
+                            .reporter
+                            .error_range(&bindings[0], &msg)
+                            .map(|_| Type::Unit);
+                    }
+                };
+                for (name, ty) in names.iter().zip(elems) {
+                    self.check_binding(name)?;
+                    self.bind(name, ty)?;
+                }
+            }
+            MultiAssign { names, bindings } => {
+                let msg = format!(
+                    "Expected {} value(s), found {}",
+                    names.len(),
+                    bindings.len()
+                );
+                if let Some(extra) = bindings.get(names.len()) {
+                    self.reporter.error_range(extra, &msg)?;
+                } else if let Some(missing) = names.get(bindings.len()) {
+                    self.reporter.error_token(&msg, missing)?;
+                }
+                let mut val_tys = vec![];
+                for b in bindings.iter_mut() {
+                    val_tys.push(self.check_exp(b)?);
+                }
+                for (name, ty) in names.iter().zip(val_tys) {
+                    self.check_binding(name)?;
+                    self.bind(name, ty)?;
+                }
             }
         }
 
         Ok(Type::Unit)
     }
 
-    fn check_exp(&mut self, e: &Expr) -> Result<Type> {
-        use super::ast::ExprData;
-        match &e.data {
-            ExprData::Name(n) => {
-                if let Some(ty) = self.env.lookup(&n.lexeme()).cloned() {
-                    Ok(ty)
-                } else {
-                    Err(self
-                        .reporter
-                        .error_token("cannot find name", n)
-                        .unwrap_err())
+    /// 检查`e`, 期望它的类型是`expected`. lambda的参数类型只能由期望的类型确定.
+    fn check_exp_as(&mut self, e: &mut Expr, expected: &Type) -> Result<Type> {
+        match e.data {
+            ExprData::Lambda { .. } => self.check_lambda(e, Some(expected)),
+            ExprData::Tuple { .. } => self.check_tuple(e, Some(expected)),
+            ExprData::List { .. } => self.check_list(e, Some(expected)),
+            _ => self.check_exp(e),
+        }
+    }
+
+    /// 元素的类型由期望的类型或第一个元素确定, 之后的元素都按这个类型检查.
+    fn check_list(&mut self, e: &mut Expr, expected: Option<&Type>) -> Result<Type> {
+        let range = e.range;
+        let ExprData::List { elems, ty, .. } = &mut e.data else {
+            unreachable!("Expected a list.")
+        };
+        let mut elem_ty = match expected {
+            Some(Type::List(t)) => Some(*t.clone()),
+            _ => None,
+        };
+        for elem in elems.iter_mut() {
+            match &elem_ty {
+                Some(expected) => {
+                    let t = self.check_exp_as(elem, expected)?;
+                    self.expect_same_type(&t, expected, elem)?;
                 }
+                None => elem_ty = Some(self.check_exp(elem)?),
             }
-            ExprData::Int(i) => return Ok(Type::Int),
-            ExprData::Float(n) => return Ok(Type::Float),
-            ExprData::Call { name, args } => {
-                let fun_ty = self.check_exp(name)?;
-                let mut arg_tys = vec![];
-                for a in args {
-                    arg_tys.push(self.check_exp(a)?);
-                }
+        }
+        let Some(elem_ty) = elem_ty else {
+            let msg = "Cannot infer the type of an empty list";
+            return Err(self.reporter.error_range(&range, msg).unwrap_err());
+        };
+        *ty = Some(elem_ty.clone());
+        Ok(Type::List(elem_ty.into()))
+    }
+
+    /// 期望的类型是同样长度的元组时, 每个元素都按对应的类型检查, 所以元素可以是lambda.
+    fn check_tuple(&mut self, e: &mut Expr, expected: Option<&Type>) -> Result<Type> {
+        let ExprData::Tuple { elems } = &mut e.data else {
+            unreachable!("Expected a tuple.")
+        };
+        let expected = match expected {
+            Some(Type::Tuple(tys)) if tys.len() == elems.len() => Some(tys),
+            _ => None,
+        };
+        let mut tys = vec![];
+        for (i, elem) in elems.iter_mut().enumerate() {
+            let ty = match expected {
+                Some(expected) => self.check_exp_as(elem, &expected[i])?,
+                None => self.check_exp(elem)?,
+            };
+            tys.push(ty);
+        }
+        if tys.len() > MAX_TUPLE_LEN {
+            let msg = format!("Tuples can have at most {} elements", MAX_TUPLE_LEN);
+            self.reporter.error_range(e, &msg)?;
+        }
+        Ok(Type::Tuple(tys))
+    }
+
+    /// 没有期望的类型时, 只有无参数的lambda可以由函数体推导出类型.
+    /// 期望的返回值类型也用于检查函数体, 所以lambda可以嵌套. 函数体中不能`return`.
+    fn check_lambda(&mut self, e: &mut Expr, expected: Option<&Type>) -> Result<Type> {
+        let range = e.range;
+        let ExprData::Lambda {
+            params,
+            body,
+            ty,
+            captures,
+        } = &mut e.data
+        else {
+            unreachable!("Expected a lambda.")
+        };
+        let (param_tys, ret) = match expected {
+            Some(Type::Func { params: p, ret }) if p.len() == params.len() => {
+                (p.clone(), Some(*ret.clone()))
+            }
+            Some(Type::Func { params: p, .. }) => {
+                let msg = format!("Expected a function with {} parameter(s)", p.len());
+                return Err(self.reporter.error_range(&range, &msg).unwrap_err());
+            }
+            _ if params.is_empty() => (vec![], None),
+            _ => {
+                let msg = "Cannot infer the parameter types of `lambda`";
+                return Err(self.reporter.error_range(&range, msg).unwrap_err());
+            }
+        };
+        for (i, p) in params.iter().enumerate() {
+            self.check_binding(p)?;
+            if params[..i].iter().any(|q| q.lexeme() == p.lexeme()) {
+                let msg = format!("Duplicate parameter `{}`", p.lexeme());
+                self.reporter.error_token(&msg, p)?;
+            }
+        }
+
+        // lambda的函数体在闭包转换之后是单独的函数.
+        self.env.init_scope();
+        let outer_locals = mem::take(&mut self.locals);
+        let outer_captured = mem::take(&mut self.captured);
+        let outer_loop = self.loop_assigned.take();
+        for (p, ty) in params.iter().zip(&param_tys) {
+            self.locals.insert(p.lexeme().into(), ty.clone());
+            self.env.insert(p.lexeme().into(), ty.clone());
+        }
+        let outer_ret = self.ret.take();
+        let res = match &ret {
+            Some(r) => self.check_exp_as(body, r),
+            None => self.check_exp(body),
+        };
+        self.ret = outer_ret;
+        self.locals = outer_locals;
+        self.captured = outer_captured;
+        self.loop_assigned = outer_loop;
+        self.env.exit_scope();
+        let body_ty = res?;
 
+        let globals = &self.globals;
+        *captures = free_vars(params, body, &|n| globals.lookup(n).is_some())
+            .into_iter()
+            .map(|v| {
+                let ty = self.env.lookup(&v).cloned();
+                (v, ty.expect("Free variables are checked in the body."))
+            })
+            .collect();
+        self.captured
+            .extend(captures.iter().map(|(v, _)| v.clone()));
+        if let Some(r) = &ret {
+            self.expect_same_type(&body_ty, r, body)?;
+        }
+
+        let fun_ty = Type::Func {
+            params: param_tys,
+            ret: ret.unwrap_or(body_ty).into(),
+        };
+        *ty = Some(fun_ty.clone());
+        Ok(fun_ty)
+    }
+
+    fn check_exp(&mut self, e: &mut Expr) -> Result<Type> {
+        use super::ast::ExprData;
+        match &mut e.data {
+            ExprData::Name(n) => match self.env.lookup(n.lexeme()).cloned() {
+                Some(Type::Func { .. }) if BUILTINS.contains(&n.lexeme()) => Err(self
+                    .reporter
+                    .error_token("Built-in functions can only be called", n)
+                    .unwrap_err()),
+                Some(ty) => Ok(ty),
+                None => Err(self
+                    .reporter
+                    .error_token("cannot find name", n)
+                    .unwrap_err()),
+            },
+            ExprData::Int(_) => Ok(Type::Int),
+            // 后端不支持浮点数, 在这里报错, 以免后面的阶段崩溃.
+            ExprData::Float(_) => Err(self
+                .reporter
+                .error_range(e, "Floats are not supported")
+                .unwrap_err()),
+            ExprData::Str(_) => Ok(Type::Str),
+            ExprData::Call { name, args } => {
+                let fun_ty = match &name.data {
+                    ExprData::Name(n) => self.env.lookup(n.lexeme()).cloned().ok_or_else(|| {
+                        self.reporter
+                            .error_token("cannot find name", n)
+                            .unwrap_err()
+                    })?,
+                    _ => self.check_exp(name)?,
+                };
                 if let Type::Func { params, ret } = fun_ty {
-                    for (index, (left, right)) in arg_tys.iter().zip(params.iter()).enumerate() {
-                        let e = &args[index];
-                        self.expect_same_type(left, right, &args[index])?;
+                    if params.len() != args.len() {
+                        let msg = format!(
+                            "Expected {} argument(s), found {}",
+                            params.len(),
+                            args.len()
+                        );
+                        return Err(self.reporter.error_range(e, &msg).unwrap_err());
+                    }
+                    let mut arg_tys = vec![];
+                    for (a, param) in args.iter_mut().zip(params.iter()) {
+                        let ty = self.check_exp_as(a, param)?;
+                        self.expect_same_type(&ty, param, a)?;
+                        arg_tys.push(ty);
+                    }
+                    if let ExprData::Name(func) = &name.data {
+                        if func.lexeme() == "len"
+                            && !matches!(arg_tys[0], Type::Tuple(_) | Type::List(_))
+                        {
+                            let msg = format!("Expected a tuple or a list, found {}", arg_tys[0]);
+                            self.reporter.error_range(&args[0], &msg)?;
+                        }
+                    }
+                    // 运行时没有通用的`print`, 按参数类型选择具体的打印函数.
+                    if let ExprData::Name(func) = &mut name.data {
+                        if func.lexeme() == "print" {
+                            if let Type::Func { .. } = arg_tys[0] {
+                                let msg = "Cannot print a function";
+                                self.reporter.error_range(&args[0], msg)?;
+                            }
+                            if let Type::Tuple(_) = arg_tys[0] {
+                                let msg = "Cannot print a tuple";
+                                self.reporter.error_range(&args[0], msg)?;
+                            }
+                            if let Type::List(_) = arg_tys[0] {
+                                let msg = "Cannot print a list";
+                                self.reporter.error_range(&args[0], msg)?;
+                            }
+                            let runtime_func = match arg_tys[0] {
+                                Type::Bool => "print_bool",
+                                Type::Str => "print_str",
+                                _ => "print_int",
+                            };
+                            *func = Token::new(
+                                Kind::Name,
+                                runtime_func.into(),
+                                func.line(),
+                                func.pos(),
+                            );
+                        }
                     }
                     Ok(*ret)
                 } else {
@@ -188,14 +784,34 @@ impl<'r> TypeChecker<'r> {
                 }
             }
             ExprData::Prim { op, operands } if operands.len() == 2 => {
-                use super::token::{Kind, Token};
                 let mut operand_types = vec![];
-                for e in operands {
+                for e in operands.iter_mut() {
                     operand_types.push(self.check_exp(e)?);
                 }
 
                 match op.kind() {
-                    Kind::Plus | Kind::Minus => {
+                    // `[0] * n`改写为带`repeat`的列表.
+                    Kind::Star if matches!(operand_types[0], Type::List(_)) => {
+                        self.expect_same_type(&operand_types[1], &Type::Int, &operands[1])?;
+                        let count = operands.pop().unwrap();
+                        let list = operands.pop().unwrap();
+                        let ExprData::List {
+                            elems,
+                            repeat: None,
+                            ty,
+                        } = list.data
+                        else {
+                            let msg = "Only list literals can be repeated";
+                            return Err(self.reporter.error_range(&list, msg).unwrap_err());
+                        };
+                        e.data = ExprData::List {
+                            elems,
+                            repeat: Some(count.into()),
+                            ty,
+                        };
+                        Ok(operand_types.swap_remove(0))
+                    }
+                    Kind::Plus | Kind::Minus | Kind::Star | Kind::Slash | Kind::Percent => {
                         for (t, e) in operand_types.iter().zip(operands.iter()) {
                             self.expect_same_type(t, &Type::Int, e)?;
                         }
@@ -215,6 +831,19 @@ impl<'r> TypeChecker<'r> {
                         Ok(Type::Bool)
                     }
                     Kind::EqualEqual | Kind::BangEqual => {
+                        if let Type::Func { .. } = operand_types[0] {
+                            self.reporter
+                                .error_range(e, "Functions cannot be compared")?;
+                        }
+                        if let Type::Tuple(_) = operand_types[0] {
+                            self.reporter.error_range(e, "Tuples cannot be compared")?;
+                        }
+                        if let Type::List(_) = operand_types[0] {
+                            self.reporter.error_range(e, "Lists cannot be compared")?;
+                        }
+                        if let Type::Str = operand_types[0] {
+                            self.reporter.error_range(e, "Strings cannot be compared")?;
+                        }
                         self.expect_same_type(&operand_types[0], &operand_types[1], e)?;
                         Ok(Type::Bool)
                     }
@@ -222,8 +851,7 @@ impl<'r> TypeChecker<'r> {
                 }
             }
             ExprData::Prim { op, operands } if operands.len() == 1 => {
-                use super::token::{Kind, Token};
-                let operand_type = self.check_exp(operands.get(0).unwrap())?;
+                let operand_type = self.check_exp(&mut operands[0])?;
                 match op.kind() {
                     Kind::Minus => {
                         self.expect_same_type(&operand_type, &Type::Int, e)?;
@@ -236,7 +864,7 @@ impl<'r> TypeChecker<'r> {
                     _ => unreachable!(),
                 }
             }
-            ExprData::Bool(b) => Ok(Type::Bool),
+            ExprData::Bool(_) => Ok(Type::Bool),
             ExprData::Condition {
                 condition,
                 then,
@@ -256,13 +884,94 @@ impl<'r> TypeChecker<'r> {
                 }
             }
             ExprData::Block { body, result } => {
-                if let Some(r) = result {
-                    self.check_exp(r)
-                } else {
+                self.env.init_scope();
+                let res = self.check_stmts(body).and_then(|_| match result {
+                    Some(r) => self.check_exp(r),
+                    None => Ok(Type::Unit),
+                });
+                self.env.exit_scope();
+                res
+            }
+            ExprData::Lambda { .. } => self.check_lambda(e, None),
+            ExprData::Tuple { .. } => self.check_tuple(e, None),
+            ExprData::List { .. } => self.check_list(e, None),
+            // 元组的下标必须是常量, 才能确定元素的类型. 列表的下标可以是任意整数.
+            ExprData::Subscript { value, index } => {
+                let tys = match self.check_exp(value)? {
+                    Type::Tuple(tys) => tys,
+                    Type::List(elem) => {
+                        let index_ty = self.check_exp(index)?;
+                        self.expect_same_type(&index_ty, &Type::Int, index)?;
+                        let ExprData::Subscript { value, index } =
+                            mem::replace(&mut e.data, ExprData::Bool(false))
+                        else {
+                            unreachable!()
+                        };
+                        e.data = ExprData::ListRef { list: value, index };
+                        return Ok(*elem);
+                    }
+                    ty => {
+                        let msg = format!("Expected a tuple or a list, found {}", ty);
+                        return Err(self.reporter.error_range(value.as_ref(), &msg).unwrap_err());
+                    }
+                };
+                let Some(i) = index.const_int() else {
+                    let msg = "Tuple index must be an integer literal";
+                    return Err(self.reporter.error_range(index.as_ref(), msg).unwrap_err());
+                };
+                match usize::try_from(i).ok().and_then(|i| tys.get(i)) {
+                    Some(ty) => Ok(ty.clone()),
+                    None => {
+                        let msg = format!("Index {} out of range for {}", i, Type::Tuple(tys));
+                        Err(self.reporter.error_range(index.as_ref(), &msg).unwrap_err())
+                    }
+                }
+            }
+            ExprData::ListSet { list, index, value } => match self.check_exp(list)? {
+                Type::List(elem) => {
+                    let index_ty = self.check_exp(index)?;
+                    self.expect_same_type(&index_ty, &Type::Int, index)?;
+                    let value_ty = self.check_exp_as(value, &elem)?;
+                    self.expect_same_type(&value_ty, &elem, value)?;
                     Ok(Type::Unit)
                 }
+                Type::Tuple(_) => Err(self
+                    .reporter
+                    .error_range(e, "Tuples are immutable")
+                    .unwrap_err()),
+                ty => {
+                    let msg = format!("Expected a list, found {}", ty);
+                    Err(self.reporter.error_range(list.as_ref(), &msg).unwrap_err())
+                }
+            },
+            ExprData::ListRef { .. } => {
+                unreachable!("List subscripts are created by the type checker.")
+            }
+            ExprData::Closure { .. }
+            | ExprData::Capture { .. }
+            | ExprData::Apply { .. }
+            | ExprData::GlobalValue(_)
+            | ExprData::Collect(_)
+            | ExprData::AllocateList { .. }
+            | ExprData::ListInit { .. } => {
+                unreachable!("Closures and allocations are created after type checking.")
             }
             _ => unimplemented!(),
         }
     }
 }
+
+/// 语句执行完之前是否一定会`return`. 循环可能一次也不执行, 所以不算.
+fn always_returns(e: &Expr) -> bool {
+    match &e.data {
+        ExprData::Block { body, result } => {
+            body.iter().any(|s| match &s.stmt {
+                StmtData::Return(_) => true,
+                StmtData::Expr(e) => always_returns(e),
+                StmtData::If { then, else_, .. } => always_returns(then) && always_returns(else_),
+                _ => false,
+            }) || result.as_deref().is_some_and(always_returns)
+        }
+        _ => false,
+    }
+}
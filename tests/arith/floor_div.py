@@ -0,0 +1,14 @@
+a = input_int()
+b = input_int()
+print(a // b)
+print(a % b)
+print(-a // b)
+print(-a % b)
+print(a // -b)
+print(a % -b)
+print(-a // -b)
+print(-a % -b)
+print(a // a)
+print(a % a)
+print(-17 // 4)
+print(-17 % 4)
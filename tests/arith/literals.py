@@ -0,0 +1,10 @@
+print(0xff)
+print(0XFF + 0x_10)
+print(0b1010)
+print(0o17)
+print(1_000_000)
+print(0x7fff_ffff_ffff_ffff)
+print(-9_223_372_036_854_775_807 - 1)
+print(-9223372036854775808)
+print(-0x8000_0000_0000_0000 == -9_223_372_036_854_775_807 - 1)
+print(0)
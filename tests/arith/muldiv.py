@@ -0,0 +1,12 @@
+x = input_int()
+y = input_int()
+print(x * y)
+print(x / y)
+print(x // 3)
+print(x % y)
+print(-x % 4)
+print(-x / 4)
+print(7 * 6 - 100 // 7 % 5)
+z = x * 2 * y
+print(z % 7 * 3)
+print(100 / x if x != 0 else 0)
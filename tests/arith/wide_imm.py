@@ -0,0 +1,21 @@
+# 超过32位的立即数只能由movabsq写入寄存器.
+def seven(a: int, b: int, c: int, d: int, e: int, f: int, g: int) -> int {
+    return g - 5_000_000_000
+}
+
+x = input_int()
+print(x + 5_000_000_000)
+print(x * 0x1_0000_0000)
+print(x - 0x7fff_ffff_ffff_ffff)
+print(x < 4_294_967_296)
+print(x == 0x1_0000_0001)
+print(4_294_967_296 < 4_294_967_297)
+print(seven(1, 2, 3, 4, 5, 6, 0x2_0000_0000))
+t = (x, 0x1234_5678_9abc)
+print(t[1])
+a = [0x1_0000_0000] * 3
+a[1] = -0x1_0000_0000
+print(a[0] + a[1] + a[2])
+y = 0
+y += 0x8000_0000
+print(y)
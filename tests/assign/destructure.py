@@ -0,0 +1,18 @@
+def divmod(a: int, b: int) -> tuple[int, int] {
+    return (a // b, a % b)
+}
+
+a, b = (1, 2)
+print(a)
+print(b)
+t = (input_int(), true, 3)
+x, y, z = t
+print(x)
+print(y)
+print(z)
+q, r = divmod(x, 4)
+print(q)
+print(r)
+a, b = b, a
+print(a)
+print(b)
@@ -0,0 +1,9 @@
+a = input_int()
+b = input_int()
+a, b = b, a
+print(a)
+print(b)
+x, y, z = a + b, a - b, a < b
+print(x)
+print(y)
+print(z)
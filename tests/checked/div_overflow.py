@@ -0,0 +1,4 @@
+a = input_int()
+b = input_int()
+print(b // a)
+print(a // b)
@@ -0,0 +1,4 @@
+a = input_int()
+b = input_int()
+print(a // b)
+print(a % (b - 2))
@@ -0,0 +1,4 @@
+x = input_int()
+y = -x
+print(y)
+print(-(y - 1))
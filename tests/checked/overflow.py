@@ -0,0 +1,5 @@
+x = input_int()
+y = x + 1
+print(y)
+z = y * y
+print(z)
@@ -0,0 +1,4 @@
+a = input_int()
+b = input_int()
+print(a % 7)
+print(a % b)
@@ -0,0 +1 @@
+p = print_int
@@ -0,0 +1,9 @@
+def add(a: int, b: int) -> int {
+    return a + b
+}
+
+def apply(f: Callable[[int], int], x: int) -> int {
+    return f(x)
+}
+
+print(apply(add, 1))
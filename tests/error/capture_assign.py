@@ -0,0 +1,4 @@
+x = 1
+f: Callable[[int], int] = lambda y: x + y
+x = 10
+print(f(1))
@@ -0,0 +1,5 @@
+fs = [lambda: 0] * 3
+for i in range(3) {
+    fs[i] = lambda: i
+}
+print(fs[0]())
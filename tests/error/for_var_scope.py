@@ -0,0 +1,4 @@
+for i in range(0, 10) {
+    print(i)
+}
+print(i)
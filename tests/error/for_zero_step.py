@@ -0,0 +1,3 @@
+for i in range(0, 10, 0) {
+    print(i)
+}
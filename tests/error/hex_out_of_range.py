@@ -0,0 +1 @@
+print(0x1_0000_0000_0000_0000)
@@ -0,0 +1,2 @@
+x = 1
+print(x -9223372036854775808)
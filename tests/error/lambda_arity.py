@@ -0,0 +1,5 @@
+def apply(f: Callable[[int], int], x: int) -> int {
+    return f(x)
+}
+
+print(apply(lambda a, b: a + b, 1))
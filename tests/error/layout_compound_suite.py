@@ -0,0 +1,3 @@
+x = 1
+if x > 0: while x > 0: x = x - 1
+print(x)
@@ -0,0 +1,3 @@
+if true:
+    print(1)
+  print(2)
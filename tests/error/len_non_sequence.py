@@ -0,0 +1 @@
+print(len(3))
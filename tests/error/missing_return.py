@@ -0,0 +1,7 @@
+def sign(x: int) -> int {
+    if x < 0 {
+        return -1
+    } else {}
+}
+
+print(sign(3))
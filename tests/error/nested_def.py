@@ -0,0 +1,6 @@
+def outer() -> int {
+    def inner() -> int {
+        return 1
+    }
+    return 2
+}
@@ -0,0 +1,3 @@
+x = "变量"
+print("变量" +)
+print(x)
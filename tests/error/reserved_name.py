@@ -0,0 +1,3 @@
+def print_int(x: int) {
+    return
+}
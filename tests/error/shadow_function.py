@@ -0,0 +1,5 @@
+def f(x: int) -> int {
+    return x
+}
+
+f = lambda: 1
@@ -0,0 +1,2 @@
+x = 1
+print(x +
\ No newline at end of file
@@ -0,0 +1,9 @@
+x = 1 +
+y = (2, 3))
+def f(a: int) -> int {
+    b = a * * 2
+    return a
+}
+print(x ])
+z = 4
+}
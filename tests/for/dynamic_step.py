@@ -0,0 +1,9 @@
+a = input_int()
+b = input_int()
+step = input_int()
+count = 0
+for i in range(a, b, step) {
+    print(i)
+    count += 1
+}
+print(count)
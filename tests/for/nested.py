@@ -0,0 +1,9 @@
+n = input_int()
+for i in range(1, n + 1) {
+    line = 0
+    for j in range(i) {
+        line = line * 10 + j
+        i = 100
+    }
+    print(line)
+}
@@ -0,0 +1,11 @@
+total = 0
+for i in range(1, 11) {
+    total += i
+}
+print(total)
+for i in range(3) {
+    print(i)
+}
+for j in range(10, 0, -3) {
+    print(j)
+}
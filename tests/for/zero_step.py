@@ -0,0 +1,5 @@
+s = input_int()
+for i in range(0, 5, s) {
+    print(i)
+}
+print(-1)
@@ -0,0 +1,27 @@
+def first_divisor(n: int) -> int {
+    for d in range(2, n) {
+        if n % d == 0 {
+            return d
+        } else {}
+    }
+    return n
+}
+
+def report(n: int) {
+    if n < 2 {
+        print(false)
+        return
+    } else {}
+    print(first_divisor(n) == n)
+}
+
+def both(a: bool, b: bool) -> bool {
+    return a and b
+}
+
+i = 0
+while i < 5 {
+    report(input_int())
+    i += 1
+}
+print(both(true, first_divisor(91) == 7))
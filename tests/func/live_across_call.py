@@ -0,0 +1,13 @@
+def id(x: int) -> int {
+    return x
+}
+
+a = input_int()
+b = a + 1
+c = a + 2
+d = a + 3
+e = a + 4
+f = a + 5
+g = id(a) + id(b) + id(c)
+print(a + b + c + d + e + f + g)
+print(id(d) * id(e) - f)
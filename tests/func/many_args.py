@@ -0,0 +1,11 @@
+def sum8(a: int, b: int, c: int, d: int, e: int, f: int, g: int, h: int) -> int {
+    return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f + 7 * g + 8 * h
+}
+
+def sum7(a: int, b: int, c: int, d: int, e: int, f: int, g: int) -> int {
+    return sum8(g, f, e, d, c, b, a, 0) - g
+}
+
+x = input_int()
+print(sum8(x, 2, 3, 4, 5, 6, 7, 8))
+print(sum7(1, 2, 3, 4, 5, 6, x))
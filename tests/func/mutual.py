@@ -0,0 +1,16 @@
+print(is_even(input_int()))
+print(is_odd(7))
+
+def is_even(n: int) -> bool {
+    if n == 0 {
+        return true
+    } else {}
+    return is_odd(n - 1)
+}
+
+def is_odd(n: int) -> bool {
+    if n == 0 {
+        return false
+    } else {}
+    return is_even(n - 1)
+}
@@ -0,0 +1,14 @@
+def fact(n: int) -> int {
+    if n <= 1 {
+        return 1
+    } else {}
+    return n * fact(n - 1)
+}
+
+def fib(n: int) -> int {
+    return n if n < 2 else fib(n - 1) + fib(n - 2)
+}
+
+n = input_int()
+print(fact(n))
+print(fib(n))
@@ -0,0 +1,153 @@
+//! 端到端测试: 编译tests/下的每个`.py`程序和`.clike`程序, 与运行时链接后运行,
+//! 比较stdout和退出码.
+//!
+//! - `foo.in`: 可选, 作为程序的stdin.
+//! - `foo.flags`: 可选, 编译和解释时额外传给编译器的选项, 以空白分隔.
+//! - `foo.golden`: 期望的stdout, 最后一行是`--- exit <code>`;
+//!   编译失败时是`--- compile error`加上编译器的stderr.
+//!   没有这个文件时, 以参照解释器(`--interp`)的输出为准.
+//!
+//! 运行`cargo test --test golden -- --bless`按当前的结果重新生成`.golden`文件,
+//! 其余参数用于按路径筛选要运行的测试.
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{exit, Command, Output, Stdio},
+};
+
+const COMPILER: &str = env!("CARGO_BIN_EXE_eoc-rs");
+
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    entries.sort();
+    for p in entries {
+        if p.is_dir() {
+            // 旧的构建产物
+            if p.file_name().is_some_and(|n| n != "target") {
+                collect(&p, files);
+            }
+        } else if p.extension().is_some_and(|e| e == "py" || e == "clike") {
+            files.push(p);
+        }
+    }
+}
+
+fn stdin_for(test: &Path) -> Stdio {
+    match File::open(test.with_extension("in")) {
+        Ok(f) => Stdio::from(f),
+        Err(_) => Stdio::null(),
+    }
+}
+
+fn flags_for(test: &Path) -> Vec<String> {
+    fs::read_to_string(test.with_extension("flags"))
+        .map(|s| s.split_whitespace().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn exit_line(out: &Output) -> String {
+    let code = match out.status.code() {
+        Some(c) => c.to_string(),
+        None => "signal".to_string(),
+    };
+    let mut stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+    // 运行时打印整数和布尔值之后不换行, 补上换行使`---`单独成行.
+    if !stdout.is_empty() && !stdout.ends_with('\n') {
+        stdout.push('\n');
+    }
+    format!("{}--- exit {}\n", stdout, code)
+}
+
+/// 编译并运行程序, 得到与`.golden`相同格式的结果.
+fn compile_and_run(test: &Path, build_dir: &Path) -> String {
+    let exe = build_dir.join(test.file_stem().unwrap());
+    let compile = Command::new(COMPILER)
+        .arg("--link")
+        .args(flags_for(test))
+        .arg("-o")
+        .arg(&exe)
+        .arg(test)
+        .output()
+        .unwrap();
+    if !compile.status.success() {
+        return format!(
+            "--- compile error\n{}",
+            String::from_utf8_lossy(&compile.stderr)
+        );
+    }
+
+    let out = Command::new(&exe)
+        .stdin(stdin_for(test))
+        .output()
+        .unwrap();
+    exit_line(&out)
+}
+
+fn interpret(test: &Path) -> String {
+    let out = Command::new(COMPILER)
+        .arg("--interp")
+        .args(flags_for(test))
+        .arg(test)
+        .stdin(stdin_for(test))
+        .output()
+        .unwrap();
+    exit_line(&out)
+}
+
+fn main() {
+    let mut bless = false;
+    let mut filters = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            // 忽略cargo传给测试程序的其他选项.
+            a if a.starts_with('-') => (),
+            a => filters.push(a.to_string()),
+        }
+    }
+
+    // 用相对路径, 使编译错误信息中的文件名与机器无关.
+    std::env::set_current_dir(env!("CARGO_MANIFEST_DIR")).unwrap();
+    let mut tests = vec![];
+    collect(Path::new("tests"), &mut tests);
+    tests.retain(|t| filters.is_empty() || filters.iter().any(|f| t.to_string_lossy().contains(f)));
+
+    let build_dir = std::env::temp_dir().join(format!("eoc-rs-golden-{}", std::process::id()));
+    fs::create_dir_all(&build_dir).unwrap();
+
+    let mut failed = vec![];
+    for test in &tests {
+        let actual = compile_and_run(test, &build_dir);
+        let golden = test.with_extension("golden");
+        if bless {
+            fs::write(&golden, &actual).unwrap();
+            println!("blessed {}", golden.display());
+            continue;
+        }
+
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|_| interpret(test));
+        if actual == expected {
+            println!("test {} ... ok", test.display());
+        } else {
+            println!("test {} ... FAILED", test.display());
+            println!("--- expected:\n{}--- actual:\n{}", expected, actual);
+            failed.push(test);
+        }
+    }
+    let _ = fs::remove_dir_all(&build_dir);
+
+    if !bless {
+        println!(
+            "\ngolden: {} passed; {} failed",
+            tests.len() - failed.len(),
+            failed.len()
+        );
+    }
+    if !failed.is_empty() {
+        exit(1);
+    }
+}
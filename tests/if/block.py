@@ -0,0 +1,22 @@
+x = input_int()
+a = x > 1 and x < 10
+b = x < 0 or x == 5
+print(a)
+print(b)
+print(not a or b)
+c = { t = x + 1
+  t + t }
+print(c)
+if x == 5 or x == 6 {
+  y = x + 100
+  print(y)
+} else {
+  print(0)
+}
+z = (input_int() + 1) if x > 3 else 7
+print(z)
+if (x == 5 if a else false) { print(1) } else { print(2) }
+k = true
+if not k { print(3) } else { print(4) }
+if k { input_int() } else { 0 }
+print(input_int())
@@ -0,0 +1,9 @@
+x = input_int()
+print(1 < 2)
+print(not (3 == 4))
+print(x >= 5)
+print(x != 7)
+y = x + 1
+b = y <= x
+print(not b)
+print(x > y)
@@ -0,0 +1,10 @@
+x = input_int()
+if x < 0 {
+    print(-1)
+} elif x == 0 {
+    print(0)
+} elif x < 10 {
+    print(1)
+} else {
+    print(2)
+}
@@ -0,0 +1,9 @@
+def make_adder(n: int) -> Callable[[int], int] {
+    return lambda x: x + n
+}
+
+n = input_int()
+add = make_adder(n)
+n = 100
+print(add(1))
+print(make_adder(n)(1))
@@ -0,0 +1,13 @@
+def count(f: Callable[[int], bool], n: int) -> int {
+    c = 0
+    for i in range(n) {
+        if f(i) {
+            c += 1
+        } else {}
+    }
+    return c
+}
+
+m = input_int()
+print(count(lambda i: i % m == 0, 20))
+print(count(lambda i: i > m and i < 2 * m, 20))
@@ -0,0 +1,19 @@
+def twice(f: Callable[[int], int], x: int) -> int {
+    return f(f(x))
+}
+
+def compose(f: Callable[[int], int], g: Callable[[int], int]) -> Callable[[int], int] {
+    return lambda x: f(g(x))
+}
+
+def square(x: int) -> int {
+    return x * x
+}
+
+k = input_int()
+print(twice(square, k))
+print(twice(lambda x: x - k, 10))
+h = compose(square, lambda x: x + 1)
+print(h(k))
+f: Callable[[int], int] = square
+print(twice(f, 2))
@@ -0,0 +1,7 @@
+a = input_int()
+curry: Callable[[int], Callable[[int, bool], int]] = lambda b: lambda c, neg: -(a + b + c) if neg else a + b + c
+g = curry(10)
+print(g(100, false))
+print(g(200, true))
+thunk = lambda: a * 2
+print(thunk())
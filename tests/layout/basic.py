@@ -0,0 +1,33 @@
+def collatz(n: int) -> int:
+    steps = 0
+    while n != 1:
+        if n % 2 == 0:
+            n = n / 2
+        else:
+            n = 3 * n + 1
+        steps += 1
+    return steps
+
+def describe(n: int):
+    if n < 0:
+        print(-1)
+        return
+    # 没有else分支
+    if n == 0:
+        print(0)
+
+	# 空行和注释不影响缩进
+    print(n)
+
+x = input_int()
+describe(x)
+describe(-x)
+describe(0)
+print(collatz(x))
+total = 0
+for i in range(1, 4):
+    for j in range(i):
+        total += i * j
+print(total)
+f: Callable[[int], int] = lambda a: a + 1
+print(f(collatz(27)))
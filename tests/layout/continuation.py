@@ -0,0 +1,14 @@
+def add(a: int,
+        b: int) -> int:
+    return a + \
+        b
+
+xs = [
+  1,
+      2,
+ 3]
+if add(xs[0],
+       xs[1]) == 3:
+    print(add(xs[1], xs[2]))
+else:
+    print(0)
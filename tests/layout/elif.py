@@ -0,0 +1,28 @@
+def sign(n: int) -> int:
+    if n < 0:
+        return -1
+    elif n == 0:
+        return 0
+    else:
+        return 1
+
+def grade(n: int):
+    if n >= 90: print(4)
+    elif n >= 80: print(3)
+    elif n >= 70:
+        print(2)
+    else: print(0)
+
+def count_down(n: int) -> int: return n if n <= 0 else count_down(n - 1)
+
+x = input_int()
+print(sign(x))
+print(sign(-x))
+print(sign(0))
+scores = [95, 85, 75, 10]
+for i in range(len(scores)): grade(scores[i])
+c = x
+while c > 0: c = c - 1
+print(c)
+if x > 100: print(100)
+print(count_down(x))
@@ -0,0 +1,22 @@
+a = [1, 2, 3]
+print(len(a))
+a[1] = 20
+print(a[0] + a[1] + a[2])
+n = input_int()
+b = [0] * n
+i = 0
+while i < n {
+    b[i] = i * i
+    i = i + 1
+}
+s = 0
+for j in range(n) {
+    s = s + b[j]
+}
+print(s)
+c = [7, 8] * 3
+print(len(c))
+print(c[5])
+e: list[bool] = []
+print(len(e))
+print(len([1] * -3))
@@ -0,0 +1,5 @@
+a = [1, 2, 3]
+i = input_int()
+print(a[i - 1])
+a[i] = 4
+print(a[i])
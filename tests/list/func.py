@@ -0,0 +1,24 @@
+def fill(a: list[int], v: int) {
+    for i in range(len(a)) {
+        a[i] = v
+    }
+}
+
+def total(a: list[int]) -> int {
+    s = 0
+    for i in range(len(a)) {
+        s = s + a[i]
+    }
+    return s
+}
+
+n = input_int()
+a = [1, 2, 3]
+b = a
+fill(b, n)
+print(total(a))
+fs: list[Callable[[int], int]] = [lambda x: x + 1, lambda x: x * n]
+print(fs[0](10) + fs[1](10))
+pairs = [(1, true), (2, false)]
+print(pairs[1][0])
+print(pairs[0][1])
@@ -0,0 +1,25 @@
+def row(n: int, k: int) -> list[int] {
+    r = [k] * n
+    for j in range(n) {
+        r[j] = r[j] + j
+    }
+    return r
+}
+
+n = input_int()
+grid: list[list[int]] = [[0]] * 10
+keep = [(1, 2), (3, 4)]
+total = 0
+for i in range(n) {
+    r = row(20, i)
+    grid[i % 10] = r
+    keep[i % 2] = (keep[i % 2][1], r[19])
+    total = (total + r[i % 20]) % 100000
+}
+print(total)
+s = 0
+for i in range(10) {
+    s = s + grid[i][0] + len(grid[i])
+}
+print(s)
+print(keep[0][0] + keep[0][1] + keep[1][0] + keep[1][1])
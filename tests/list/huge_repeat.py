@@ -0,0 +1,3 @@
+s = input_int()
+x = [7] * s
+print(len(x))
@@ -0,0 +1,3 @@
+a = [0] * 4
+print(len(a))
+print(a[-1])
@@ -157,7 +157,18 @@ void validate_vector(int64_t** scan_addr) {
   int64_t* scan_ptr = *scan_addr;
   int64_t tag = *scan_ptr;
   if (is_vecof(tag)) {
-    exit(EXIT_FAILURE);
+    int len = get_vecof_length(tag);
+    int eltsArePointers = get_vecof_ptr_bitfield(tag);
+    int64_t* data = scan_ptr + 1;
+    *scan_addr = scan_ptr + len + 1;
+    for (int i = 0; eltsArePointers && i != len; i++){
+      int64_t* ptr = (int64_t*) data[i];
+      if (is_ptr(ptr)) {
+        int64_t* real_ptr = to_ptr(ptr);
+        assert(real_ptr < fromspace_end);
+        assert(real_ptr >= fromspace_begin);
+      }
+    }
   } else {
     unsigned char len = get_vector_length(tag);
     int64_t isPtrBits = get_vec_ptr_bitfield(tag);
@@ -617,6 +628,11 @@ void print_bool(int64_t x) {
   }
 }
 
+// print a NUL-terminated string from the read-only data section, followed by a newline
+void print_str(const char* s) {
+  printf("%s\n", s);
+}
+
 void print_void() {
   printf("#<void>");
 }
@@ -825,3 +841,22 @@ int64_t proxy_vec_set(int64_t* vec, int i, int64_t arg) {
   }
     
 }
+
+// report a failed arithmetic check (0: overflow, 1: division by zero,
+// 2: zero step in range) at the given source position and exit
+void arith_error(int64_t kind, int64_t line, int64_t col) {
+  const char* msg = kind == 0   ? "integer overflow"
+                    : kind == 2 ? "`range` step must not be zero"
+                                : "division by zero";
+  fflush(stdout);
+  fprintf(stderr, "runtime error: %s at %" PRId64 ":%" PRId64 "\n", msg, line, col);
+  exit(1);
+}
+
+// report a list index out of range at the given source position and exit
+void index_error(int64_t index, int64_t len, int64_t line, int64_t col) {
+  fflush(stdout);
+  fprintf(stderr, "runtime error: index %" PRId64 " out of range for list of length %" PRId64
+          " at %" PRId64 ":%" PRId64 "\n", index, len, line, col);
+  exit(1);
+}
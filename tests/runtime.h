@@ -38,6 +38,18 @@ void print_int(int64_t x);
 // Print a boolean to stdout.
 void print_bool(int64_t x);
 
+// Print a NUL-terminated string to stdout.
+void print_str(const char* s);
+
+// Report a failed arithmetic check (0: overflow, 1: division by zero,
+// 2: zero `range` step) at the given source line and column, then exit
+// with status 1.
+void arith_error(int64_t kind, int64_t line, int64_t col);
+
+// Report a list index out of range for a list of length `len`
+// at the given source line and column, then exit with status 1.
+void index_error(int64_t index, int64_t len, int64_t line, int64_t col);
+
 void print_heap(int64_t** rootstack_ptr);
 void print_vector(int64_t* vector_ptr);
 void print_vecbegin();
@@ -0,0 +1,16 @@
+print("hello, world")
+print('single "quoted"')
+print("tab:\tend")
+print("two\nlines")
+print("back\\slash and \"quotes\" and \'")
+print("")
+print("中文")
+
+greeting: str = "hi"
+if input_int() > 0 {
+    greeting = "positive"
+} else {
+    greeting = "not positive"
+}
+print(greeting)
+print("yes" if 1 < 2 else "no")
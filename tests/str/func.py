@@ -0,0 +1,21 @@
+def sign(n: int) -> str {
+    if n < 0 {
+        return "negative"
+    } else {}
+    if n == 0 {
+        return "zero"
+    } else {}
+    return "positive"
+}
+
+def show(label: str, f: Callable[[int], str], n: int) {
+    print(label)
+    print(f(n))
+}
+
+i = 0
+while i < 3 {
+    show("value:", sign, input_int())
+    i += 1
+}
+show("lambda:", lambda n: "small" if n < 10 else "big", 42)
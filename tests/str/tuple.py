@@ -0,0 +1,17 @@
+# 字符串不在堆上, 垃圾回收时不会被移动.
+pairs = [("zero", 0)] * 1
+i = 0
+while i < 2000 {
+    p = ("n", i)
+    if i % 500 == 0 {
+        pairs[0] = ("multiple of 500", i)
+    } else {}
+    i += 1
+}
+print(pairs[0][0])
+print(pairs[0][1])
+names = ["a", "b\tc"]
+names[0] = "d"
+print(names[0])
+print(names[1])
+print(len(names))
@@ -0,0 +1,9 @@
+# 整行注释
+x = 1  # 行尾注释
+
+# 注释中的字符不会被扫描: ` $ ? {
+if x == 1 {  # 条件后的注释
+    print(x)
+} else {}
+#
+print(x + 1)#紧跟着的注释
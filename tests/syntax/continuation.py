@@ -0,0 +1,22 @@
+def sum4(a: int, b: int,
+         c: int, d: int) -> int {
+    return a + b \
+        + c + d
+}
+
+total = sum4(
+    1,  # 括号中的注释
+    2,
+
+    3,
+    4)
+print(total)
+xs = [
+    total,
+    (total
+     + 1),
+]
+print(xs[0] + xs[1])
+y = 2 + \
+    3
+print(y)
@@ -0,0 +1,19 @@
+def sum_to(n: int, acc: int) -> int {
+    if n == 0 {
+        return acc
+    } else {
+        return sum_to(n - 1, acc + n)
+    }
+}
+
+def is_even(n: int) -> bool {
+    return true if n == 0 else is_odd(n - 1)
+}
+
+def is_odd(n: int) -> bool {
+    return false if n == 0 else is_even(n - 1)
+}
+
+n = input_int()
+print(sum_to(n, 0))
+print(is_even(n))
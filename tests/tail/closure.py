@@ -0,0 +1,15 @@
+def count(n: int, acc: int) -> int {
+    if n == 0 {
+        return acc
+    } else {}
+    step: Callable[[int, int], int] = count
+    return step(n - 1, acc + 1)
+}
+
+def apply7(f: Callable[[int, int, int, int, int, int, int], int], n: int) -> int {
+    return f(n, 1, 2, 3, 4, 5, 6)
+}
+
+n = input_int()
+print(count(n, 0))
+print(apply7(lambda n, a, b, c, d, e, g: n + a + b + c + d + e + g, n))
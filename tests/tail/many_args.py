@@ -0,0 +1,8 @@
+def rotate(n: int, a: int, b: int, c: int, d: int, e: int, f: int, g: int) -> int {
+    if n == 0 {
+        return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f + 7 * g
+    } else {}
+    return rotate(n - 1, g, a, b, c, d, e, f + 1)
+}
+
+print(rotate(input_int(), 1, 2, 3, 4, 5, 6, 7))
@@ -0,0 +1,25 @@
+def read() -> int {
+    return input_int()
+}
+
+def sum8(a: int, b: int, c: int, d: int, e: int, f: int, g: int, h: int) -> int {
+    return a + b + c + d + e + f + g + h
+}
+
+def shift(n: int, a: int, b: int, c: int, d: int, e: int, f: int, g: int) -> int {
+    if n == 0 {
+        return sum8(a, b, c, d, e, f, g, n)
+    } else {}
+    return shift(n - 1, g, a, b, c, d, e, f + n)
+}
+
+def countdown(n: int) {
+    if n > 0 {
+        print(n)
+        return countdown(n - 1)
+    } else {}
+}
+
+x = read()
+print(shift(x, 1, 2, 3, 4, 5, 6, 7))
+countdown(read())
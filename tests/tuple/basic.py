@@ -0,0 +1,11 @@
+t = (1, true, input_int())
+print(t[0] + t[2])
+print(t[1])
+print(len(t))
+u: tuple[tuple[int, bool, int], tuple[int]] = (t, (t[2] * 2,))
+print(u[0][2] + u[1][0])
+if u[0][1] {
+    print(len(u))
+} else {}
+single = (u[1],)
+print(single[0][0])
@@ -0,0 +1,12 @@
+def make(t: tuple[int, int]) -> Callable[[int], int] {
+    return lambda i: t[0] * i + t[1]
+}
+
+f = make((input_int(), 7))
+print(f(1))
+print(f(10))
+fs: tuple[Callable[[int], int], Callable[[int], int], Callable[[int], int]] = (lambda x: x + 1, lambda x: x * 2, f)
+print(fs[1](fs[0](3)))
+print(fs[2](0))
+nested = (fs, 1)
+print(nested[0][0](nested[1]))
@@ -0,0 +1,19 @@
+def swap(p: tuple[int, int]) -> tuple[int, int] {
+    return (p[1], p[0])
+}
+
+def sum_pair(p: tuple[int, int]) -> int {
+    return p[0] + p[1]
+}
+
+def pick(a: tuple[int, int], b: tuple[int, int], c: tuple[int, int], d: tuple[int, int], e: tuple[int, int], f: tuple[int, int], g: tuple[int, int]) -> tuple[int, int] {
+    return (a[0] + g[1], sum_pair(swap(d)))
+}
+
+a = input_int()
+p = swap((a, 2 * a))
+print(p[0])
+print(sum_pair(swap(p)))
+q = pick(p, p, p, (3, 4), p, p, (5, 6))
+print(q[0])
+print(q[1])
@@ -0,0 +1,21 @@
+def step(p: tuple[int, int], k: int) -> tuple[int, int] {
+    return (p[1], (p[0] + k) % 1000)
+}
+
+n = input_int()
+first = (1, (2, 3), true)
+scale = (3,)
+mul: Callable[[int], int] = lambda x: x * scale[0]
+pair = (0, 1)
+sum = 0
+for i in range(n) {
+    garbage = (i, i + 1, (i + 2, i))
+    pair = step(pair, garbage[2][0])
+    sum = (sum + mul(pair[0])) % 100000
+}
+print(sum)
+print(first[0] + first[1][0] + first[1][1])
+print(first[2])
+print(pair[0])
+print(pair[1])
+print(mul(scale[0]))
@@ -0,0 +1,7 @@
+n = input_int()
+steps = 0
+while not (n == 1) {
+    n = n // 2 if n % 2 == 0 else 3 * n + 1
+    steps = steps + 1
+}
+print(steps)
@@ -0,0 +1,12 @@
+i = 0
+total = 0
+while i < 4 {
+    j = 0
+    while j < i {
+        total = total + i * j
+        j = j + 1
+    }
+    if total > 5 { print(total) } else { print(0) }
+    i = i + 1
+}
+print(total)
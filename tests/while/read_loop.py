@@ -0,0 +1,8 @@
+x = input_int()
+count = 0
+while x != 0 and count < 100 {
+    print(x)
+    x = input_int()
+    count = count + 1
+}
+print(count)
@@ -0,0 +1,8 @@
+n = input_int()
+i = 0
+sum = 0
+while i < n {
+    sum = sum + i
+    i = i + 1
+}
+print(sum)